@@ -0,0 +1,466 @@
+//! Threshold alerts evaluated by the recorder after each sample.
+//!
+//! A rule fires once when its value crosses the threshold and re-arms only
+//! after the value drops below `threshold * (1 - hysteresis)`, so a value
+//! hovering around the threshold does not produce a stream of alerts.
+//!
+//! Whether a rule is active is stored in the database, so a restart or a
+//! config reload while a rule is active neither fires it again nor forgets to
+//! clear it. In case the stored state and the system disagree (a crash between
+//! applying a limit and storing the state, a renamed rule), a rule with
+//! enforcement that is below its threshold when first evaluated, or when its
+//! quota starts a new cycle, produces a `Reset` event that lifts any leftover
+//! limit.
+
+use crate::db;
+use crate::enforce::Enforcement;
+use crate::quota::{Direction, parse_size};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Deserializer};
+use sqlx::SqlitePool;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Default re-arm margin: 5% below the threshold.
+const DEFAULT_HYSTERESIS: f64 = 0.05;
+
+/// How long one action may run before it is abandoned (and a command killed).
+pub const ACTION_TIMEOUT: Duration = Duration::from_secs(30);
+
+fn default_hysteresis() -> f64 {
+    DEFAULT_HYSTERESIS
+}
+
+fn default_direction() -> Direction {
+    Direction::Both
+}
+
+/// Accepts either a number of bytes or a size string such as `"2GiB"`.
+pub(crate) fn de_size<'de, D: Deserializer<'de>>(d: D) -> Result<i64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Size {
+        Bytes(i64),
+        Text(String),
+    }
+    match Size::deserialize(d)? {
+        Size::Bytes(n) => Ok(n),
+        Size::Text(s) => parse_size(&s).map_err(serde::de::Error::custom),
+    }
+}
+
+/// What a rule measures.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Condition {
+    /// Current-cycle usage of a stored quota reaches `percent`.
+    Quota { quota: String, percent: f64 },
+    /// More than `bytes` transferred in the last `window_minutes`.
+    Volume {
+        #[serde(deserialize_with = "de_size")]
+        bytes: i64,
+        window_minutes: u32,
+        #[serde(default = "default_direction")]
+        direction: Direction,
+        interface: Option<String>,
+    },
+    /// Rate above `bytes_per_sec` on every sample for `for_minutes`.
+    Rate {
+        #[serde(deserialize_with = "de_size")]
+        bytes_per_sec: i64,
+        #[serde(default)]
+        for_minutes: u32,
+        #[serde(default = "default_direction")]
+        direction: Direction,
+        interface: Option<String>,
+    },
+}
+
+/// What happens when a rule fires.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Action {
+    /// Runs `sh -c command` with `AMBR_*` environment variables set.
+    Command { command: String },
+    /// Appends a line to a file.
+    Log { path: PathBuf },
+    /// Desktop notification (freedesktop notification spec, via `notify-send`).
+    Notify,
+    /// POSTs the event as JSON.
+    Webhook { url: String },
+    /// Rate-limits or blocks an interface while the rule is active; the
+    /// limit is lifted when the rule clears (at the cycle reset for quotas).
+    Enforce(Enforcement),
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Rule {
+    pub name: String,
+    #[serde(flatten)]
+    pub condition: Condition,
+    #[serde(default = "default_hysteresis")]
+    pub hysteresis: f64,
+    #[serde(default, rename = "action")]
+    pub actions: Vec<Action>,
+}
+
+/// Deltas written by the recorder in one sampling round.
+#[derive(Debug, Clone)]
+pub struct Tick {
+    pub at: NaiveDateTime,
+    pub interval_secs: u64,
+    /// `(interface, rx_delta, tx_delta)`
+    pub deltas: Vec<(String, i64, i64)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertState {
+    Fired,
+    Cleared,
+    /// Below the threshold after a restart or at a new quota cycle; only
+    /// lifts leftover enforcement, other actions don't run.
+    Reset,
+}
+
+impl AlertState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlertState::Fired => "fired",
+            AlertState::Cleared => "cleared",
+            AlertState::Reset => "reset",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AlertEvent {
+    pub rule: String,
+    pub state: AlertState,
+    pub value: f64,
+    pub threshold: f64,
+    pub at: NaiveDateTime,
+    pub message: String,
+}
+
+struct RuleState {
+    rule: Rule,
+    active: bool,
+    /// Rate rules: when the rate first went above the threshold.
+    above_since: Option<NaiveDateTime>,
+    /// Quota rules: start of the cycle seen at the last evaluation.
+    cycle_start: Option<NaiveDateTime>,
+    evaluated: bool,
+}
+
+impl Rule {
+    fn enforces(&self) -> bool {
+        self.actions.iter().any(|a| matches!(a, Action::Enforce(_)))
+    }
+}
+
+/// Evaluates rules against the recorder's samples and the database.
+pub struct AlertEngine {
+    rules: Vec<RuleState>,
+    http: reqwest::Client,
+    saved_state: bool,
+}
+
+impl AlertEngine {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        Self {
+            rules: rules
+                .into_iter()
+                .map(|rule| RuleState {
+                    rule,
+                    active: false,
+                    above_since: None,
+                    cycle_start: None,
+                    evaluated: false,
+                })
+                .collect(),
+            http: reqwest::Client::builder()
+                .timeout(ACTION_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            saved_state: true,
+        }
+    }
+
+    /// Keeps rule state in memory only, for an engine whose rules differ
+    /// from the daemon's (so its state must not stand in for the daemon's).
+    /// Its rules fire again after a restart.
+    pub fn in_memory(self) -> Self {
+        Self {
+            saved_state: false,
+            ..self
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Updates every rule for `tick` and returns the alerts that changed state.
+    pub async fn evaluate(&mut self, pool: &SqlitePool, tick: &Tick) -> Vec<AlertEvent> {
+        let mut events = Vec::new();
+        for st in &mut self.rules {
+            let cycle_start = st.cycle_start;
+            let Some((value, threshold, unit)) = measure(pool, st, tick).await else {
+                continue;
+            };
+            let fresh = !st.evaluated || st.cycle_start != cycle_start;
+            if !st.evaluated && self.saved_state {
+                st.active = db::alert_active(pool, &st.rule.name).await.unwrap_or(false);
+            }
+            st.evaluated = true;
+            let triggered = match &st.rule.condition {
+                Condition::Rate { for_minutes, .. } => st.above_since.is_some_and(|since| {
+                    tick.at - since >= chrono::Duration::minutes(*for_minutes as i64)
+                }),
+                _ => value >= threshold,
+            };
+            let rearm = value < threshold * (1.0 - st.rule.hysteresis);
+
+            let state = if !st.active && triggered {
+                st.active = true;
+                AlertState::Fired
+            } else if st.active && rearm {
+                st.active = false;
+                AlertState::Cleared
+            } else if !st.active && !triggered && fresh && st.rule.enforces() {
+                AlertState::Reset
+            } else {
+                continue;
+            };
+            // Best effort: if this fails the rule may fire again after a restart.
+            if state != AlertState::Reset && self.saved_state {
+                let _ = db::set_alert_active(pool, &st.rule.name, st.active).await;
+            }
+            events.push(AlertEvent {
+                rule: st.rule.name.clone(),
+                state,
+                value,
+                threshold,
+                at: tick.at,
+                message: format!(
+                    "{} {}: {:.1}{} (threshold {:.1}{})",
+                    st.rule.name,
+                    state.as_str(),
+                    value,
+                    unit,
+                    threshold,
+                    unit
+                ),
+            });
+        }
+        events
+    }
+
+    /// Runs the actions of the rule behind `event`. Actions run when a rule
+    /// fires; when it clears or resets, only enforcement is undone. Every
+    /// action is attempted even if an earlier one fails.
+    pub async fn dispatch(
+        &self,
+        event: &AlertEvent,
+    ) -> Vec<Box<dyn std::error::Error + Send + Sync>> {
+        self.job(event).run().await
+    }
+
+    /// The actions for `event`, detached from the engine so they can run on
+    /// another task while the recorder keeps sampling.
+    pub fn job(&self, event: &AlertEvent) -> AlertJob {
+        let actions = self
+            .rules
+            .iter()
+            .find(|s| s.rule.name == event.rule)
+            .map(|s| s.rule.actions.clone())
+            .unwrap_or_default();
+        AlertJob {
+            event: event.clone(),
+            actions,
+            http: self.http.clone(),
+        }
+    }
+}
+
+/// One event's actions, ready to run.
+pub struct AlertJob {
+    pub event: AlertEvent,
+    actions: Vec<Action>,
+    http: reqwest::Client,
+}
+
+impl AlertJob {
+    /// Whether the job applies or lifts a limit, and so must not be dropped.
+    pub fn enforces(&self) -> bool {
+        self.actions.iter().any(|a| matches!(a, Action::Enforce(_)))
+    }
+
+    /// Runs every action in order, each limited to `ACTION_TIMEOUT`, and
+    /// returns the failures.
+    pub async fn run(self) -> Vec<Box<dyn std::error::Error + Send + Sync>> {
+        let mut errors = Vec::new();
+        for action in &self.actions {
+            let res = match (action, self.event.state) {
+                (Action::Enforce(e), AlertState::Cleared) => {
+                    within_timeout(action, e.remove()).await
+                }
+                (Action::Enforce(e), AlertState::Reset) => {
+                    within_timeout(action, e.lift_leftovers()).await
+                }
+                (_, AlertState::Cleared | AlertState::Reset) => Ok(()),
+                (_, AlertState::Fired) => {
+                    within_timeout(action, run_action(&self.http, action, &self.event)).await
+                }
+            };
+            if let Err(e) = res {
+                errors.push(e);
+            }
+        }
+        errors
+    }
+}
+
+impl Action {
+    fn kind(&self) -> &'static str {
+        match self {
+            Action::Command { .. } => "command",
+            Action::Log { .. } => "log",
+            Action::Notify => "notify",
+            Action::Webhook { .. } => "webhook",
+            Action::Enforce(_) => "enforce",
+        }
+    }
+}
+
+async fn within_timeout(
+    action: &Action,
+    fut: impl std::future::Future<Output = Result<(), Box<dyn std::error::Error + Send + Sync>>>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    match tokio::time::timeout(ACTION_TIMEOUT, fut).await {
+        Ok(res) => res.map_err(|e| format!("{} action: {}", action.kind(), e).into()),
+        Err(_) => Err(format!(
+            "{} action timed out after {}s",
+            action.kind(),
+            ACTION_TIMEOUT.as_secs()
+        )
+        .into()),
+    }
+}
+
+/// Current value, threshold and display unit for a rule, or `None` if it
+/// cannot be evaluated (e.g. the quota does not exist).
+async fn measure(
+    pool: &SqlitePool,
+    st: &mut RuleState,
+    tick: &Tick,
+) -> Option<(f64, f64, &'static str)> {
+    match &st.rule.condition {
+        Condition::Quota { quota, percent } => {
+            let q = db::list_quotas(pool)
+                .await
+                .ok()?
+                .into_iter()
+                .find(|q| &q.name == quota)?;
+            let usage = db::quota_usage(pool, &q, tick.at, db::HostFilter::Local)
+                .await
+                .ok()?;
+            st.cycle_start = Some(usage.cycle_start);
+            Some((usage.percent(), *percent, "%"))
+        }
+        Condition::Volume {
+            bytes,
+            window_minutes,
+            direction,
+            interface,
+        } => {
+            let since = tick.at - chrono::Duration::minutes(*window_minutes as i64);
+            let (rx, tx) =
+                db::bytes_since(pool, since, interface.as_deref(), db::HostFilter::Local)
+                    .await
+                    .ok()?;
+            Some((direction.pick(rx, tx) as f64, *bytes as f64, " B"))
+        }
+        Condition::Rate {
+            bytes_per_sec,
+            direction,
+            interface,
+            ..
+        } => {
+            let bytes: i64 = tick
+                .deltas
+                .iter()
+                .filter(|(name, _, _)| interface.as_ref().is_none_or(|i| i == name))
+                .map(|(_, rx, tx)| direction.pick(*rx, *tx))
+                .sum();
+            let rate = bytes as f64 / tick.interval_secs.max(1) as f64;
+            let threshold = *bytes_per_sec as f64;
+            if rate >= threshold {
+                st.above_since.get_or_insert(tick.at);
+            } else {
+                st.above_since = None;
+            }
+            Some((rate, threshold, " B/s"))
+        }
+    }
+}
+
+async fn run_action(
+    http: &reqwest::Client,
+    action: &Action,
+    event: &AlertEvent,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    match action {
+        Action::Command { command } => {
+            let status = tokio::process::Command::new("sh")
+                .arg("-c")
+                .arg(command)
+                .env("AMBR_ALERT", &event.rule)
+                .env("AMBR_STATE", event.state.as_str())
+                .env("AMBR_VALUE", event.value.to_string())
+                .env("AMBR_THRESHOLD", event.threshold.to_string())
+                .env("AMBR_MESSAGE", &event.message)
+                .env("AMBR_TIME", event.at.to_string())
+                .kill_on_drop(true)
+                .status()
+                .await?;
+            if !status.success() {
+                return Err(format!("alert command exited with {}", status).into());
+            }
+        }
+        Action::Log { path } => {
+            let mut f = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?;
+            writeln!(f, "{} {}", event.at, event.message)?;
+        }
+        Action::Notify => {
+            tokio::process::Command::new("notify-send")
+                .arg("--app-name=ambr")
+                .arg(format!("ambr: {}", event.rule))
+                .arg(&event.message)
+                .kill_on_drop(true)
+                .status()
+                .await?;
+        }
+        Action::Webhook { url } => {
+            let body = serde_json::json!({
+                "rule": event.rule,
+                "state": event.state.as_str(),
+                "value": event.value,
+                "threshold": event.threshold,
+                "time": event.at.to_string(),
+                "message": event.message,
+            });
+            http.post(url)
+                .json(&body)
+                .send()
+                .await?
+                .error_for_status()?;
+        }
+        Action::Enforce(e) => e.apply().await?,
+    }
+    Ok(())
+}
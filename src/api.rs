@@ -0,0 +1,329 @@
+//! Local JSON API for dashboards and scripts (`ambr daemon --api`).
+//!
+//! Read-only endpoints over the `db` query functions. Byte counts are exact
+//! integers; timestamps are UTC. The server binds to localhost unless told
+//! otherwise, since the data reveals a lot about a household's habits.
+
+use crate::db::{self, Granularity, HostFilter, UsageFilter};
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::SqlitePool;
+
+/// Page size when `limit` is not given.
+pub const DEFAULT_LIMIT: u32 = 100;
+/// Largest page a client can ask for.
+pub const MAX_LIMIT: u32 = 1000;
+
+/// Serves the API on `listener` until the task is dropped.
+pub async fn serve(listener: tokio::net::TcpListener, pool: SqlitePool) -> std::io::Result<()> {
+    axum::serve(listener, router(pool)).await
+}
+
+pub fn router(pool: SqlitePool) -> Router {
+    Router::new()
+        .route("/usage/{granularity}", get(usage))
+        .route("/live", get(live))
+        .route("/interfaces", get(interfaces))
+        .route("/hosts", get(hosts))
+        .route("/openapi.json", get(openapi))
+        .with_state(pool)
+}
+
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+impl From<sqlx::Error> for ApiError {
+    fn from(e: sqlx::Error) -> Self {
+        ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+    }
+}
+
+fn bad_request(msg: String) -> ApiError {
+    ApiError(StatusCode::BAD_REQUEST, msg)
+}
+
+/// `?host=`: a hostname, a machine id or `local`; all hosts without one.
+async fn host_filter(pool: &SqlitePool, host: Option<&str>) -> Result<HostFilter, ApiError> {
+    match host {
+        Some(name) => Ok(db::resolve_host(pool, name)
+            .await?
+            .ok_or_else(|| bad_request(format!("unknown host '{}'", name)))?),
+        None => Ok(HostFilter::All),
+    }
+}
+
+#[derive(Deserialize)]
+struct HostParams {
+    host: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct UsageParams {
+    interface: Option<String>,
+    host: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    limit: Option<u32>,
+    offset: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct UsagePage {
+    granularity: &'static str,
+    interface: Option<String>,
+    host: Option<String>,
+    from: Option<NaiveDateTime>,
+    to: Option<NaiveDateTime>,
+    limit: u32,
+    offset: u32,
+    /// Offset of the next page, or `null` on the last page.
+    next_offset: Option<u32>,
+    items: Vec<db::UsageRow>,
+}
+
+async fn usage(
+    State(pool): State<SqlitePool>,
+    Path(granularity): Path<String>,
+    Query(params): Query<UsageParams>,
+) -> Result<Json<UsagePage>, ApiError> {
+    let granularity: Granularity = granularity.parse().map_err(bad_request)?;
+    let from = params
+        .from
+        .as_deref()
+        .map(db::parse_time)
+        .transpose()
+        .map_err(bad_request)?;
+    let to = params
+        .to
+        .as_deref()
+        .map(db::parse_time)
+        .transpose()
+        .map_err(bad_request)?;
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT);
+    if limit == 0 || limit > MAX_LIMIT {
+        return Err(bad_request(format!("limit must be 1..={}", MAX_LIMIT)));
+    }
+    let offset = params.offset.unwrap_or(0);
+    let host = host_filter(&pool, params.host.as_deref()).await?;
+    let filter = UsageFilter {
+        interface: params.interface,
+        host,
+    };
+
+    // One extra row tells us whether another page exists.
+    let mut items =
+        db::usage_page(&pool, granularity, &filter, from, to, limit + 1, offset).await?;
+    let next_offset = (items.len() > limit as usize).then_some(offset + limit);
+    items.truncate(limit as usize);
+
+    Ok(Json(UsagePage {
+        granularity: granularity.as_str(),
+        interface: filter.interface,
+        host: params.host,
+        from,
+        to,
+        limit,
+        offset,
+        next_offset,
+        items,
+    }))
+}
+
+async fn live(State(pool): State<SqlitePool>) -> Result<Json<serde_json::Value>, ApiError> {
+    let (rx1, tx1, total1) = db::recent_totals(&pool, 1, HostFilter::Local).await?;
+    let (rx5, tx5, total5) = db::recent_totals(&pool, 5, HostFilter::Local).await?;
+    let by_interface = db::recent_by_interface(&pool, 1, HostFilter::Local).await?;
+    Ok(Json(json!({
+        "last_1min": { "rx_mib": rx1, "tx_mib": tx1, "total_mib": total1 },
+        "last_5min": { "rx_mib": rx5, "tx_mib": tx5, "total_mib": total5 },
+        "by_interface": by_interface,
+    })))
+}
+
+async fn interfaces(
+    State(pool): State<SqlitePool>,
+    Query(params): Query<HostParams>,
+) -> Result<Json<Vec<db::InterfaceSummary>>, ApiError> {
+    let host = host_filter(&pool, params.host.as_deref()).await?;
+    Ok(Json(db::interfaces(&pool, host).await?))
+}
+
+async fn hosts(State(pool): State<SqlitePool>) -> Result<Json<Vec<db::Host>>, ApiError> {
+    Ok(Json(db::hosts(&pool).await?))
+}
+
+async fn openapi() -> Json<serde_json::Value> {
+    Json(openapi_spec())
+}
+
+/// OpenAPI 3.0 description of the endpoints above.
+pub fn openapi_spec() -> serde_json::Value {
+    let bytes = json!({ "type": "integer", "format": "int64" });
+    let mib = json!({ "type": "number", "format": "double" });
+    let time = json!({ "type": "string", "example": "2026-09-01T00:00:00" });
+    let error = json!({
+        "description": "Invalid parameter",
+        "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Error" } } }
+    });
+    let query = |name: &str, description: &str, schema: serde_json::Value| {
+        json!({
+            "name": name,
+            "in": "query",
+            "required": false,
+            "description": description,
+            "schema": schema
+        })
+    };
+    let totals = json!({
+        "type": "object",
+        "properties": { "rx_mib": mib, "tx_mib": mib, "total_mib": mib }
+    });
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "ambr",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Network usage recorded by ambr. Times are UTC; ranges are [from, to)."
+        },
+        "paths": {
+            "/usage/{granularity}": {
+                "get": {
+                    "summary": "Usage per period, newest first",
+                    "parameters": [
+                        {
+                            "name": "granularity", "in": "path", "required": true,
+                            "schema": { "type": "string", "enum": ["minute", "hour", "day", "week", "month"] }
+                        },
+                        query("interface", "Only this interface", json!({ "type": "string" })),
+                        query("host", "Only this pushing host (name or machine id), or 'local' for this machine's own rows", json!({ "type": "string" })),
+                        query("from", "Start (inclusive): YYYY-MM-DD or YYYY-MM-DDTHH:MM:SS", json!({ "type": "string" })),
+                        query("to", "End (exclusive)", json!({ "type": "string" })),
+                        query("limit", "Page size", json!({ "type": "integer", "minimum": 1, "maximum": MAX_LIMIT, "default": DEFAULT_LIMIT })),
+                        query("offset", "Periods to skip", json!({ "type": "integer", "minimum": 0, "default": 0 }))
+                    ],
+                    "responses": {
+                        "200": {
+                            "description": "One page of periods",
+                            "content": { "application/json": { "schema": { "$ref": "#/components/schemas/UsagePage" } } }
+                        },
+                        "400": error
+                    }
+                }
+            },
+            "/live": {
+                "get": {
+                    "summary": "Totals for the last 1 and 5 minutes and per interface for the last minute",
+                    "responses": {
+                        "200": {
+                            "description": "Recent usage in MiB",
+                            "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Live" } } }
+                        }
+                    }
+                }
+            },
+            "/interfaces": {
+                "get": {
+                    "summary": "Recorded interfaces with all-time totals",
+                    "parameters": [
+                        query("host", "Only this pushing host (name or machine id), or 'local' for this machine's own rows", json!({ "type": "string" }))
+                    ],
+                    "responses": {
+                        "200": {
+                            "description": "Interfaces by name",
+                            "content": { "application/json": { "schema": {
+                                "type": "array", "items": { "$ref": "#/components/schemas/Interface" }
+                            } } }
+                        },
+                        "400": error
+                    }
+                }
+            },
+            "/hosts": {
+                "get": {
+                    "summary": "Machines that push to this database",
+                    "responses": {
+                        "200": {
+                            "description": "Hosts by name",
+                            "content": { "application/json": { "schema": {
+                                "type": "array", "items": { "$ref": "#/components/schemas/Host" }
+                            } } }
+                        }
+                    }
+                }
+            }
+        },
+        "components": {
+            "schemas": {
+                "UsageRow": {
+                    "type": "object",
+                    "properties": {
+                        "period": { "type": "string", "example": "2026-09-01" },
+                        "rx_bytes": bytes, "tx_bytes": bytes, "total_bytes": bytes
+                    }
+                },
+                "UsagePage": {
+                    "type": "object",
+                    "properties": {
+                        "granularity": { "type": "string" },
+                        "interface": { "type": "string", "nullable": true },
+                        "host": { "type": "string", "nullable": true },
+                        "from": { "type": "string", "nullable": true },
+                        "to": { "type": "string", "nullable": true },
+                        "limit": { "type": "integer" },
+                        "offset": { "type": "integer" },
+                        "next_offset": { "type": "integer", "nullable": true },
+                        "items": { "type": "array", "items": { "$ref": "#/components/schemas/UsageRow" } }
+                    }
+                },
+                "Live": {
+                    "type": "object",
+                    "properties": {
+                        "last_1min": totals,
+                        "last_5min": totals,
+                        "by_interface": { "type": "array", "items": {
+                            "type": "object",
+                            "properties": {
+                                "interface": { "type": "string" },
+                                "rx_mib": mib, "tx_mib": mib, "total_mib": mib
+                            }
+                        } }
+                    }
+                },
+                "Interface": {
+                    "type": "object",
+                    "properties": {
+                        "interface": { "type": "string" },
+                        "first_seen": time, "last_seen": time,
+                        "rx_bytes": bytes, "tx_bytes": bytes
+                    }
+                },
+                "Host": {
+                    "type": "object",
+                    "properties": {
+                        "id": { "type": "integer" },
+                        "hostname": { "type": "string" },
+                        "machine_id": { "type": "string" },
+                        "labels": { "type": "object", "additionalProperties": { "type": "string" } },
+                        "last_seq": { "type": "integer", "format": "int64" }
+                    }
+                },
+                "Error": {
+                    "type": "object",
+                    "properties": { "error": { "type": "string" } }
+                }
+            }
+        }
+    })
+}
@@ -0,0 +1,182 @@
+//! Period-over-period comparisons: this week against last week, or this
+//! month against the same month a year ago, per interface.
+//!
+//! The current period only runs up to `now`, so the previous one is cut at
+//! the same point: Monday to Thursday morning is compared with Monday to
+//! Thursday morning last week, not with all of last week.
+
+use crate::db::{self, Granularity, HostFilter};
+use chrono::{Duration, Months, NaiveDateTime};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::collections::BTreeSet;
+
+/// Which period the current one is compared with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Baseline {
+    /// This week against last week.
+    LastWeek,
+    /// This month against the same month last year.
+    LastYear,
+}
+
+impl Baseline {
+    pub const ALL: [Baseline; 2] = [Baseline::LastWeek, Baseline::LastYear];
+
+    /// Names of the current and the previous period.
+    pub fn labels(&self) -> (&'static str, &'static str) {
+        match self {
+            Baseline::LastWeek => ("This week", "Last week"),
+            Baseline::LastYear => ("This month", "Same month last year"),
+        }
+    }
+
+    fn granularity(&self) -> Granularity {
+        match self {
+            Baseline::LastWeek => Granularity::Week,
+            Baseline::LastYear => Granularity::Month,
+        }
+    }
+
+    fn back(&self, t: NaiveDateTime) -> NaiveDateTime {
+        match self {
+            Baseline::LastWeek => t - Duration::weeks(1),
+            Baseline::LastYear => t - Months::new(12),
+        }
+    }
+
+    /// The current period up to `now`, and the previous period cut at the
+    /// same offset.
+    pub fn periods(&self, now: NaiveDateTime) -> (Period, Period) {
+        let granularity = self.granularity();
+        let from = granularity.period_start(now);
+        let period = |from: NaiveDateTime, to: NaiveDateTime| Period {
+            label: granularity.label(from),
+            from,
+            to,
+        };
+        (period(from, now), period(self.back(from), self.back(now)))
+    }
+
+    /// Compares the current period with its baseline as of `now`.
+    pub async fn compare(
+        &self,
+        pool: &SqlitePool,
+        now: NaiveDateTime,
+        host: HostFilter,
+    ) -> Result<Comparison, sqlx::Error> {
+        let (current, previous) = self.periods(now);
+        compare(pool, current, previous, host).await
+    }
+}
+
+/// One side of a comparison: `[from, to)`, labelled like the history tabs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Period {
+    pub label: String,
+    pub from: NaiveDateTime,
+    pub to: NaiveDateTime,
+}
+
+/// A byte count in the current and the previous period.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Change {
+    pub current: i64,
+    pub previous: i64,
+}
+
+impl Change {
+    pub fn delta(&self) -> i64 {
+        self.current - self.previous
+    }
+
+    /// Change relative to the previous period, in percent; `None` when the
+    /// previous period saw no traffic.
+    pub fn percent(&self) -> Option<f64> {
+        (self.previous != 0).then(|| self.delta() as f64 * 100.0 / self.previous as f64)
+    }
+
+    fn add(self, other: Change) -> Change {
+        Change {
+            current: self.current + other.current,
+            previous: self.previous + other.previous,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InterfaceChange {
+    pub interface: String,
+    pub rx: Change,
+    pub tx: Change,
+    pub total: Change,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Comparison {
+    pub current: Period,
+    pub previous: Period,
+    /// By name; every interface with traffic in either period.
+    pub interfaces: Vec<InterfaceChange>,
+}
+
+impl Comparison {
+    /// All interfaces together, under the name "all".
+    pub fn total(&self) -> InterfaceChange {
+        self.interfaces.iter().fold(
+            InterfaceChange {
+                interface: "all".to_string(),
+                rx: Change::default(),
+                tx: Change::default(),
+                total: Change::default(),
+            },
+            |sum, i| InterfaceChange {
+                rx: sum.rx.add(i.rx),
+                tx: sum.tx.add(i.tx),
+                total: sum.total.add(i.total),
+                ..sum
+            },
+        )
+    }
+}
+
+/// Usage per interface in `current` next to `previous`.
+pub async fn compare(
+    pool: &SqlitePool,
+    current: Period,
+    previous: Period,
+    host: HostFilter,
+) -> Result<Comparison, sqlx::Error> {
+    let now = db::bytes_by_interface(pool, current.from, Some(current.to), host).await?;
+    let before = db::bytes_by_interface(pool, previous.from, Some(previous.to), host).await?;
+
+    let names: BTreeSet<&String> = now.keys().chain(before.keys()).collect();
+    let interfaces = names
+        .into_iter()
+        .map(|name| {
+            let (rx, tx) = now.get(name).copied().unwrap_or_default();
+            let (prev_rx, prev_tx) = before.get(name).copied().unwrap_or_default();
+            InterfaceChange {
+                interface: name.clone(),
+                rx: Change {
+                    current: rx,
+                    previous: prev_rx,
+                },
+                tx: Change {
+                    current: tx,
+                    previous: prev_tx,
+                },
+                total: Change {
+                    current: rx + tx,
+                    previous: prev_rx + prev_tx,
+                },
+            }
+        })
+        .collect();
+
+    Ok(Comparison {
+        current,
+        previous,
+        interfaces,
+    })
+}
@@ -0,0 +1,49 @@
+//! Optional TOML configuration (`config.toml` in the user config directory).
+//!
+//! ```toml
+//! [[alert]]
+//! name = "mobile-80"
+//! kind = "quota"
+//! quota = "mobile"
+//! percent = 80
+//!
+//!   [[alert.action]]
+//!   type = "notify"
+//! ```
+
+use crate::{alert, mqtt, push, tariff};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default, rename = "alert")]
+    pub alerts: Vec<alert::Rule>,
+    #[serde(default, rename = "tariff")]
+    pub tariffs: Vec<tariff::Tariff>,
+    /// Forward samples to an `ambr server`.
+    pub push: Option<push::PushConfig>,
+    /// Publish rates and totals to an MQTT broker.
+    pub mqtt: Option<mqtt::MqttConfig>,
+}
+
+impl Config {
+    /// Reads `path`; a missing file yields the default (empty) configuration.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Ok(Self::parse(&text)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(format!("{}: {}", path.display(), e).into()),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(text)
+    }
+}
+
+/// `~/.config/ambr/config.toml` on Linux, the platform equivalent elsewhere.
+pub fn default_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|p| p.join("ambr").join("config.toml"))
+}
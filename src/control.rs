@@ -0,0 +1,331 @@
+//! Control socket for a running daemon.
+//!
+//! Line-delimited JSON over a Unix socket, by default
+//! `$XDG_RUNTIME_DIR/ambr.sock`. Each request is one object with a `cmd`
+//! field and gets one response object with a `type` field:
+//!
+//! ```text
+//! → {"cmd":"live"}
+//! ← {"type":"rates","at":"2026-10-18T09:00:10","interval_secs":10,"paused":false,"interfaces":[...]}
+//! → {"cmd":"rollup","older_than_days":30}
+//! ← {"type":"rolled_up","samples":8640,"rows":24,"before":"2026-09-18T09:00:00"}
+//! ```
+//!
+//! `subscribe` turns the connection into a stream: one `rates` line now and
+//! one after every sampling round until the client disconnects.
+//!
+//! `rollup` loses accuracy: merged rows are stamped at the start of their
+//! hour, so quota free windows and tariff periods count a whole hour by when
+//! it starts instead of by each sample's time. So it needs `older_than_days`
+//! of at least 1 and never merges samples in a quota's current cycle; `before`
+//! in the response is the cutoff actually used.
+
+use crate::alert::AlertEngine;
+use crate::config::Config;
+use crate::db;
+use crate::recorder::{LiveRates, RecorderHandle};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{UnixListener, UnixStream};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// Age of the samples `rollup` merges when the request does not say.
+pub const DEFAULT_ROLLUP_DAYS: u32 = 30;
+
+fn default_rollup_days() -> u32 {
+    DEFAULT_ROLLUP_DAYS
+}
+
+/// `$XDG_RUNTIME_DIR/ambr.sock`, if the runtime directory is known.
+pub fn default_socket_path() -> Option<PathBuf> {
+    dirs::runtime_dir().map(|d| d.join("ambr.sock"))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum Request {
+    /// Rates from the last sampling round.
+    Live,
+    /// Rates now and after every round.
+    Subscribe,
+    /// Merge raw samples older than `older_than_days` into hourly rows.
+    Rollup {
+        #[serde(default = "default_rollup_days")]
+        older_than_days: u32,
+    },
+    /// Re-read `config.toml` and replace the alert rules.
+    Reload,
+    /// Stop storing samples (counters are still read, so nothing is
+    /// attributed to the paused period on resume).
+    Pause,
+    Resume,
+    Status,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Response {
+    Rates(LiveRates),
+    RolledUp {
+        samples: u64,
+        rows: u64,
+        /// Samples before this time were merged.
+        before: NaiveDateTime,
+    },
+    Reloaded {
+        rules: usize,
+    },
+    Status {
+        paused: bool,
+        rounds: u64,
+        insert_errors: u64,
+        alert_errors: u64,
+        last_sample: Option<NaiveDateTime>,
+    },
+    Ok,
+    Error {
+        message: String,
+    },
+}
+
+/// Serves the control protocol for one recorder.
+pub struct ControlServer {
+    recorder: RecorderHandle,
+    pool: SqlitePool,
+    config_path: Option<PathBuf>,
+}
+
+impl ControlServer {
+    pub fn new(recorder: RecorderHandle, pool: SqlitePool) -> Self {
+        Self {
+            recorder,
+            pool,
+            config_path: None,
+        }
+    }
+
+    /// File `reload` reads; without one, `reload` is an error.
+    pub fn with_config_path(mut self, path: PathBuf) -> Self {
+        self.config_path = Some(path);
+        self
+    }
+
+    /// Binds `path`, replacing a stale socket left by a previous run. Fails if
+    /// another daemon is still answering on it.
+    pub async fn bind(path: &Path) -> Result<UnixListener, Error> {
+        if path.exists() {
+            if UnixStream::connect(path).await.is_ok() {
+                return Err(format!("{} is in use by another ambr daemon", path.display()).into());
+            }
+            std::fs::remove_file(path)?;
+        }
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        Ok(UnixListener::bind(path)?)
+    }
+
+    /// Accepts connections until the task is dropped.
+    pub async fn serve(self, listener: UnixListener) -> Result<(), Error> {
+        let server = std::sync::Arc::new(self);
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let server = server.clone();
+            tokio::spawn(async move {
+                let _ = server.connection(stream).await;
+            });
+        }
+    }
+
+    async fn connection(&self, stream: UnixStream) -> Result<(), Error> {
+        let (read, mut write) = stream.into_split();
+        let mut lines = BufReader::new(read).lines();
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let request = match serde_json::from_str::<Request>(&line) {
+                Ok(r) => r,
+                Err(e) => {
+                    send(
+                        &mut write,
+                        &Response::Error {
+                            message: format!("invalid request: {}", e),
+                        },
+                    )
+                    .await?;
+                    continue;
+                }
+            };
+            if request == Request::Subscribe {
+                return self.stream_rates(write).await;
+            }
+            let response = self.handle(request).await;
+            send(&mut write, &response).await?;
+        }
+        Ok(())
+    }
+
+    async fn stream_rates(&self, mut write: OwnedWriteHalf) -> Result<(), Error> {
+        let mut rounds = self.recorder.rounds();
+        loop {
+            let rates = LiveRates::from_state(&self.recorder.snapshot());
+            send(&mut write, &Response::Rates(rates)).await?;
+            if rounds.changed().await.is_err() {
+                return Ok(());
+            }
+        }
+    }
+
+    async fn handle(&self, request: Request) -> Response {
+        match request {
+            Request::Live | Request::Subscribe => {
+                Response::Rates(LiveRates::from_state(&self.recorder.snapshot()))
+            }
+            Request::Rollup { older_than_days } => match self.rollup(older_than_days).await {
+                Ok(response) => response,
+                Err(e) => Response::Error {
+                    message: e.to_string(),
+                },
+            },
+            Request::Reload => {
+                let Some(path) = &self.config_path else {
+                    return Response::Error {
+                        message: "no config file to reload".to_string(),
+                    };
+                };
+                match Config::load(path) {
+                    Ok(config) => {
+                        let rules = config.alerts.len();
+                        self.recorder
+                            .replace_alerts(AlertEngine::new(config.alerts))
+                            .await;
+                        Response::Reloaded { rules }
+                    }
+                    Err(e) => Response::Error {
+                        message: e.to_string(),
+                    },
+                }
+            }
+            Request::Pause => {
+                self.recorder.set_paused(true);
+                Response::Ok
+            }
+            Request::Resume => {
+                self.recorder.set_paused(false);
+                Response::Ok
+            }
+            Request::Status => {
+                let s = self.recorder.snapshot();
+                Response::Status {
+                    paused: s.paused,
+                    rounds: s.rounds,
+                    insert_errors: s.insert_errors,
+                    alert_errors: s.alert_errors,
+                    last_sample: s.last_sample,
+                }
+            }
+        }
+    }
+
+    /// Merges samples older than `older_than_days`, stopping at the start of
+    /// the earliest current quota cycle.
+    async fn rollup(&self, older_than_days: u32) -> Result<Response, Error> {
+        if older_than_days == 0 {
+            return Err("older_than_days must be at least 1".into());
+        }
+        let now = chrono::Utc::now().naive_utc();
+        let mut before = now - chrono::Duration::days(older_than_days as i64);
+        for quota in db::list_quotas(&self.pool).await? {
+            let (start, end) = quota.cycle_at(now);
+            if end > now {
+                before = before.min(start);
+            }
+        }
+        let (samples, rows) = db::rollup_hourly(&self.pool, before).await?;
+        Ok(Response::RolledUp {
+            samples,
+            rows,
+            before,
+        })
+    }
+}
+
+async fn send(write: &mut OwnedWriteHalf, response: &Response) -> Result<(), Error> {
+    let mut line = serde_json::to_vec(response)?;
+    line.push(b'\n');
+    write.write_all(&line).await?;
+    Ok(())
+}
+
+/// Connection to a daemon's control socket.
+pub struct Client {
+    lines: tokio::io::Lines<BufReader<OwnedReadHalf>>,
+    write: OwnedWriteHalf,
+}
+
+impl Client {
+    pub async fn connect(path: &Path) -> std::io::Result<Self> {
+        let (read, write) = UnixStream::connect(path).await?.into_split();
+        Ok(Self {
+            lines: BufReader::new(read).lines(),
+            write,
+        })
+    }
+
+    /// Sends one request and waits for its response. Error responses are
+    /// returned as `Err`.
+    pub async fn request(&mut self, request: &Request) -> Result<Response, Error> {
+        let mut line = serde_json::to_vec(request)?;
+        line.push(b'\n');
+        self.write.write_all(&line).await?;
+        match self.read().await? {
+            Some(Response::Error { message }) => Err(message.into()),
+            Some(response) => Ok(response),
+            None => Err("daemon closed the connection".into()),
+        }
+    }
+
+    async fn read(&mut self) -> Result<Option<Response>, Error> {
+        match self.lines.next_line().await? {
+            Some(line) => Ok(Some(serde_json::from_str(&line)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn live(&mut self) -> Result<LiveRates, Error> {
+        match self.request(&Request::Live).await? {
+            Response::Rates(rates) => Ok(rates),
+            other => Err(format!("unexpected response {:?}", other).into()),
+        }
+    }
+
+    /// Turns the connection into a stream of rates, one per sampling round.
+    pub async fn subscribe(mut self) -> Result<Subscription, Error> {
+        let mut line = serde_json::to_vec(&Request::Subscribe)?;
+        line.push(b'\n');
+        self.write.write_all(&line).await?;
+        Ok(Subscription { client: self })
+    }
+}
+
+/// Live rates streamed by the daemon.
+pub struct Subscription {
+    client: Client,
+}
+
+impl Subscription {
+    /// Next update; `None` once the daemon goes away.
+    pub async fn next(&mut self) -> Result<Option<LiveRates>, Error> {
+        match self.client.read().await? {
+            Some(Response::Rates(rates)) => Ok(Some(rates)),
+            Some(other) => Err(format!("unexpected response {:?}", other).into()),
+            None => Ok(None),
+        }
+    }
+}
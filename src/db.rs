@@ -1,4 +1,11 @@
-use sqlx::{FromRow, sqlite::SqlitePool};
+use crate::quota::{Cycle, Direction, Quota, QuotaUsage};
+use crate::tariff::TimeWindow;
+use chrono::{Datelike, Duration, Months, NaiveDate, NaiveDateTime, Timelike};
+use futures_util::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, QueryBuilder, Sqlite, sqlite::SqlitePool};
+use std::collections::BTreeMap;
+use std::str::FromStr;
 
 pub async fn init_db(pool: &SqlitePool) -> Result<(), sqlx::Error> {
     sqlx::query(
@@ -12,6 +19,108 @@ pub async fn init_db(pool: &SqlitePool) -> Result<(), sqlx::Error> {
     )
     .execute(pool)
     .await?;
+    // Machines that push to this database (`ambr server`). Rows recorded
+    // locally have no host.
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS hosts (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            hostname TEXT NOT NULL,
+            machine_id TEXT NOT NULL UNIQUE,
+            labels TEXT NOT NULL DEFAULT '{}',
+            last_seq INTEGER NOT NULL DEFAULT 0
+        )",
+    )
+    .execute(pool)
+    .await?;
+    add_column_if_missing(pool, "hosts", "labels", "TEXT NOT NULL DEFAULT '{}'").await?;
+    add_column_if_missing(pool, "hosts", "database_id", "TEXT").await?;
+    add_column_if_missing(pool, "traffic", "host_id", "INTEGER REFERENCES hosts (id)").await?;
+    // Facts about this database itself, such as its `database_id`.
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS meta (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        )",
+    )
+    .execute(pool)
+    .await?;
+    // Samples waiting to be pushed to a collector.
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS outbox (
+            seq INTEGER PRIMARY KEY AUTOINCREMENT,
+            interface TEXT NOT NULL,
+            rx_bytes INTEGER NOT NULL,
+            tx_bytes INTEGER NOT NULL,
+            timestamp DATETIME NOT NULL
+        )",
+    )
+    .execute(pool)
+    .await?;
+    // Per-interface range lookups (imports, filtered queries).
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS traffic_interface_timestamp ON traffic (interface, timestamp)",
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS quotas (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE,
+            limit_bytes INTEGER NOT NULL,
+            interfaces TEXT NOT NULL DEFAULT '',
+            direction TEXT NOT NULL DEFAULT 'both',
+            cycle_start_day INTEGER,
+            period_start DATETIME,
+            period_end DATETIME,
+            free_windows TEXT NOT NULL DEFAULT ''
+        )",
+    )
+    .execute(pool)
+    .await?;
+    add_column_if_missing(pool, "quotas", "free_windows", "TEXT NOT NULL DEFAULT ''").await?;
+    // Which alert rules are active, so a restart doesn't fire them again.
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS alert_state (
+            rule TEXT PRIMARY KEY,
+            active INTEGER NOT NULL,
+            changed_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS ptr_cache (
+            ip TEXT PRIMARY KEY,
+            name TEXT,
+            resolved_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Adds a column that older databases were created without.
+async fn add_column_if_missing(
+    pool: &SqlitePool,
+    table: &str,
+    column: &str,
+    decl: &str,
+) -> Result<(), sqlx::Error> {
+    let (count,): (i64,) =
+        sqlx::query_as("SELECT COUNT(*) FROM pragma_table_info(?) WHERE name = ?")
+            .bind(table)
+            .bind(column)
+            .fetch_one(pool)
+            .await?;
+    if count == 0 {
+        sqlx::query(&format!(
+            "ALTER TABLE {} ADD COLUMN {} {}",
+            table, column, decl
+        ))
+        .execute(pool)
+        .await?;
+    }
     Ok(())
 }
 
@@ -31,6 +140,206 @@ pub async fn save_delta(
     Ok(())
 }
 
+/// Formats a timestamp the way SQLite's `CURRENT_TIMESTAMP` stores it.
+fn sql_ts(t: NaiveDateTime) -> String {
+    t.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// Like `save_delta`, but with an explicit (UTC) sample time.
+pub async fn save_delta_at(
+    pool: &SqlitePool,
+    interface: &str,
+    rx_delta: &i64,
+    tx_delta: &i64,
+    timestamp: NaiveDateTime,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO traffic (interface, rx_bytes, tx_bytes, timestamp) VALUES (?, ?, ?, ?)",
+    )
+    .bind(interface)
+    .bind(rx_delta)
+    .bind(tx_delta)
+    .bind(sql_ts(timestamp))
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Like `save_delta_at`, and also queues the sample in the outbox for
+/// pushing to a collector.
+pub async fn save_delta_queued(
+    pool: &SqlitePool,
+    interface: &str,
+    rx_delta: &i64,
+    tx_delta: &i64,
+    timestamp: NaiveDateTime,
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    for table in ["traffic", "outbox"] {
+        sqlx::query(&format!(
+            "INSERT INTO {} (interface, rx_bytes, tx_bytes, timestamp) VALUES (?, ?, ?, ?)",
+            table
+        ))
+        .bind(interface)
+        .bind(rx_delta)
+        .bind(tx_delta)
+        .bind(sql_ts(timestamp))
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await
+}
+
+/// Bucket size of the history views.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Granularity {
+    Minute,
+    Hour,
+    Day,
+    Week,
+    Month,
+}
+
+impl Granularity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Granularity::Minute => "minute",
+            Granularity::Hour => "hour",
+            Granularity::Day => "day",
+            Granularity::Week => "week",
+            Granularity::Month => "month",
+        }
+    }
+
+    /// strftime pattern of the period labels; SQLite and chrono agree on these.
+    pub fn format(&self) -> &'static str {
+        match self {
+            Granularity::Minute => "%Y-%m-%d %H:%M",
+            Granularity::Hour => "%Y-%m-%d %H:00",
+            Granularity::Day => "%Y-%m-%d",
+            Granularity::Week => "%Y-W%W",
+            Granularity::Month => "%Y-%m",
+        }
+    }
+
+    pub fn label(&self, t: NaiveDateTime) -> String {
+        t.format(self.format()).to_string()
+    }
+
+    /// Start of the period containing `t` (weeks start on Monday, as with `%W`).
+    pub fn period_start(&self, t: NaiveDateTime) -> NaiveDateTime {
+        let midnight = t.date().and_time(chrono::NaiveTime::MIN);
+        match self {
+            Granularity::Minute => {
+                midnight + Duration::minutes((t.hour() * 60 + t.minute()) as i64)
+            }
+            Granularity::Hour => midnight + Duration::hours(t.hour() as i64),
+            Granularity::Day => midnight,
+            Granularity::Week => {
+                midnight - Duration::days(t.weekday().num_days_from_monday() as i64)
+            }
+            Granularity::Month => midnight - Duration::days(t.day0() as i64),
+        }
+    }
+
+    /// Start of the period `n` periods before the one containing `t`.
+    pub fn periods_back(&self, t: NaiveDateTime, n: u32) -> NaiveDateTime {
+        let start = self.period_start(t);
+        match self {
+            Granularity::Minute => start - Duration::minutes(n as i64),
+            Granularity::Hour => start - Duration::hours(n as i64),
+            Granularity::Day => start - Duration::days(n as i64),
+            Granularity::Week => start - Duration::weeks(n as i64),
+            Granularity::Month => start - Months::new(n),
+        }
+    }
+
+    /// Start of the period after the one starting at `start`.
+    pub fn next(&self, start: NaiveDateTime) -> NaiveDateTime {
+        match self {
+            Granularity::Minute => start + Duration::minutes(1),
+            Granularity::Hour => start + Duration::hours(1),
+            Granularity::Day => start + Duration::days(1),
+            Granularity::Week => start + Duration::weeks(1),
+            Granularity::Month => start + Months::new(1),
+        }
+    }
+
+    /// The granularity one step down, used to break a period into its parts;
+    /// weeks break into days. `None` for minutes.
+    pub fn finer(&self) -> Option<Granularity> {
+        match self {
+            Granularity::Minute => None,
+            Granularity::Hour => Some(Granularity::Minute),
+            Granularity::Day => Some(Granularity::Hour),
+            Granularity::Week | Granularity::Month => Some(Granularity::Day),
+        }
+    }
+
+    /// Start of the period labelled `label` (the inverse of [`Self::label`]).
+    pub fn parse_label(&self, label: &str) -> Option<NaiveDateTime> {
+        let midnight = |d: NaiveDate| d.and_time(chrono::NaiveTime::MIN);
+        match self {
+            // The hour label's literal ":00" parses as the minutes.
+            Granularity::Minute | Granularity::Hour => {
+                NaiveDateTime::parse_from_str(label, "%Y-%m-%d %H:%M").ok()
+            }
+            Granularity::Day => NaiveDate::parse_from_str(label, "%Y-%m-%d")
+                .ok()
+                .map(midnight),
+            // Week 01 starts on the year's first Monday; week 00 is the days
+            // before it, from the last Monday of the previous year.
+            Granularity::Week => {
+                let (year, week) = label.split_once("-W")?;
+                let jan1 = NaiveDate::from_ymd_opt(year.parse().ok()?, 1, 1)?;
+                let to_monday = (7 - jan1.weekday().num_days_from_monday() as i64) % 7;
+                let week: i64 = week.parse().ok()?;
+                Some(midnight(
+                    jan1 + Duration::days(to_monday) + Duration::weeks(week - 1),
+                ))
+            }
+            Granularity::Month => NaiveDate::parse_from_str(&format!("{label}-01"), "%Y-%m-%d")
+                .ok()
+                .map(midnight),
+        }
+        .map(|t| self.period_start(t))
+    }
+
+    /// The `[from, to)` range whose traffic makes up the row labelled
+    /// `label`. `%W` restarts on 1 January, so weeks are cut at the year
+    /// boundary: 2026-W00 only holds the days of 2026 before its first
+    /// Monday, and the last week of 2025 ends on 31 December.
+    pub fn label_range(&self, label: &str) -> Option<(NaiveDateTime, NaiveDateTime)> {
+        let from = self.parse_label(label)?;
+        let to = self.next(from);
+        if *self != Granularity::Week {
+            return Some((from, to));
+        }
+        let year: i32 = label.split_once("-W")?.0.parse().ok()?;
+        let jan1 = |y| NaiveDate::from_ymd_opt(y, 1, 1).map(|d| d.and_time(chrono::NaiveTime::MIN));
+        Some((from.max(jan1(year)?), to.min(jan1(year + 1)?)))
+    }
+}
+
+impl FromStr for Granularity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "minute" | "minutely" => Ok(Granularity::Minute),
+            "hour" | "hourly" => Ok(Granularity::Hour),
+            "day" | "daily" => Ok(Granularity::Day),
+            "week" | "weekly" => Ok(Granularity::Week),
+            "month" | "monthly" => Ok(Granularity::Month),
+            _ => Err(format!(
+                "unknown granularity '{}' (expected minute, hour, day, week or month)",
+                s
+            )),
+        }
+    }
+}
+
 #[derive(FromRow)]
 struct AggRow {
     period: String,
@@ -38,7 +347,7 @@ struct AggRow {
     tx: i64,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct PeriodRow {
     pub period: String,
     pub rx_mib: f64,
@@ -59,20 +368,25 @@ fn agg_to_period(r: AggRow) -> PeriodRow {
     }
 }
 
-pub async fn usage_by_hour(pool: &SqlitePool, limit: u32) -> Result<Vec<PeriodRow>, sqlx::Error> {
-    let rows = sqlx::query_as(
+pub async fn usage_by_hour(
+    pool: &SqlitePool,
+    limit: u32,
+    host: HostFilter,
+) -> Result<Vec<PeriodRow>, sqlx::Error> {
+    let rows = sqlx::query_as(&format!(
         r#"
         SELECT
             strftime('%Y-%m-%d %H:00', timestamp) AS period,
             SUM(rx_bytes) AS rx,
             SUM(tx_bytes) AS tx
         FROM traffic
-        WHERE timestamp >= datetime('now', '-7 days')
+        WHERE timestamp >= datetime('now', '-7 days') AND {}
         GROUP BY period
         ORDER BY period DESC
         LIMIT ?
         "#,
-    )
+        host.sql()
+    ))
     .bind(limit as i64)
     .fetch_all(pool)
     .await?;
@@ -82,19 +396,25 @@ pub async fn usage_by_hour(pool: &SqlitePool, limit: u32) -> Result<Vec<PeriodRo
     Ok(out)
 }
 
-pub async fn usage_by_day(pool: &SqlitePool, limit: u32) -> Result<Vec<PeriodRow>, sqlx::Error> {
-    let rows = sqlx::query_as(
+pub async fn usage_by_day(
+    pool: &SqlitePool,
+    limit: u32,
+    host: HostFilter,
+) -> Result<Vec<PeriodRow>, sqlx::Error> {
+    let rows = sqlx::query_as(&format!(
         r#"
         SELECT
             strftime('%Y-%m-%d', timestamp) AS period,
             SUM(rx_bytes) AS rx,
             SUM(tx_bytes) AS tx
         FROM traffic
+        WHERE {}
         GROUP BY period
         ORDER BY period DESC
         LIMIT ?
         "#,
-    )
+        host.sql()
+    ))
     .bind(limit as i64)
     .fetch_all(pool)
     .await?;
@@ -103,19 +423,25 @@ pub async fn usage_by_day(pool: &SqlitePool, limit: u32) -> Result<Vec<PeriodRow
     Ok(out)
 }
 
-pub async fn usage_by_week(pool: &SqlitePool, limit: u32) -> Result<Vec<PeriodRow>, sqlx::Error> {
-    let rows = sqlx::query_as(
+pub async fn usage_by_week(
+    pool: &SqlitePool,
+    limit: u32,
+    host: HostFilter,
+) -> Result<Vec<PeriodRow>, sqlx::Error> {
+    let rows = sqlx::query_as(&format!(
         r#"
         SELECT
             strftime('%Y-W%W', timestamp) AS period,
             SUM(rx_bytes) AS rx,
             SUM(tx_bytes) AS tx
         FROM traffic
+        WHERE {}
         GROUP BY period
         ORDER by period DESC
         LIMIT ?
         "#,
-    )
+        host.sql()
+    ))
     .bind(limit as i64)
     .fetch_all(pool)
     .await?;
@@ -124,19 +450,25 @@ pub async fn usage_by_week(pool: &SqlitePool, limit: u32) -> Result<Vec<PeriodRo
     Ok(out)
 }
 
-pub async fn usage_by_month(pool: &SqlitePool, limit: u32) -> Result<Vec<PeriodRow>, sqlx::Error> {
-    let rows = sqlx::query_as(
+pub async fn usage_by_month(
+    pool: &SqlitePool,
+    limit: u32,
+    host: HostFilter,
+) -> Result<Vec<PeriodRow>, sqlx::Error> {
+    let rows = sqlx::query_as(&format!(
         r#"
         SELECT
             strftime('%Y-%m', timestamp) AS period,
             SUM(rx_bytes) AS rx,
             SUM(tx_bytes) AS tx
         FROM traffic
+        WHERE {}
         GROUP BY period
         ORDER BY period DESC
         LIMIT ?
         "#,
-    )
+        host.sql()
+    ))
     .bind(limit as i64)
     .fetch_all(pool)
     .await?;
@@ -146,20 +478,674 @@ pub async fn usage_by_month(pool: &SqlitePool, limit: u32) -> Result<Vec<PeriodR
     Ok(out)
 }
 
+/// Usage per `granularity` period recorded in `[from, to)`, oldest first.
+/// Unlike `usage_by_*`, which return the latest periods, the range is explicit
+/// and every period in it with traffic is returned.
+pub async fn usage_between(
+    pool: &SqlitePool,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    granularity: Granularity,
+    filter: &UsageFilter,
+) -> Result<Vec<PeriodRow>, sqlx::Error> {
+    let mut qb = QueryBuilder::<Sqlite>::new(format!(
+        "SELECT strftime('{}', timestamp) AS period, SUM(rx_bytes) AS rx, SUM(tx_bytes) AS tx \
+         FROM traffic WHERE timestamp >= ",
+        granularity.format()
+    ));
+    qb.push_bind(sql_ts(from));
+    qb.push(" AND timestamp < ");
+    qb.push_bind(sql_ts(to));
+    push_interface_filter(&mut qb, filter.interface.as_slice());
+    qb.push(format!(
+        " AND {} GROUP BY period ORDER BY period",
+        filter.host.sql()
+    ));
+
+    let rows: Vec<AggRow> = qb.build_query_as().fetch_all(pool).await?;
+    Ok(rows.into_iter().map(agg_to_period).collect())
+}
+
+/// One interface's usage in one period.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InterfacePeriodRow {
+    pub period: String,
+    pub interface: String,
+    pub rx_mib: f64,
+    pub tx_mib: f64,
+    pub total_mib: f64,
+}
+
+/// Usage per `granularity` period and interface recorded in `[from, to)`,
+/// oldest first. Periods carry the same labels as `usage_by_*`, so a row can
+/// be drilled into with [`Granularity::label_range`].
+pub async fn usage_by_interface_between(
+    pool: &SqlitePool,
+    granularity: Granularity,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    host: HostFilter,
+) -> Result<Vec<InterfacePeriodRow>, sqlx::Error> {
+    let rows: Vec<(String, String, i64, i64)> = sqlx::query_as(&format!(
+        r#"
+        SELECT
+            strftime('{}', timestamp) AS period,
+            interface,
+            SUM(rx_bytes) AS rx,
+            SUM(tx_bytes) AS tx
+        FROM traffic
+        WHERE timestamp >= ? AND timestamp < ? AND {}
+        GROUP BY period, interface
+        ORDER BY period, interface
+        "#,
+        granularity.format(),
+        host.sql()
+    ))
+    .bind(sql_ts(from))
+    .bind(sql_ts(to))
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(period, interface, rx, tx)| {
+            let PeriodRow {
+                period,
+                rx_mib,
+                tx_mib,
+                total_mib,
+            } = agg_to_period(AggRow { period, rx, tx });
+            InterfacePeriodRow {
+                period,
+                interface,
+                rx_mib,
+                tx_mib,
+                total_mib,
+            }
+        })
+        .collect())
+}
+
+/// Raw `(rx, tx)` bytes recorded in `[from, to)` by weekday (Monday = 0) and
+/// hour of day, as `[weekday][hour]`, plus the time of the first sample in
+/// the range (`None` when there is none).
+pub async fn bytes_by_weekday_hour(
+    pool: &SqlitePool,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    host: HostFilter,
+) -> Result<([[(i64, i64); 24]; 7], Option<NaiveDateTime>), sqlx::Error> {
+    // strftime('%w') counts from Sunday
+    let rows: Vec<(i64, i64, i64, i64, String)> = sqlx::query_as(&format!(
+        r#"
+        SELECT
+            (CAST(strftime('%w', timestamp) AS INTEGER) + 6) % 7 AS weekday,
+            CAST(strftime('%H', timestamp) AS INTEGER) AS hour,
+            SUM(rx_bytes),
+            SUM(tx_bytes),
+            MIN(timestamp)
+        FROM traffic
+        WHERE timestamp >= ? AND timestamp < ? AND {}
+        GROUP BY weekday, hour
+        "#,
+        host.sql()
+    ))
+    .bind(sql_ts(from))
+    .bind(sql_ts(to))
+    .fetch_all(pool)
+    .await?;
+
+    let mut cells = [[(0, 0); 24]; 7];
+    let mut first: Option<NaiveDateTime> = None;
+    for (weekday, hour, rx, tx, earliest) in rows {
+        cells[weekday as usize % 7][hour as usize % 24] = (rx, tx);
+        let earliest = parse_sql_ts(&earliest)?;
+        first = Some(first.map_or(earliest, |f| f.min(earliest)));
+    }
+    Ok((cells, first))
+}
+
+/// Merges raw samples recorded before `before` (rounded down to the hour)
+/// into one row per interface and hour, stamped at the start of the hour.
+/// Hours that already hold a single row are left alone, so repeated runs are
+/// cheap. Returns `(samples_merged, rows_written)`.
+pub async fn rollup_hourly(
+    pool: &SqlitePool,
+    before: NaiveDateTime,
+) -> Result<(u64, u64), sqlx::Error> {
+    let cutoff = sql_ts(Granularity::Hour.period_start(before));
+    let mut tx = pool.begin().await?;
+    let (max_id,): (Option<i64>,) = sqlx::query_as("SELECT MAX(id) FROM traffic")
+        .fetch_one(&mut *tx)
+        .await?;
+    let Some(max_id) = max_id else {
+        return Ok((0, 0));
+    };
+
+    let written = sqlx::query(
+        r#"
+        INSERT INTO traffic (host_id, interface, rx_bytes, tx_bytes, timestamp)
+        SELECT host_id, interface, SUM(rx_bytes), SUM(tx_bytes), strftime('%Y-%m-%d %H:00:00', timestamp) AS hour
+        FROM traffic
+        WHERE id <= ? AND timestamp < ?
+        GROUP BY host_id, interface, hour
+        HAVING COUNT(*) > 1
+        "#,
+    )
+    .bind(max_id)
+    .bind(&cutoff)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+
+    let merged = sqlx::query(
+        r#"
+        DELETE FROM traffic
+        WHERE id <= ? AND timestamp < ?
+          AND EXISTS (
+            SELECT 1 FROM traffic AS r
+            WHERE r.id > ?
+              AND r.host_id IS traffic.host_id
+              AND r.interface = traffic.interface
+              AND r.timestamp = strftime('%Y-%m-%d %H:00:00', traffic.timestamp)
+          )
+        "#,
+    )
+    .bind(max_id)
+    .bind(&cutoff)
+    .bind(max_id)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+
+    tx.commit().await?;
+    Ok((merged, written))
+}
+
+// ---- Import ----
+
+/// Traffic on one interface over `[start, end)`, as other tools record it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeriodTotal {
+    pub interface: String,
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    pub rx_bytes: i64,
+    pub tx_bytes: i64,
+}
+
+/// What `import_periods` did.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    /// Periods read.
+    pub periods: u64,
+    /// Rows written to `traffic`.
+    pub rows: u64,
+    pub rx_bytes: i64,
+    pub tx_bytes: i64,
+}
+
+/// Stores `periods` as one row each at the period start, shortest periods
+/// first. A period only adds the bytes `traffic` does not already have for
+/// that interface in `[start, end)`, per direction, so overlapping levels
+/// (hours inside a day), data ambr recorded itself and repeated imports are
+/// not counted twice. Periods belong to this machine, not a pushed host.
+pub async fn import_periods(
+    pool: &SqlitePool,
+    periods: &[PeriodTotal],
+) -> Result<ImportSummary, sqlx::Error> {
+    let mut order: Vec<&PeriodTotal> = periods.iter().collect();
+    order.sort_by_key(|p| (p.end - p.start, p.start));
+
+    let mut summary = ImportSummary {
+        periods: periods.len() as u64,
+        ..Default::default()
+    };
+    let mut tx = pool.begin().await?;
+    for p in order {
+        let (have_rx, have_tx): (i64, i64) = sqlx::query_as(
+            "SELECT COALESCE(SUM(rx_bytes), 0), COALESCE(SUM(tx_bytes), 0) FROM traffic \
+             WHERE host_id IS NULL AND interface = ? AND timestamp >= ? AND timestamp < ?",
+        )
+        .bind(&p.interface)
+        .bind(sql_ts(p.start))
+        .bind(sql_ts(p.end))
+        .fetch_one(&mut *tx)
+        .await?;
+        let rx = (p.rx_bytes - have_rx).max(0);
+        let tx_bytes = (p.tx_bytes - have_tx).max(0);
+        if rx == 0 && tx_bytes == 0 {
+            continue;
+        }
+        sqlx::query(
+            "INSERT INTO traffic (interface, rx_bytes, tx_bytes, timestamp) VALUES (?, ?, ?, ?)",
+        )
+        .bind(&p.interface)
+        .bind(rx)
+        .bind(tx_bytes)
+        .bind(sql_ts(p.start))
+        .execute(&mut *tx)
+        .await?;
+        summary.rows += 1;
+        summary.rx_bytes += rx;
+        summary.tx_bytes += tx_bytes;
+    }
+    tx.commit().await?;
+    Ok(summary)
+}
+
+// ---- Push outbox and ingest ----
+
+/// A sample in the outbox, and on the wire to `ambr server`. `seq` grows with
+/// every queued sample, so the collector can drop ones it has already seen.
+#[derive(Debug, Clone, PartialEq, Eq, FromRow, Serialize, Deserialize)]
+pub struct OutboxRow {
+    pub seq: i64,
+    pub interface: String,
+    pub timestamp: NaiveDateTime,
+    pub rx_bytes: i64,
+    pub tx_bytes: i64,
+}
+
+/// Oldest `limit` queued samples.
+pub async fn outbox_batch(pool: &SqlitePool, limit: u32) -> Result<Vec<OutboxRow>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT seq, interface, timestamp, rx_bytes, tx_bytes FROM outbox ORDER BY seq LIMIT ?",
+    )
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+/// Drops samples up to and including `seq` once the collector has them.
+pub async fn outbox_ack(pool: &SqlitePool, seq: i64) -> Result<u64, sqlx::Error> {
+    Ok(sqlx::query("DELETE FROM outbox WHERE seq <= ?")
+        .bind(seq)
+        .execute(pool)
+        .await?
+        .rows_affected())
+}
+
+pub async fn outbox_len(pool: &SqlitePool) -> Result<i64, sqlx::Error> {
+    let (n,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM outbox")
+        .fetch_one(pool)
+        .await?;
+    Ok(n)
+}
+
+/// Random id generated once per database. Pushed batches carry it, so the
+/// collector can tell a recreated database (whose outbox numbers start over)
+/// from retries.
+pub async fn database_id(pool: &SqlitePool) -> Result<String, sqlx::Error> {
+    sqlx::query(
+        "INSERT OR IGNORE INTO meta (key, value) VALUES ('database_id', lower(hex(randomblob(16))))",
+    )
+    .execute(pool)
+    .await?;
+    let (id,): (String,) = sqlx::query_as("SELECT value FROM meta WHERE key = 'database_id'")
+        .fetch_one(pool)
+        .await?;
+    Ok(id)
+}
+
+/// What `ingest_samples` did with a batch.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IngestSummary {
+    pub stored: u64,
+    /// Samples at or below the host's last `seq`, i.e. retries.
+    pub skipped: u64,
+    /// The host's sequence numbers started over, so its last `seq` was reset.
+    pub restarted: bool,
+}
+
+/// Stores samples pushed by another machine under its `hosts` row, created
+/// on first contact (name and labels are updated on every batch). Samples at
+/// or below the host's last `seq` are retries and are skipped.
+///
+/// Sequence numbers start over when the pushing database is recreated (or
+/// its outbox reset), or when two machines share a machine id. That shows as
+/// a `database_id` other than the last one seen, or as a batch that ends
+/// below the last `seq` (a retry resends the same samples, so it ends at it).
+/// The last `seq` is then reset so the samples are not dropped as retries.
+pub async fn ingest_samples(
+    pool: &SqlitePool,
+    hostname: &str,
+    machine_id: &str,
+    database_id: Option<&str>,
+    labels: &BTreeMap<String, String>,
+    samples: &[OutboxRow],
+) -> Result<IngestSummary, sqlx::Error> {
+    let labels = serde_json::to_string(labels).unwrap_or_else(|_| "{}".to_string());
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        "INSERT INTO hosts (hostname, machine_id, labels) VALUES (?, ?, ?) \
+         ON CONFLICT (machine_id) DO UPDATE SET hostname = excluded.hostname, labels = excluded.labels",
+    )
+    .bind(hostname)
+    .bind(machine_id)
+    .bind(labels)
+    .execute(&mut *tx)
+    .await?;
+    let (host_id, mut last_seq, last_database): (i64, i64, Option<String>) =
+        sqlx::query_as("SELECT id, last_seq, database_id FROM hosts WHERE machine_id = ?")
+            .bind(machine_id)
+            .fetch_one(&mut *tx)
+            .await?;
+    let new_database = database_id
+        .zip(last_database.as_deref())
+        .is_some_and(|(new, old)| new != old);
+    let behind = samples
+        .iter()
+        .map(|s| s.seq)
+        .max()
+        .is_some_and(|m| m < last_seq);
+    let restarted = new_database || behind;
+    if restarted {
+        last_seq = 0;
+    }
+
+    let mut stored = 0;
+    let mut max_seq = last_seq;
+    for s in samples.iter().filter(|s| s.seq > last_seq) {
+        sqlx::query(
+            "INSERT INTO traffic (interface, rx_bytes, tx_bytes, timestamp, host_id) \
+             VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(&s.interface)
+        .bind(s.rx_bytes)
+        .bind(s.tx_bytes)
+        .bind(sql_ts(s.timestamp))
+        .bind(host_id)
+        .execute(&mut *tx)
+        .await?;
+        stored += 1;
+        max_seq = max_seq.max(s.seq);
+    }
+    sqlx::query(
+        "UPDATE hosts SET last_seq = ?, database_id = COALESCE(?, database_id) WHERE id = ?",
+    )
+    .bind(max_seq)
+    .bind(database_id)
+    .bind(host_id)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(IngestSummary {
+        stored,
+        skipped: samples.len() as u64 - stored,
+        restarted,
+    })
+}
+
+// ---- Hosts ----
+
+/// Which machine's traffic a query covers. A database that never received a
+/// push only has local rows, so every filter but `Host` returns the same.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HostFilter {
+    #[default]
+    All,
+    /// Rows this machine recorded itself.
+    Local,
+    /// Rows pushed by the host with this `hosts.id`.
+    Host(i64),
+}
+
+impl HostFilter {
+    /// Condition on `traffic.host_id`.
+    fn sql(&self) -> String {
+        match self {
+            HostFilter::All => "1 = 1".to_string(),
+            HostFilter::Local => "host_id IS NULL".to_string(),
+            HostFilter::Host(id) => format!("host_id = {}", id),
+        }
+    }
+}
+
+/// A machine that pushes to this database.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Host {
+    pub id: i64,
+    pub hostname: String,
+    pub machine_id: String,
+    pub labels: BTreeMap<String, String>,
+    /// Highest sample sequence number received.
+    pub last_seq: i64,
+}
+
+fn row_to_host(
+    (id, hostname, machine_id, labels, last_seq): (i64, String, String, String, i64),
+) -> Host {
+    Host {
+        id,
+        hostname,
+        machine_id,
+        labels: serde_json::from_str(&labels).unwrap_or_default(),
+        last_seq,
+    }
+}
+
+/// Hosts by name.
+pub async fn hosts(pool: &SqlitePool) -> Result<Vec<Host>, sqlx::Error> {
+    let rows = sqlx::query_as(
+        "SELECT id, hostname, machine_id, labels, last_seq FROM hosts ORDER BY hostname, id",
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(row_to_host).collect())
+}
+
+/// `all`, `local`, or a host's name or machine id; `None` if no host matches.
+pub async fn resolve_host(
+    pool: &SqlitePool,
+    name: &str,
+) -> Result<Option<HostFilter>, sqlx::Error> {
+    match name {
+        "all" => return Ok(Some(HostFilter::All)),
+        "local" => return Ok(Some(HostFilter::Local)),
+        _ => {}
+    }
+    let row: Option<(i64,)> = sqlx::query_as(
+        "SELECT id FROM hosts WHERE hostname = ? OR machine_id = ? ORDER BY id LIMIT 1",
+    )
+    .bind(name)
+    .bind(name)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(|(id,)| HostFilter::Host(id)))
+}
+
+// ---- Filtered, paginated usage (HTTP API) ----
+
+/// Which traffic a usage query covers.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UsageFilter {
+    /// Only this interface; `None` means all.
+    pub interface: Option<String>,
+    pub host: HostFilter,
+}
+
+/// Usage of one period in bytes.
+#[derive(Debug, Clone, PartialEq, Eq, FromRow, Serialize)]
+pub struct UsageRow {
+    pub period: String,
+    pub rx_bytes: i64,
+    pub tx_bytes: i64,
+    pub total_bytes: i64,
+}
+
+/// Usage per period, newest first, for samples in `[from, to)` (either end
+/// may be open). Skips the first `offset` periods and returns at most `limit`.
+pub async fn usage_page(
+    pool: &SqlitePool,
+    granularity: Granularity,
+    filter: &UsageFilter,
+    from: Option<NaiveDateTime>,
+    to: Option<NaiveDateTime>,
+    limit: u32,
+    offset: u32,
+) -> Result<Vec<UsageRow>, sqlx::Error> {
+    let mut qb = QueryBuilder::<Sqlite>::new(format!(
+        "SELECT strftime('{}', timestamp) AS period, \
+         SUM(rx_bytes) AS rx_bytes, SUM(tx_bytes) AS tx_bytes, \
+         SUM(rx_bytes) + SUM(tx_bytes) AS total_bytes \
+         FROM traffic WHERE 1 = 1",
+        granularity.format()
+    ));
+    if let Some(from) = from {
+        qb.push(" AND timestamp >= ");
+        qb.push_bind(sql_ts(from));
+    }
+    if let Some(to) = to {
+        qb.push(" AND timestamp < ");
+        qb.push_bind(sql_ts(to));
+    }
+    push_interface_filter(&mut qb, filter.interface.as_slice());
+    qb.push(format!(" AND {}", filter.host.sql()));
+    qb.push(" GROUP BY period ORDER BY period DESC LIMIT ");
+    qb.push_bind(limit as i64);
+    qb.push(" OFFSET ");
+    qb.push_bind(offset as i64);
+
+    qb.build_query_as().fetch_all(pool).await
+}
+
+// ---- Export ----
+
+/// A raw sample or one interface's usage in one period.
+#[derive(Debug, Clone, PartialEq, Eq, FromRow, Serialize)]
+pub struct ExportRow {
+    /// Sample time, or the start of the period (`YYYY-MM-DDTHH:MM:SS`, UTC).
+    pub time: String,
+    pub interface: String,
+    pub rx_bytes: i64,
+    pub tx_bytes: i64,
+    pub total_bytes: i64,
+}
+
+impl Granularity {
+    /// SQL expression for the start of the period containing `timestamp`.
+    fn start_sql(&self) -> &'static str {
+        match self {
+            Granularity::Minute => "strftime('%Y-%m-%dT%H:%M:00', timestamp)",
+            Granularity::Hour => "strftime('%Y-%m-%dT%H:00:00', timestamp)",
+            Granularity::Day => "strftime('%Y-%m-%dT00:00:00', timestamp)",
+            // Monday of the week, as with `%W`.
+            Granularity::Week => "date(timestamp, 'weekday 0', '-6 days') || 'T00:00:00'",
+            Granularity::Month => "strftime('%Y-%m-01T00:00:00', timestamp)",
+        }
+    }
+}
+
+/// Feeds rows for `[from, to)` to `on_row`, oldest first, one at a time so
+/// the result set is never held in memory. `granularity` of `None` exports
+/// raw samples; otherwise rows are per period and interface. Returns the
+/// number of rows.
+pub async fn for_each_export_row<F>(
+    pool: &SqlitePool,
+    granularity: Option<Granularity>,
+    filter: &UsageFilter,
+    from: Option<NaiveDateTime>,
+    to: Option<NaiveDateTime>,
+    mut on_row: F,
+) -> Result<u64, sqlx::Error>
+where
+    F: FnMut(ExportRow) -> Result<(), sqlx::Error>,
+{
+    let mut qb = QueryBuilder::<Sqlite>::new(match granularity {
+        None => "SELECT strftime('%Y-%m-%dT%H:%M:%S', timestamp) AS time, interface, \
+                 rx_bytes, tx_bytes, rx_bytes + tx_bytes AS total_bytes \
+                 FROM traffic WHERE 1 = 1"
+            .to_string(),
+        Some(g) => format!(
+            "SELECT {} AS time, interface, SUM(rx_bytes) AS rx_bytes, SUM(tx_bytes) AS tx_bytes, \
+             SUM(rx_bytes) + SUM(tx_bytes) AS total_bytes \
+             FROM traffic WHERE 1 = 1",
+            g.start_sql()
+        ),
+    });
+    if let Some(from) = from {
+        qb.push(" AND timestamp >= ");
+        qb.push_bind(sql_ts(from));
+    }
+    if let Some(to) = to {
+        qb.push(" AND timestamp < ");
+        qb.push_bind(sql_ts(to));
+    }
+    push_interface_filter(&mut qb, filter.interface.as_slice());
+    qb.push(format!(" AND {}", filter.host.sql()));
+    qb.push(match granularity {
+        None => " ORDER BY timestamp, id",
+        Some(_) => " GROUP BY time, interface ORDER BY time, interface",
+    });
+
+    let mut rows = qb.build_query_as::<ExportRow>().fetch(pool);
+    let mut count = 0;
+    while let Some(row) = rows.try_next().await? {
+        on_row(row)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// An interface seen in `traffic`, with its all-time totals.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InterfaceSummary {
+    pub interface: String,
+    pub first_seen: NaiveDateTime,
+    pub last_seen: NaiveDateTime,
+    pub rx_bytes: i64,
+    pub tx_bytes: i64,
+}
+
+/// Every interface recorded by `host`, by name. With several hosts, an
+/// interface name they share gets one row for all of them.
+pub async fn interfaces(
+    pool: &SqlitePool,
+    host: HostFilter,
+) -> Result<Vec<InterfaceSummary>, sqlx::Error> {
+    let rows: Vec<(String, String, String, i64, i64)> = sqlx::query_as(&format!(
+        r#"
+        SELECT interface, MIN(timestamp), MAX(timestamp), SUM(rx_bytes), SUM(tx_bytes)
+        FROM traffic
+        WHERE {}
+        GROUP BY interface
+        ORDER BY interface
+        "#,
+        host.sql()
+    ))
+    .fetch_all(pool)
+    .await?;
+
+    rows.into_iter()
+        .map(|(interface, first, last, rx, tx)| {
+            Ok(InterfaceSummary {
+                interface,
+                first_seen: parse_sql_ts(&first)?,
+                last_seen: parse_sql_ts(&last)?,
+                rx_bytes: rx,
+                tx_bytes: tx,
+            })
+        })
+        .collect()
+}
+
 // ---- Live tab: recent usage (totals and per-interface) ----
 
 /// Total rx/tx in MiB for the last `since_minutes` minutes.
 pub async fn recent_totals(
     pool: &SqlitePool,
     since_minutes: u32,
+    host: HostFilter,
 ) -> Result<(f64, f64, f64), sqlx::Error> {
-    let row = sqlx::query_as::<_, (Option<i64>, Option<i64>)>(
+    let row = sqlx::query_as::<_, (Option<i64>, Option<i64>)>(&format!(
         r#"
         SELECT SUM(rx_bytes), SUM(tx_bytes)
         FROM traffic
-        WHERE timestamp >= datetime('now', ?)
+        WHERE timestamp >= datetime('now', ?) AND {}
         "#,
-    )
+        host.sql()
+    ))
     .bind(format!("-{} minutes", since_minutes))
     .fetch_one(pool)
     .await?;
@@ -169,7 +1155,60 @@ pub async fn recent_totals(
     Ok((rx, tx, rx + tx))
 }
 
-#[derive(Debug, Clone)]
+/// Raw rx/tx bytes recorded at or after `since` (UTC), optionally for one interface.
+pub async fn bytes_since(
+    pool: &SqlitePool,
+    since: NaiveDateTime,
+    interface: Option<&str>,
+    host: HostFilter,
+) -> Result<(i64, i64), sqlx::Error> {
+    let row = sqlx::query_as::<_, (Option<i64>, Option<i64>)>(&format!(
+        r#"
+        SELECT SUM(rx_bytes), SUM(tx_bytes)
+        FROM traffic
+        WHERE timestamp >= ? AND (? IS NULL OR interface = ?) AND {}
+        "#,
+        host.sql()
+    ))
+    .bind(sql_ts(since))
+    .bind(interface)
+    .bind(interface)
+    .fetch_one(pool)
+    .await?;
+
+    Ok((row.0.unwrap_or(0), row.1.unwrap_or(0)))
+}
+
+/// Raw `(rx, tx)` bytes per interface recorded in `[from, to)` (UTC); without
+/// `to`, everything from `from` on.
+pub async fn bytes_by_interface(
+    pool: &SqlitePool,
+    from: NaiveDateTime,
+    to: Option<NaiveDateTime>,
+    host: HostFilter,
+) -> Result<BTreeMap<String, (i64, i64)>, sqlx::Error> {
+    let rows: Vec<(String, i64, i64)> = sqlx::query_as(&format!(
+        r#"
+        SELECT interface, SUM(rx_bytes), SUM(tx_bytes)
+        FROM traffic
+        WHERE timestamp >= ? AND (? IS NULL OR timestamp < ?) AND {}
+        GROUP BY interface
+        "#,
+        host.sql()
+    ))
+    .bind(sql_ts(from))
+    .bind(to.map(sql_ts))
+    .bind(to.map(sql_ts))
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(interface, rx, tx)| (interface, (rx, tx)))
+        .collect())
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct LiveInterfaceRow {
     pub interface: String,
     pub rx_mib: f64,
@@ -188,16 +1227,18 @@ struct LiveAggRow {
 pub async fn recent_by_interface(
     pool: &SqlitePool,
     since_minutes: u32,
+    host: HostFilter,
 ) -> Result<Vec<LiveInterfaceRow>, sqlx::Error> {
-    let rows: Vec<LiveAggRow> = sqlx::query_as(
+    let rows: Vec<LiveAggRow> = sqlx::query_as(&format!(
         r#"
         SELECT interface, SUM(rx_bytes) AS rx, SUM(tx_bytes) AS tx
         FROM traffic
-        WHERE timestamp >= datetime('now', ?)
+        WHERE timestamp >= datetime('now', ?) AND {}
         GROUP BY interface
         ORDER BY (rx + tx) DESC
         "#,
-    )
+        host.sql()
+    ))
     .bind(format!("-{} minutes", since_minutes))
     .fetch_all(pool)
     .await?;
@@ -217,3 +1258,415 @@ pub async fn recent_by_interface(
         .collect();
     Ok(out)
 }
+
+// ---- Quotas ----
+
+#[derive(FromRow)]
+struct QuotaRow {
+    name: String,
+    limit_bytes: i64,
+    interfaces: String,
+    direction: String,
+    cycle_start_day: Option<i64>,
+    period_start: Option<String>,
+    period_end: Option<String>,
+    free_windows: String,
+}
+
+fn parse_sql_ts(s: &str) -> Result<NaiveDateTime, sqlx::Error> {
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").map_err(|e| sqlx::Error::Decode(e.into()))
+}
+
+/// Parses `YYYY-MM-DD`, `YYYY-MM-DD HH:MM[:SS]` or `YYYY-MM-DDTHH:MM[:SS]` (UTC).
+pub fn parse_time(s: &str) -> Result<NaiveDateTime, String> {
+    let s = s.trim();
+    if let Ok(d) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return Ok(d.and_time(chrono::NaiveTime::MIN));
+    }
+    let s = s.replacen('T', " ", 1);
+    ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|f| NaiveDateTime::parse_from_str(&s, f).ok())
+        .ok_or_else(|| format!("invalid time '{}' (expected YYYY-MM-DD[THH:MM[:SS]])", s))
+}
+
+fn row_to_quota(r: QuotaRow) -> Result<Quota, sqlx::Error> {
+    let direction = r
+        .direction
+        .parse::<Direction>()
+        .map_err(|e| sqlx::Error::Decode(e.into()))?;
+    let cycle = match (r.cycle_start_day, r.period_start, r.period_end) {
+        (Some(day), _, _) => Cycle::Monthly {
+            start_day: day as u32,
+        },
+        (None, Some(from), Some(to)) => Cycle::Custom {
+            from: parse_sql_ts(&from)?,
+            to: parse_sql_ts(&to)?,
+        },
+        _ => {
+            return Err(sqlx::Error::Decode(
+                format!("quota '{}' has no cycle", r.name).into(),
+            ));
+        }
+    };
+    let free_windows = r
+        .free_windows
+        .split(';')
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse::<TimeWindow>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| sqlx::Error::Decode(e.into()))?;
+    Ok(Quota {
+        name: r.name,
+        limit_bytes: r.limit_bytes,
+        interfaces: r
+            .interfaces
+            .split(',')
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+            .collect(),
+        direction,
+        cycle,
+        free_windows,
+    })
+}
+
+pub async fn add_quota(pool: &SqlitePool, quota: &Quota) -> Result<(), sqlx::Error> {
+    let (start_day, from, to) = match &quota.cycle {
+        Cycle::Monthly { start_day } => (Some(*start_day as i64), None, None),
+        Cycle::Custom { from, to } => (None, Some(sql_ts(*from)), Some(sql_ts(*to))),
+    };
+    sqlx::query(
+        r#"
+        INSERT INTO quotas (name, limit_bytes, interfaces, direction, cycle_start_day, period_start, period_end, free_windows)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&quota.name)
+    .bind(quota.limit_bytes)
+    .bind(quota.interfaces.join(","))
+    .bind(quota.direction.as_str())
+    .bind(start_day)
+    .bind(from)
+    .bind(to)
+    .bind(
+        quota
+            .free_windows
+            .iter()
+            .map(|w| w.to_string())
+            .collect::<Vec<_>>()
+            .join(";"),
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn list_quotas(pool: &SqlitePool) -> Result<Vec<Quota>, sqlx::Error> {
+    let rows: Vec<QuotaRow> = sqlx::query_as(
+        r#"
+        SELECT name, limit_bytes, interfaces, direction, cycle_start_day, period_start, period_end, free_windows
+        FROM quotas
+        ORDER BY name
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    rows.into_iter().map(row_to_quota).collect()
+}
+
+/// Removes a quota by name. Returns false if it did not exist.
+pub async fn delete_quota(pool: &SqlitePool, name: &str) -> Result<bool, sqlx::Error> {
+    let res = sqlx::query("DELETE FROM quotas WHERE name = ?")
+        .bind(name)
+        .execute(pool)
+        .await?;
+    Ok(res.rows_affected() > 0)
+}
+
+/// Appends `AND interface IN (...)` when `interfaces` is non-empty.
+fn push_interface_filter(qb: &mut QueryBuilder<'_, Sqlite>, interfaces: &[String]) {
+    if interfaces.is_empty() {
+        return;
+    }
+    qb.push(" AND interface IN (");
+    let mut sep = qb.separated(", ");
+    for iface in interfaces {
+        sep.push_bind(iface.clone());
+    }
+    sep.push_unseparated(")");
+}
+
+/// SQL condition that is true when a sample's `timestamp` falls inside any
+/// of `windows`. Evaluated per raw sample, so window edges are exact to the
+/// recording interval rather than rounded to hourly buckets.
+fn free_window_sql(windows: &[TimeWindow]) -> String {
+    // strftime('%w') counts from Sunday; TimeWindow days count from Monday.
+    let dow = "CAST(strftime('%w', timestamp) AS INTEGER)";
+    let sod = "(CAST(strftime('%s', timestamp) AS INTEGER) % 86400)";
+    let days = |shift: usize, w: &TimeWindow| {
+        (0..7)
+            .filter(|d| w.days[*d])
+            .map(|d| ((d + 1 + shift) % 7).to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let parts: Vec<String> = windows
+        .iter()
+        .filter(|w| w.days.iter().any(|d| *d))
+        .map(|w| {
+            if w.end > w.start {
+                format!(
+                    "({dow} IN ({}) AND {sod} >= {} AND {sod} < {})",
+                    days(0, w),
+                    w.start,
+                    w.end
+                )
+            } else {
+                // Wraps past midnight: the tail belongs to the previous day's window.
+                format!(
+                    "({dow} IN ({}) AND {sod} >= {}) OR ({dow} IN ({}) AND {sod} < {})",
+                    days(0, w),
+                    w.start,
+                    days(1, w),
+                    w.end
+                )
+            }
+        })
+        .collect();
+    if parts.is_empty() {
+        "0".to_string()
+    } else {
+        format!("({})", parts.join(" OR "))
+    }
+}
+
+/// Traffic split into bytes that count and bytes inside free windows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SplitUsage {
+    pub counted_rx: i64,
+    pub counted_tx: i64,
+    pub free_rx: i64,
+    pub free_tx: i64,
+}
+
+/// Usage in `[from, to)` split by `free_windows`, optionally restricted to
+/// `interfaces`.
+pub async fn split_usage(
+    pool: &SqlitePool,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    interfaces: &[String],
+    free_windows: &[TimeWindow],
+    host: HostFilter,
+) -> Result<SplitUsage, sqlx::Error> {
+    let free = free_window_sql(free_windows);
+    let mut qb = QueryBuilder::<Sqlite>::new(format!(
+        "SELECT \
+         SUM(CASE WHEN {free} THEN 0 ELSE rx_bytes END), \
+         SUM(CASE WHEN {free} THEN 0 ELSE tx_bytes END), \
+         SUM(CASE WHEN {free} THEN rx_bytes ELSE 0 END), \
+         SUM(CASE WHEN {free} THEN tx_bytes ELSE 0 END) \
+         FROM traffic WHERE timestamp >= "
+    ));
+    qb.push_bind(sql_ts(from));
+    qb.push(" AND timestamp < ");
+    qb.push_bind(sql_ts(to));
+    push_interface_filter(&mut qb, interfaces);
+    qb.push(format!(" AND {}", host.sql()));
+
+    let (counted_rx, counted_tx, free_rx, free_tx) = qb
+        .build_query_as::<(Option<i64>, Option<i64>, Option<i64>, Option<i64>)>()
+        .fetch_one(pool)
+        .await?;
+    Ok(SplitUsage {
+        counted_rx: counted_rx.unwrap_or(0),
+        counted_tx: counted_tx.unwrap_or(0),
+        free_rx: free_rx.unwrap_or(0),
+        free_tx: free_tx.unwrap_or(0),
+    })
+}
+
+/// Usage counted against `quota` in the billing cycle containing `now` (UTC).
+/// Traffic inside the quota's free windows is reported separately. Quotas
+/// are this machine's data cap, so callers pass `HostFilter::Local`.
+pub async fn quota_usage(
+    pool: &SqlitePool,
+    quota: &Quota,
+    now: NaiveDateTime,
+    host: HostFilter,
+) -> Result<QuotaUsage, sqlx::Error> {
+    let (start, end) = quota.cycle_at(now);
+    let split = split_usage(
+        pool,
+        start,
+        end,
+        &quota.interfaces,
+        &quota.free_windows,
+        host,
+    )
+    .await?;
+
+    Ok(QuotaUsage {
+        quota: quota.clone(),
+        cycle_start: start,
+        cycle_end: end,
+        used_bytes: quota.direction.pick(split.counted_rx, split.counted_tx),
+        free_bytes: quota.direction.pick(split.free_rx, split.free_tx),
+    })
+}
+
+/// Raw rx/tx per UTC hour in `[from, to)` as `(hour_start, rx, tx)`, oldest
+/// first, optionally restricted to `interfaces`. Hours without samples are omitted.
+pub async fn hourly_bytes(
+    pool: &SqlitePool,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    interfaces: &[String],
+    host: HostFilter,
+) -> Result<Vec<(NaiveDateTime, i64, i64)>, sqlx::Error> {
+    let mut qb = QueryBuilder::<Sqlite>::new(
+        "SELECT strftime('%Y-%m-%d %H:00:00', timestamp) AS hour, SUM(rx_bytes), SUM(tx_bytes) \
+         FROM traffic WHERE timestamp >= ",
+    );
+    qb.push_bind(sql_ts(from));
+    qb.push(" AND timestamp < ");
+    qb.push_bind(sql_ts(to));
+    push_interface_filter(&mut qb, interfaces);
+    qb.push(format!(" AND {}", host.sql()));
+    qb.push(" GROUP BY hour ORDER BY hour");
+
+    let rows = qb
+        .build_query_as::<(String, i64, i64)>()
+        .fetch_all(pool)
+        .await?;
+
+    rows.into_iter()
+        .map(|(hour, rx, tx)| Ok((parse_sql_ts(&hour)?, rx, tx)))
+        .collect()
+}
+
+/// Bytes counted against `quota` per UTC day in `[from, to)`, oldest first,
+/// excluding its free windows. Days without samples are omitted.
+pub async fn quota_daily_usage(
+    pool: &SqlitePool,
+    quota: &Quota,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    host: HostFilter,
+) -> Result<Vec<(NaiveDate, i64)>, sqlx::Error> {
+    let free = free_window_sql(&quota.free_windows);
+    let mut qb = QueryBuilder::<Sqlite>::new(format!(
+        "SELECT strftime('%Y-%m-%d', timestamp) AS day, \
+         SUM(CASE WHEN {free} THEN 0 ELSE rx_bytes END), \
+         SUM(CASE WHEN {free} THEN 0 ELSE tx_bytes END) \
+         FROM traffic WHERE timestamp >= "
+    ));
+    qb.push_bind(sql_ts(from));
+    qb.push(" AND timestamp < ");
+    qb.push_bind(sql_ts(to));
+    push_interface_filter(&mut qb, &quota.interfaces);
+    qb.push(format!(" AND {}", host.sql()));
+    qb.push(" GROUP BY day ORDER BY day");
+
+    let rows = qb
+        .build_query_as::<(String, i64, i64)>()
+        .fetch_all(pool)
+        .await?;
+
+    rows.into_iter()
+        .map(|(day, rx, tx)| {
+            let day = NaiveDate::parse_from_str(&day, "%Y-%m-%d")
+                .map_err(|e| sqlx::Error::Decode(e.into()))?;
+            Ok((day, quota.direction.pick(rx, tx)))
+        })
+        .collect()
+}
+
+/// Current-cycle usage for every stored quota.
+pub async fn quota_usages(
+    pool: &SqlitePool,
+    now: NaiveDateTime,
+    host: HostFilter,
+) -> Result<Vec<QuotaUsage>, sqlx::Error> {
+    let mut out = Vec::new();
+    for quota in list_quotas(pool).await? {
+        out.push(quota_usage(pool, &quota, now, host).await?);
+    }
+    Ok(out)
+}
+
+// ---- Reverse DNS cache ----
+
+/// A cached PTR lookup. `name` is `None` when the address has no PTR record.
+#[derive(Debug, Clone)]
+pub struct PtrEntry {
+    pub ip: String,
+    pub name: Option<String>,
+}
+
+/// Cached PTR result for `ip`, if it was resolved within the last `max_age_secs` seconds.
+pub async fn cached_ptr(
+    pool: &SqlitePool,
+    ip: &str,
+    max_age_secs: u64,
+) -> Result<Option<PtrEntry>, sqlx::Error> {
+    let row = sqlx::query_as::<_, (String, Option<String>)>(
+        r#"
+        SELECT ip, name
+        FROM ptr_cache
+        WHERE ip = ? AND resolved_at >= datetime('now', ?)
+        "#,
+    )
+    .bind(ip)
+    .bind(format!("-{} seconds", max_age_secs))
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|(ip, name)| PtrEntry { ip, name }))
+}
+
+/// Stores (or refreshes) the PTR result for `ip`.
+pub async fn store_ptr(pool: &SqlitePool, ip: &str, name: Option<&str>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO ptr_cache (ip, name, resolved_at) VALUES (?, ?, CURRENT_TIMESTAMP)
+        ON CONFLICT(ip) DO UPDATE SET name = excluded.name, resolved_at = excluded.resolved_at
+        "#,
+    )
+    .bind(ip)
+    .bind(name)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Whether alert rule `rule` was active when its state last changed; `false`
+/// if it never fired.
+pub async fn alert_active(pool: &SqlitePool, rule: &str) -> Result<bool, sqlx::Error> {
+    let active: Option<(bool,)> = sqlx::query_as("SELECT active FROM alert_state WHERE rule = ?")
+        .bind(rule)
+        .fetch_optional(pool)
+        .await?;
+    Ok(active.is_some_and(|(a,)| a))
+}
+
+/// Records that alert rule `rule` fired (`active`) or cleared.
+pub async fn set_alert_active(
+    pool: &SqlitePool,
+    rule: &str,
+    active: bool,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO alert_state (rule, active, changed_at) VALUES (?, ?, CURRENT_TIMESTAMP)
+        ON CONFLICT(rule) DO UPDATE SET active = excluded.active, changed_at = excluded.changed_at
+        "#,
+    )
+    .bind(rule)
+    .bind(active)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
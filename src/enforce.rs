@@ -0,0 +1,171 @@
+//! Traffic enforcement when a quota trips: a `tc` rate limit or an nftables
+//! drop rule on one interface, removed again when the rule clears (i.e. at
+//! the cycle reset). Needs root unless `dry_run` is set, in which case the
+//! commands are only printed, to stderr.
+
+use serde::Deserialize;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum Method {
+    /// Shape egress with `tbf` and police ingress to `rate` (tc syntax, e.g. `256kbit`).
+    Tc { rate: String },
+    /// Drop all traffic in and out of the interface.
+    Nftables,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Enforcement {
+    pub interface: String,
+    #[serde(flatten)]
+    pub method: Method,
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+fn cmd(parts: &[&str]) -> Vec<String> {
+    parts.iter().map(|s| s.to_string()).collect()
+}
+
+impl Enforcement {
+    /// nftables table owned by ambr for this interface.
+    fn nft_table(&self) -> String {
+        let sanitized: String = self
+            .interface
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        format!("ambr_{}", sanitized)
+    }
+
+    pub fn apply_commands(&self) -> Vec<Vec<String>> {
+        let dev = self.interface.as_str();
+        match &self.method {
+            Method::Tc { rate } => vec![
+                cmd(&[
+                    "tc", "qdisc", "replace", "dev", dev, "root", "tbf", "rate", rate, "burst",
+                    "32kbit", "latency", "400ms",
+                ]),
+                cmd(&[
+                    "tc", "qdisc", "add", "dev", dev, "handle", "ffff:", "ingress",
+                ]),
+                cmd(&[
+                    "tc", "filter", "add", "dev", dev, "parent", "ffff:", "protocol", "all",
+                    "prio", "1", "u32", "match", "u32", "0", "0", "police", "rate", rate, "burst",
+                    "32k", "drop", "flowid", ":1",
+                ]),
+            ],
+            Method::Nftables => {
+                let table = self.nft_table();
+                let t = table.as_str();
+                vec![
+                    cmd(&["nft", "add", "table", "inet", t]),
+                    cmd(&[
+                        "nft",
+                        "add",
+                        "chain",
+                        "inet",
+                        t,
+                        "input",
+                        "{ type filter hook input priority 0; policy accept; }",
+                    ]),
+                    cmd(&[
+                        "nft",
+                        "add",
+                        "chain",
+                        "inet",
+                        t,
+                        "output",
+                        "{ type filter hook output priority 0; policy accept; }",
+                    ]),
+                    cmd(&[
+                        "nft", "add", "rule", "inet", t, "input", "iifname", dev, "drop",
+                    ]),
+                    cmd(&[
+                        "nft", "add", "rule", "inet", t, "output", "oifname", dev, "drop",
+                    ]),
+                ]
+            }
+        }
+    }
+
+    pub fn remove_commands(&self) -> Vec<Vec<String>> {
+        let dev = self.interface.as_str();
+        match &self.method {
+            Method::Tc { .. } => vec![
+                cmd(&["tc", "qdisc", "del", "dev", dev, "root"]),
+                cmd(&["tc", "qdisc", "del", "dev", dev, "ingress"]),
+            ],
+            Method::Nftables => vec![cmd(&["nft", "delete", "table", "inet", &self.nft_table()])],
+        }
+    }
+
+    /// Applies the limit. Leftovers from an earlier run (e.g. a daemon restart
+    /// while enforced) are removed first so the rules are never duplicated.
+    pub async fn apply(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if !self.dry_run {
+            self.lift_leftovers().await?;
+        }
+        for c in self.apply_commands() {
+            self.execute(&c).await?;
+        }
+        Ok(())
+    }
+
+    /// Removes a limit left behind by an earlier run, if there is one; unlike
+    /// `remove`, finding nothing to remove is not an error.
+    pub async fn lift_leftovers(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        for c in self.remove_commands() {
+            if self.dry_run {
+                eprintln!("[dry-run] {}", shell_words(&c));
+            } else {
+                let _ = run(&c, true).await;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn remove(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        for c in self.remove_commands() {
+            self.execute(&c).await?;
+        }
+        Ok(())
+    }
+
+    async fn execute(&self, c: &[String]) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if self.dry_run {
+            eprintln!("[dry-run] {}", shell_words(c));
+            return Ok(());
+        }
+        run(c, false).await
+    }
+}
+
+async fn run(c: &[String], quiet: bool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut command = tokio::process::Command::new(&c[0]);
+    command.args(&c[1..]).kill_on_drop(true);
+    if quiet {
+        command
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null());
+    }
+    let status = command.status().await?;
+    if !status.success() {
+        return Err(format!("`{}` exited with {}", shell_words(c), status).into());
+    }
+    Ok(())
+}
+
+/// Joins a command for display, quoting arguments that contain spaces.
+pub fn shell_words(c: &[String]) -> String {
+    c.iter()
+        .map(|a| {
+            if a.contains(' ') || a.contains(';') {
+                format!("'{}'", a)
+            } else {
+                a.clone()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
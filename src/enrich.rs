@@ -0,0 +1,214 @@
+//! Reverse DNS and ASN enrichment for remote addresses.
+//!
+//! PTR names come from the system resolver and are cached in the `ptr_cache`
+//! table. ASN/organisation data comes from a local offline database, either a
+//! MaxMind `.mmdb` (e.g. GeoLite2-ASN) or an ip2asn TSV file.
+
+use crate::db;
+use dns_lookup::LookupErrorKind;
+use sqlx::SqlitePool;
+use std::fmt;
+use std::io::BufRead;
+use std::net::IpAddr;
+use std::path::Path;
+
+/// Autonomous system an address belongs to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsnInfo {
+    pub asn: u32,
+    pub org: String,
+}
+
+impl fmt::Display for AsnInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} / AS{}", self.org, self.asn)
+    }
+}
+
+struct AsnRange {
+    start: u128,
+    end: u128,
+    info: usize,
+}
+
+enum AsnSource {
+    MaxMind(maxminddb::Reader<Vec<u8>>),
+    Ranges {
+        v4: Vec<AsnRange>,
+        v6: Vec<AsnRange>,
+        infos: Vec<AsnInfo>,
+    },
+}
+
+/// Offline IP → ASN database.
+pub struct AsnDb(AsnSource);
+
+fn ip_key(ip: IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(v4) => u32::from(v4) as u128,
+        IpAddr::V6(v6) => u128::from(v6),
+    }
+}
+
+impl AsnDb {
+    /// Opens a MaxMind database (`.mmdb`) or an ip2asn TSV file (anything else).
+    pub fn open(path: &Path) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        if path.extension().is_some_and(|e| e == "mmdb") {
+            Ok(AsnDb(AsnSource::MaxMind(maxminddb::Reader::open_readfile(
+                path,
+            )?)))
+        } else {
+            let file = std::fs::File::open(path)?;
+            Self::from_ip2asn_tsv(std::io::BufReader::new(file))
+        }
+    }
+
+    /// Parses ip2asn TSV (`range_start range_end AS_number country AS_description`).
+    /// Ranges with AS 0 ("Not routed") are skipped.
+    pub fn from_ip2asn_tsv(
+        reader: impl BufRead,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let mut v4 = Vec::new();
+        let mut v6 = Vec::new();
+        let mut infos: Vec<AsnInfo> = Vec::new();
+
+        for (n, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let cols: Vec<&str> = line.split('\t').collect();
+            if cols.len() < 5 {
+                return Err(format!("ip2asn line {}: expected 5 columns", n + 1).into());
+            }
+            let start: IpAddr = cols[0].parse()?;
+            let end: IpAddr = cols[1].parse()?;
+            let asn: u32 = cols[2].parse()?;
+            if asn == 0 {
+                continue;
+            }
+            // Consecutive ranges of the same AS share one info entry.
+            let info = match infos.last() {
+                Some(last) if last.asn == asn && last.org == cols[4] => infos.len() - 1,
+                _ => {
+                    infos.push(AsnInfo {
+                        asn,
+                        org: cols[4].to_string(),
+                    });
+                    infos.len() - 1
+                }
+            };
+            let range = AsnRange {
+                start: ip_key(start),
+                end: ip_key(end),
+                info,
+            };
+            match (start, end) {
+                (IpAddr::V4(_), IpAddr::V4(_)) => v4.push(range),
+                (IpAddr::V6(_), IpAddr::V6(_)) => v6.push(range),
+                _ => return Err(format!("ip2asn line {}: mixed address families", n + 1).into()),
+            }
+        }
+
+        v4.sort_by_key(|r| r.start);
+        v6.sort_by_key(|r| r.start);
+        Ok(AsnDb(AsnSource::Ranges { v4, v6, infos }))
+    }
+
+    pub fn lookup(&self, ip: IpAddr) -> Option<AsnInfo> {
+        match &self.0 {
+            AsnSource::MaxMind(reader) => {
+                let asn: maxminddb::geoip2::Asn = reader.lookup(ip).ok()?.decode().ok()??;
+                Some(AsnInfo {
+                    asn: asn.autonomous_system_number?,
+                    org: asn.autonomous_system_organization.unwrap_or("").to_string(),
+                })
+            }
+            AsnSource::Ranges { v4, v6, infos } => {
+                let ranges = if ip.is_ipv4() { v4 } else { v6 };
+                let key = ip_key(ip);
+                let idx = ranges.partition_point(|r| r.start <= key);
+                let range = ranges.get(idx.checked_sub(1)?)?;
+                (key <= range.end).then(|| infos[range.info].clone())
+            }
+        }
+    }
+}
+
+/// Everything known about a remote address.
+#[derive(Debug, Clone)]
+pub struct HostLabel {
+    pub ip: IpAddr,
+    pub ptr: Option<String>,
+    pub asn: Option<AsnInfo>,
+}
+
+impl fmt::Display for HostLabel {
+    /// "Netflix / AS2906" when the ASN is known, otherwise the PTR name, otherwise the IP.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.asn, &self.ptr) {
+            (Some(asn), _) => write!(f, "{}", asn),
+            (None, Some(ptr)) => write!(f, "{}", ptr),
+            (None, None) => write!(f, "{}", self.ip),
+        }
+    }
+}
+
+/// Resolves PTR names (cached in SQLite) and ASN data for remote addresses.
+pub struct Enricher {
+    pool: SqlitePool,
+    asn: Option<AsnDb>,
+    ptr_ttl_secs: u64,
+}
+
+/// PTR results (including addresses without a name) are re-resolved after a day.
+const DEFAULT_PTR_TTL_SECS: u64 = 24 * 60 * 60;
+
+impl Enricher {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self {
+            pool,
+            asn: None,
+            ptr_ttl_secs: DEFAULT_PTR_TTL_SECS,
+        }
+    }
+
+    pub fn with_asn_db(mut self, asn: AsnDb) -> Self {
+        self.asn = Some(asn);
+        self
+    }
+
+    pub fn with_ptr_ttl(mut self, secs: u64) -> Self {
+        self.ptr_ttl_secs = secs;
+        self
+    }
+
+    /// PTR name for `ip`, from the cache or the system resolver.
+    pub async fn ptr(&self, ip: IpAddr) -> Result<Option<String>, sqlx::Error> {
+        let key = ip.to_string();
+        if let Some(entry) = db::cached_ptr(&self.pool, &key, self.ptr_ttl_secs).await? {
+            return Ok(entry.name);
+        }
+
+        // getnameinfo blocks; keep it off the async workers.
+        let name = match tokio::task::spawn_blocking(move || dns_lookup::lookup_addr(&ip)).await {
+            Ok(Ok(name)) => Some(name),
+            // Only a definite "no such name" is cached; timeouts, SERVFAIL or
+            // an unreachable resolver are retried on the next lookup.
+            Ok(Err(e)) if matches!(e.kind(), LookupErrorKind::NoName | LookupErrorKind::NoData) => {
+                None
+            }
+            _ => return Ok(None),
+        };
+        db::store_ptr(&self.pool, &key, name.as_deref()).await?;
+        Ok(name)
+    }
+
+    pub async fn label(&self, ip: IpAddr) -> Result<HostLabel, sqlx::Error> {
+        Ok(HostLabel {
+            ip,
+            ptr: self.ptr(ip).await?,
+            asn: self.asn.as_ref().and_then(|db| db.lookup(ip)),
+        })
+    }
+}
@@ -0,0 +1,164 @@
+//! `ambr export`: usage as CSV, JSON, NDJSON or InfluxDB line protocol.
+//!
+//! Rows are written as the database yields them, so exporting years of raw
+//! samples needs no more memory than exporting a day. Columns are always
+//! `time, interface, rx_bytes, tx_bytes, total_bytes`, oldest first; byte
+//! counts are exact integers and times are UTC (`YYYY-MM-DDTHH:MM:SS`, the
+//! period start for aggregated granularities). Line protocol has its own
+//! shape, see `influx`.
+
+use crate::db::{self, ExportRow, Granularity, UsageFilter};
+use crate::influx;
+use chrono::NaiveDateTime;
+use sqlx::SqlitePool;
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// Column names, in order, for formats with a header.
+pub const COLUMNS: [&str; 5] = ["time", "interface", "rx_bytes", "tx_bytes", "total_bytes"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    /// One JSON array.
+    Json,
+    /// One JSON object per line.
+    Ndjson,
+    /// InfluxDB line protocol.
+    Influx,
+}
+
+impl Format {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Json => "json",
+            Format::Ndjson => "ndjson",
+            Format::Influx => "influx",
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            "ndjson" | "jsonl" => Ok(Format::Ndjson),
+            "influx" | "line" => Ok(Format::Influx),
+            _ => Err(format!(
+                "unknown format '{}' (csv, json, ndjson, influx)",
+                s
+            )),
+        }
+    }
+}
+
+/// What to export.
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    pub format: Format,
+    /// `None` exports raw samples.
+    pub granularity: Option<Granularity>,
+    pub filter: UsageFilter,
+    /// Start (inclusive).
+    pub from: Option<NaiveDateTime>,
+    /// End (exclusive).
+    pub to: Option<NaiveDateTime>,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            format: Format::Csv,
+            granularity: None,
+            filter: UsageFilter::default(),
+            from: None,
+            to: None,
+        }
+    }
+}
+
+/// Quotes a CSV field if it contains a separator, quote or line break.
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn write_row(
+    out: &mut impl Write,
+    format: Format,
+    row: &ExportRow,
+    first: bool,
+) -> Result<(), Error> {
+    match format {
+        Format::Csv => writeln!(
+            out,
+            "{},{},{},{},{}",
+            row.time,
+            csv_field(&row.interface),
+            row.rx_bytes,
+            row.tx_bytes,
+            row.total_bytes
+        )?,
+        Format::Json => {
+            out.write_all(if first { b"\n" } else { b",\n" })?;
+            serde_json::to_writer(&mut *out, row)?;
+        }
+        Format::Ndjson => {
+            serde_json::to_writer(&mut *out, row)?;
+            out.write_all(b"\n")?;
+        }
+        Format::Influx => influx::write_line(out, row)?,
+    }
+    Ok(())
+}
+
+/// Writes the export to `out` and returns the number of rows.
+pub async fn export(
+    pool: &SqlitePool,
+    opts: &ExportOptions,
+    mut out: impl Write,
+) -> Result<u64, Error> {
+    match opts.format {
+        Format::Csv => writeln!(out, "{}", COLUMNS.join(","))?,
+        Format::Json => out.write_all(b"[")?,
+        Format::Ndjson | Format::Influx => {}
+    }
+
+    let mut first = true;
+    let rows = db::for_each_export_row(
+        pool,
+        opts.granularity,
+        &opts.filter,
+        opts.from,
+        opts.to,
+        |row| {
+            write_row(&mut out, opts.format, &row, first)
+                .map_err(|e| sqlx::Error::Io(std::io::Error::other(e)))?;
+            first = false;
+            Ok(())
+        },
+    )
+    .await?;
+
+    if opts.format == Format::Json {
+        out.write_all(if rows == 0 { b"]\n" } else { b"\n]\n" })?;
+    }
+    out.flush()?;
+    Ok(rows)
+}
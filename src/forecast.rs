@@ -0,0 +1,196 @@
+//! End-of-cycle usage projection for quotas.
+//!
+//! Daily totals from the recent past are fitted with a linear trend, scaled by
+//! a day-of-week factor (weekends often look nothing like weekdays), and then
+//! extrapolated over the rest of the billing cycle.
+
+use crate::db::{self, HostFilter};
+use crate::quota::QuotaUsage;
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime};
+use sqlx::SqlitePool;
+
+/// Days of history used to fit the model.
+pub const LOOKBACK_DAYS: i64 = 28;
+
+/// Seasonality needs at least one full week to mean anything.
+const MIN_DAYS_FOR_SEASONALITY: usize = 7;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Forecast {
+    /// Projected usage at the end of the cycle (already-used bytes included).
+    pub projected_bytes: i64,
+    /// Day the projection reaches the limit, if it does before the cycle ends.
+    /// `None` when the limit is out of reach or was already reached by `now`.
+    pub cap_hit: Option<NaiveDate>,
+}
+
+/// Fitted daily-usage model: `(intercept + slope * x) * factor[weekday]`,
+/// where `x` is days since `origin`.
+struct Model {
+    origin: NaiveDate,
+    intercept: f64,
+    slope: f64,
+    factors: [f64; 7],
+}
+
+impl Model {
+    fn fit(history: &[(NaiveDate, i64)]) -> Option<Self> {
+        let origin = history.first()?.0;
+        let weekday = |d: &NaiveDate| d.weekday().num_days_from_monday() as usize;
+
+        // Weekday factor: that weekday's mean over the overall mean.
+        let mut factors = [1.0; 7];
+        if history.len() >= MIN_DAYS_FOR_SEASONALITY {
+            let overall =
+                history.iter().map(|(_, v)| *v as f64).sum::<f64>() / history.len() as f64;
+            let mut sums = [0.0; 7];
+            let mut counts = [0usize; 7];
+            for (d, v) in history {
+                sums[weekday(d)] += *v as f64;
+                counts[weekday(d)] += 1;
+            }
+            if overall > 0.0 {
+                for wd in 0..7 {
+                    if counts[wd] > 0 {
+                        factors[wd] = sums[wd] / counts[wd] as f64 / overall;
+                    }
+                }
+            }
+        }
+
+        // Linear trend on the deseasonalised series. Weekdays that never see
+        // traffic carry no trend information.
+        let points: Vec<(f64, f64)> = history
+            .iter()
+            .filter(|(d, _)| factors[weekday(d)] > 0.0)
+            .map(|(d, v)| {
+                let x = (*d - origin).num_days() as f64;
+                (x, *v as f64 / factors[weekday(d)])
+            })
+            .collect();
+        let n = points.len().max(1) as f64;
+        let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+        let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+        let var_x: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+        let slope = if points.len() >= 3 && var_x > 0.0 {
+            points
+                .iter()
+                .map(|(x, y)| (x - mean_x) * (y - mean_y))
+                .sum::<f64>()
+                / var_x
+        } else {
+            0.0
+        };
+
+        Some(Model {
+            origin,
+            intercept: mean_y - slope * mean_x,
+            slope,
+            factors,
+        })
+    }
+
+    fn expected(&self, day: NaiveDate) -> f64 {
+        let x = (day - self.origin).num_days() as f64;
+        let wd = day.weekday().num_days_from_monday() as usize;
+        ((self.intercept + self.slope * x) * self.factors[wd]).max(0.0)
+    }
+}
+
+/// Projects usage to the end of the cycle.
+///
+/// `history` holds complete days before `now` (oldest first, gaps filled with
+/// zero); `used_bytes` is what the cycle has used up to `now`. A cycle that
+/// starts after `now` is projected from its start.
+pub fn project(
+    history: &[(NaiveDate, i64)],
+    used_bytes: i64,
+    limit_bytes: i64,
+    now: NaiveDateTime,
+    cycle_start: NaiveDateTime,
+    cycle_end: NaiveDateTime,
+) -> Forecast {
+    let mut cumulative = used_bytes as f64;
+    let mut cap_hit = None;
+
+    let model = Model::fit(history);
+    let day_len = Duration::days(1).num_seconds() as f64;
+    let start = now.max(cycle_start);
+    let mut day = start.date();
+    while day.and_time(chrono::NaiveTime::MIN) < cycle_end {
+        let day_start = day.and_time(chrono::NaiveTime::MIN);
+        let from = day_start.max(start);
+        let to = (day_start + Duration::days(1)).min(cycle_end);
+        let share = (to - from).num_seconds().max(0) as f64 / day_len;
+
+        let rate = match &model {
+            Some(m) => m.expected(day),
+            // No complete days yet: extrapolate the cycle's own average.
+            None => {
+                let elapsed = (now - cycle_start).num_seconds() as f64 / day_len;
+                if elapsed > 0.0 {
+                    used_bytes as f64 / elapsed
+                } else {
+                    0.0
+                }
+            }
+        };
+        let before = cumulative;
+        cumulative += rate * share;
+        if cap_hit.is_none() && before < limit_bytes as f64 && cumulative >= limit_bytes as f64 {
+            cap_hit = Some(day);
+        }
+        day += Duration::days(1);
+    }
+
+    Forecast {
+        projected_bytes: cumulative.round() as i64,
+        cap_hit,
+    }
+}
+
+/// Forecast for a quota's current cycle, fitted on the last `LOOKBACK_DAYS` days.
+pub async fn forecast_usage(
+    pool: &SqlitePool,
+    usage: &QuotaUsage,
+    now: NaiveDateTime,
+    host: HostFilter,
+) -> Result<Forecast, sqlx::Error> {
+    let today = now.date();
+    let from = today - Duration::days(LOOKBACK_DAYS);
+    let daily = db::quota_daily_usage(
+        pool,
+        &usage.quota,
+        from.and_time(chrono::NaiveTime::MIN),
+        today.and_time(chrono::NaiveTime::MIN),
+        host,
+    )
+    .await?;
+
+    // Fill gaps with zero from the first recorded day; earlier days are unknown.
+    let mut history = Vec::new();
+    if let Some(&(first, _)) = daily.first() {
+        let mut it = daily.iter().peekable();
+        let mut day = first;
+        while day < today {
+            let v = match it.peek() {
+                Some(&&(d, v)) if d == day => {
+                    it.next();
+                    v
+                }
+                _ => 0,
+            };
+            history.push((day, v));
+            day += Duration::days(1);
+        }
+    }
+
+    Ok(project(
+        &history,
+        usage.used_bytes,
+        usage.quota.limit_bytes,
+        now,
+        usage.cycle_start,
+        usage.cycle_end,
+    ))
+}
@@ -0,0 +1,220 @@
+//! InfluxDB line protocol, for `ambr export --format influx` and
+//! `ambr import influx`.
+//!
+//! ```text
+//! ambr_traffic,interface=eth0 rx=1048576i,tx=65536i 1789034400000000000
+//! ```
+//!
+//! Byte counts are integer fields and the timestamp is the sample time (or
+//! period start) in nanoseconds, InfluxDB's default precision. The importer
+//! also takes other precisions and plain numeric fields, so test fixtures can
+//! be written by hand.
+
+use crate::db::{self, ExportRow, PeriodTotal};
+use chrono::{DateTime, Duration, NaiveDateTime, Timelike};
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+use std::str::FromStr;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+pub const MEASUREMENT: &str = "ambr_traffic";
+
+/// Unit of line protocol timestamps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Precision {
+    Nanoseconds,
+    Microseconds,
+    Milliseconds,
+    Seconds,
+}
+
+impl Precision {
+    fn per_second(self) -> i64 {
+        match self {
+            Precision::Nanoseconds => 1_000_000_000,
+            Precision::Microseconds => 1_000_000,
+            Precision::Milliseconds => 1_000,
+            Precision::Seconds => 1,
+        }
+    }
+}
+
+impl FromStr for Precision {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ns" => Ok(Precision::Nanoseconds),
+            "us" | "u" => Ok(Precision::Microseconds),
+            "ms" => Ok(Precision::Milliseconds),
+            "s" => Ok(Precision::Seconds),
+            _ => Err(format!("unknown precision '{}' (ns, us, ms, s)", s)),
+        }
+    }
+}
+
+/// Escapes a tag value (commas, equals signs, spaces and backslashes).
+fn escape_tag(v: &str) -> String {
+    let mut out = String::with_capacity(v.len());
+    for c in v.chars() {
+        if matches!(c, ',' | '=' | ' ' | '\\') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Writes one export row as a line.
+pub fn write_line(out: &mut impl Write, row: &ExportRow) -> Result<(), Error> {
+    let at = db::parse_time(&row.time)?.and_utc();
+    let nanos = at
+        .timestamp_nanos_opt()
+        .ok_or_else(|| format!("{} is out of range for nanosecond timestamps", row.time))?;
+    writeln!(
+        out,
+        "{},interface={} rx={}i,tx={}i {}",
+        MEASUREMENT,
+        escape_tag(&row.interface),
+        row.rx_bytes,
+        row.tx_bytes,
+        nanos
+    )?;
+    Ok(())
+}
+
+/// Splits at unescaped `sep`, keeping escapes in the pieces.
+fn split_unescaped(s: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == sep {
+            parts.push(&s[start..i]);
+            start = i + c.len_utf8();
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => out.extend(chars.next()),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Parses an integer field value: `123i`, `123u` or an integral float.
+fn parse_bytes(v: &str) -> Option<i64> {
+    if let Some(n) = v.strip_suffix('i').or_else(|| v.strip_suffix('u')) {
+        return n.parse().ok();
+    }
+    let f: f64 = v.parse().ok()?;
+    (f.fract() == 0.0 && f >= 0.0).then_some(f as i64)
+}
+
+/// One `ambr_traffic` point.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Point {
+    pub interface: String,
+    pub at: NaiveDateTime,
+    pub rx_bytes: i64,
+    pub tx_bytes: i64,
+}
+
+/// Parses one line. Comments, blank lines and other measurements give
+/// `Ok(None)`.
+pub fn parse_line(line: &str, precision: Precision) -> Result<Option<Point>, String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+    let parts = split_unescaped(line, ' ');
+    let [series, fields, timestamp] = parts[..] else {
+        return Err("expected '<measurement,tags> <fields> <timestamp>'".to_string());
+    };
+    let mut series = split_unescaped(series, ',').into_iter();
+    if series.next().map(unescape).as_deref() != Some(MEASUREMENT) {
+        return Ok(None);
+    }
+
+    let mut interface = None;
+    for tag in series {
+        if let Some((k, v)) = tag.split_once('=')
+            && k == "interface"
+        {
+            interface = Some(unescape(v));
+        }
+    }
+    let interface = interface.ok_or("missing interface tag")?;
+
+    let (mut rx, mut tx) = (None, None);
+    for field in split_unescaped(fields, ',') {
+        let (k, v) = field
+            .split_once('=')
+            .ok_or_else(|| format!("invalid field '{}'", field))?;
+        let slot = match k {
+            "rx" => &mut rx,
+            "tx" => &mut tx,
+            _ => continue,
+        };
+        *slot = Some(parse_bytes(v).ok_or_else(|| format!("invalid byte count '{}'", v))?);
+    }
+    let (Some(rx_bytes), Some(tx_bytes)) = (rx, tx) else {
+        return Err("expected rx and tx fields".to_string());
+    };
+
+    let ts: i64 = timestamp
+        .parse()
+        .map_err(|_| format!("invalid timestamp '{}'", timestamp))?;
+    let per_second = precision.per_second();
+    let at = DateTime::from_timestamp(
+        ts.div_euclid(per_second),
+        (ts.rem_euclid(per_second) * (1_000_000_000 / per_second)) as u32,
+    )
+    .ok_or_else(|| format!("timestamp {} is out of range", ts))?
+    .naive_utc();
+
+    Ok(Some(Point {
+        interface,
+        at,
+        rx_bytes,
+        tx_bytes,
+    }))
+}
+
+/// Reads every `ambr_traffic` point as a one-second period, summing points
+/// that fall in the same second on one interface (ambr stores whole seconds).
+pub fn read(input: impl BufRead, precision: Precision) -> Result<Vec<PeriodTotal>, Error> {
+    let mut seconds: BTreeMap<(String, NaiveDateTime), (i64, i64)> = BTreeMap::new();
+    for (n, line) in input.lines().enumerate() {
+        let point = parse_line(&line?, precision).map_err(|e| format!("line {}: {}", n + 1, e))?;
+        if let Some(p) = point {
+            let at = p.at.with_nanosecond(0).unwrap_or(p.at);
+            let sum = seconds.entry((p.interface, at)).or_default();
+            sum.0 += p.rx_bytes;
+            sum.1 += p.tx_bytes;
+        }
+    }
+    Ok(seconds
+        .into_iter()
+        .map(|((interface, start), (rx_bytes, tx_bytes))| PeriodTotal {
+            interface,
+            start,
+            end: start + Duration::seconds(1),
+            rx_bytes,
+            tx_bytes,
+        })
+        .collect())
+}
@@ -0,0 +1,96 @@
+//! Collector side of `push`: `ambr server` accepts batches on
+//! `POST /ingest` and stores them with a host, see `db::ingest_samples`.
+
+use crate::db;
+use crate::push::{Batch, IngestReply};
+use axum::body::Bytes;
+use axum::extract::{DefaultBodyLimit, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::routing::post;
+use axum::{Json, Router};
+use flate2::read::GzDecoder;
+use sqlx::SqlitePool;
+use std::io::Read;
+
+/// Largest request body, compressed or not.
+const MAX_BODY: usize = 16 * 1024 * 1024;
+
+#[derive(Clone)]
+struct Collector {
+    pool: SqlitePool,
+    token: Option<String>,
+}
+
+/// Serves `POST /ingest` on `listener` until the task is dropped. With a
+/// `token`, requests must carry it as a bearer token.
+pub async fn serve(
+    listener: tokio::net::TcpListener,
+    pool: SqlitePool,
+    token: Option<String>,
+) -> std::io::Result<()> {
+    axum::serve(listener, router(pool, token)).await
+}
+
+pub fn router(pool: SqlitePool, token: Option<String>) -> Router {
+    Router::new()
+        .route("/ingest", post(ingest))
+        .layer(DefaultBodyLimit::max(MAX_BODY))
+        .with_state(Collector { pool, token })
+}
+
+type Rejection = (StatusCode, String);
+
+fn decode(headers: &HeaderMap, body: &[u8]) -> Result<Batch, Rejection> {
+    let gzip = headers
+        .get(header::CONTENT_ENCODING)
+        .is_some_and(|v| v.as_bytes().eq_ignore_ascii_case(b"gzip"));
+    let bad = |e: &dyn std::fmt::Display| (StatusCode::BAD_REQUEST, e.to_string());
+    if gzip {
+        let mut json = Vec::new();
+        GzDecoder::new(body)
+            .take(MAX_BODY as u64 * 8)
+            .read_to_end(&mut json)
+            .map_err(|e| bad(&e))?;
+        serde_json::from_slice(&json).map_err(|e| bad(&e))
+    } else {
+        serde_json::from_slice(body).map_err(|e| bad(&e))
+    }
+}
+
+async fn ingest(
+    State(collector): State<Collector>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<IngestReply>, Rejection> {
+    if let Some(token) = &collector.token {
+        let expected = format!("Bearer {}", token);
+        if headers.get(header::AUTHORIZATION).map(|v| v.as_bytes()) != Some(expected.as_bytes()) {
+            return Err((StatusCode::UNAUTHORIZED, "invalid token".to_string()));
+        }
+    }
+    let batch = decode(&headers, &body)?;
+    if batch.host.machine_id.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "missing machine_id".to_string()));
+    }
+    let summary = db::ingest_samples(
+        &collector.pool,
+        &batch.host.hostname,
+        &batch.host.machine_id,
+        batch.database_id.as_deref(),
+        &batch.host.labels,
+        &batch.samples,
+    )
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if summary.restarted {
+        eprintln!(
+            "host {} ({}): sequence numbers started over (new database, or another \
+             machine with the same machine id); accepting them as new samples",
+            batch.host.hostname, batch.host.machine_id
+        );
+    }
+    Ok(Json(IngestReply {
+        stored: summary.stored,
+        skipped: summary.skipped,
+    }))
+}
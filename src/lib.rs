@@ -1,2 +1,22 @@
+pub mod alert;
+pub mod api;
+pub mod compare;
+pub mod config;
+#[cfg(unix)]
+pub mod control;
 pub mod db;
+pub mod enforce;
+pub mod enrich;
+pub mod export;
+pub mod forecast;
+pub mod influx;
+pub mod ingest;
+pub mod metrics;
+pub mod mqtt;
+pub mod profile;
+pub mod push;
+pub mod quota;
 pub mod recorder;
+pub mod report;
+pub mod tariff;
+pub mod vnstat;
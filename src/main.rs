@@ -1,6 +1,15 @@
-use ambr::{db, recorder};
+#[cfg(unix)]
+use ambr::control;
+use ambr::{
+    alert, api, compare, config, db, enforce, export, forecast, influx, ingest, metrics, mqtt,
+    profile, push, quota, recorder, report, tariff, vnstat,
+};
+use chrono::{Datelike, Months, NaiveDate, NaiveDateTime, Utc};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use crossterm::{
-    event::{self, Event, KeyCode, KeyEventKind},
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind, MouseEventKind,
+    },
     execute,
     terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
 };
@@ -8,10 +17,15 @@ use ratatui::{
     prelude::*,
     style::{Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, Cell, Paragraph, Row, Table, Tabs},
+    widgets::{
+        Bar, BarChart, BarGroup, Block, Borders, Cell, Gauge, HighlightSpacing, Paragraph, Row,
+        Scrollbar, ScrollbarOrientation, ScrollbarState, Table, TableState, Tabs,
+    },
 };
+use std::collections::{BTreeMap, HashMap};
 use std::io;
 use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
 // Networking-style palette: dark base, cyan (in/rx), green (out/tx), amber (total)
@@ -27,6 +41,284 @@ mod theme {
     pub const HEADER: Color = Color::Rgb(0xe6, 0xed, 0xf3);
     pub const ROW_ALT: Color = Color::Rgb(0x16, 0x1b, 0x22);
     pub const HINT: Color = Color::Rgb(0x8b, 0x94, 0x9f);
+    pub const OVER: Color = Color::Rgb(0xff, 0x55, 0x55); // quota exceeded
+    pub const COST: Color = Color::Rgb(0xd2, 0xa8, 0xff); // tariff cost
+
+    /// Step `level` of `levels` on the amber scale from the background to
+    /// `TOTAL`, for heatmap cells.
+    pub fn total_shade(level: usize, levels: usize) -> Color {
+        let (Color::Rgb(r0, g0, b0), Color::Rgb(r1, g1, b1)) = (BG, TOTAL) else {
+            return TOTAL;
+        };
+        let t = level.min(levels) as f32 / levels.max(1) as f32;
+        let mix = |from: u8, to: u8| (from as f32 + (to as f32 - from as f32) * t).round() as u8;
+        Color::Rgb(mix(r0, r1), mix(g0, g1), mix(b0, b1))
+    }
+}
+
+#[derive(Parser)]
+#[command(
+    name = "ambr",
+    version,
+    about = "Terminal UI for monitoring network traffic"
+)]
+struct Cli {
+    /// Config file (default: config.toml in the user config directory)
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// Daemon control socket (default: $XDG_RUNTIME_DIR/ambr.sock)
+    #[arg(long, global = true)]
+    socket: Option<PathBuf>,
+    /// Database file (default: ambr.db in the user data directory)
+    #[arg(long, global = true)]
+    db: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Manage data cap quotas
+    #[command(subcommand)]
+    Quota(QuotaCommand),
+    /// Print usage for today, yesterday, this and last month, and quota status
+    Report(ReportArgs),
+    /// Record in the background without the TUI, evaluating alert rules
+    Daemon(DaemonArgs),
+    /// Collect samples pushed by other machines' daemons
+    Server(ServerArgs),
+    /// Apply or lift a quota enforcement limit by hand
+    #[command(subcommand)]
+    Enforce(EnforceCommand),
+    /// Write recorded usage as CSV, JSON, NDJSON or InfluxDB line protocol
+    Export(ExportArgs),
+    /// Import history recorded by other tools
+    #[command(subcommand)]
+    Import(ImportCommand),
+    /// Send a command to a running daemon
+    #[cfg(unix)]
+    #[command(subcommand)]
+    Ctl(CtlCommand),
+}
+
+#[derive(Args)]
+struct DaemonArgs {
+    /// Sampling interval in seconds
+    #[arg(long, default_value_t = 10)]
+    interval: u64,
+    /// Serve Prometheus metrics on this address, e.g. 127.0.0.1:9191
+    #[arg(long, value_name = "ADDR")]
+    metrics: Option<std::net::SocketAddr>,
+    /// Serve the JSON API (default address 127.0.0.1:9192)
+    #[arg(long, value_name = "ADDR", num_args = 0..=1, default_missing_value = "127.0.0.1:9192")]
+    api: Option<std::net::SocketAddr>,
+    /// Do not listen on the control socket
+    #[arg(long)]
+    no_socket: bool,
+}
+
+#[derive(Args)]
+struct ReportArgs {
+    /// Print JSON instead of a table
+    #[arg(long, conflicts_with = "html")]
+    json: bool,
+    /// Only this interface
+    #[arg(short, long, conflicts_with = "html")]
+    interface: Option<String>,
+    /// Exit with status 2 when a quota is used up
+    #[arg(long, conflicts_with = "html")]
+    check: bool,
+    /// Write a self-contained HTML report for one month to this file instead
+    #[arg(long, value_name = "FILE")]
+    html: Option<PathBuf>,
+    /// Month to report on, YYYY-MM (default: the current month)
+    #[arg(long, value_name = "YYYY-MM", requires = "html", value_parser = report::parse_month)]
+    period: Option<NaiveDate>,
+}
+
+#[derive(Args)]
+struct ServerArgs {
+    /// Address to accept pushes on; other than loopback only with --token
+    #[arg(long, default_value = "127.0.0.1:9193")]
+    listen: std::net::SocketAddr,
+    /// Require this bearer token
+    #[arg(long)]
+    token: Option<String>,
+}
+
+#[derive(Args)]
+struct ExportArgs {
+    /// csv, json, ndjson or influx
+    #[arg(long, default_value = "csv")]
+    format: export::Format,
+    /// Totals per period, or raw for the individual samples
+    #[arg(long, value_enum, default_value_t = ExportGranularity::Raw)]
+    granularity: ExportGranularity,
+    /// Start (inclusive): YYYY-MM-DD or YYYY-MM-DDTHH:MM[:SS], UTC
+    #[arg(long, value_parser = db::parse_time)]
+    from: Option<chrono::NaiveDateTime>,
+    /// End (exclusive)
+    #[arg(long, value_parser = db::parse_time)]
+    to: Option<chrono::NaiveDateTime>,
+    /// Only this interface
+    #[arg(long)]
+    interface: Option<String>,
+    /// Only this pushing host (name or machine id), or "local"
+    #[arg(long)]
+    host: Option<String>,
+    /// Write to a file instead of stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportGranularity {
+    Raw,
+    Minute,
+    Hour,
+    Day,
+    Week,
+    Month,
+}
+
+impl ExportGranularity {
+    /// The period to total over; `None` for raw samples.
+    fn period(self) -> Option<db::Granularity> {
+        match self {
+            ExportGranularity::Raw => None,
+            ExportGranularity::Minute => Some(db::Granularity::Minute),
+            ExportGranularity::Hour => Some(db::Granularity::Hour),
+            ExportGranularity::Day => Some(db::Granularity::Day),
+            ExportGranularity::Week => Some(db::Granularity::Week),
+            ExportGranularity::Month => Some(db::Granularity::Month),
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum ImportCommand {
+    /// vnStat 2.x database or `vnstat --json` output
+    Vnstat {
+        /// vnstat.db, a JSON file, or - for JSON on stdin
+        #[arg(default_value = vnstat::DEFAULT_DB_PATH)]
+        path: PathBuf,
+        /// vnStat ran with UseUTC 1 (dates are otherwise local time)
+        #[arg(long)]
+        utc: bool,
+        /// Only this interface
+        #[arg(long)]
+        interface: Option<String>,
+    },
+    /// InfluxDB line protocol (ambr_traffic points, as written by export)
+    Influx {
+        /// File, or - for stdin
+        path: PathBuf,
+        /// Timestamp unit: ns, us, ms or s
+        #[arg(long, default_value = "ns")]
+        precision: influx::Precision,
+        /// Only this interface
+        #[arg(long)]
+        interface: Option<String>,
+    },
+}
+
+#[cfg(unix)]
+#[derive(Subcommand)]
+enum CtlCommand {
+    /// Print the rates from the last sampling round
+    Live,
+    /// Print rates after every sampling round
+    Watch,
+    /// Stop storing samples
+    Pause,
+    /// Resume storing samples
+    Resume,
+    /// Re-read the config file and replace the alert rules
+    Reload,
+    /// Merge old raw samples into hourly rows
+    Rollup {
+        /// Only samples older than this many days
+        #[arg(long, default_value_t = control::DEFAULT_ROLLUP_DAYS)]
+        older_than_days: u32,
+    },
+    /// Print recorder status
+    Status,
+}
+
+#[derive(Subcommand)]
+enum EnforceCommand {
+    /// Apply the limit
+    Apply(EnforceArgs),
+    /// Remove the limit
+    Remove(EnforceArgs),
+}
+
+#[derive(Args)]
+struct EnforceArgs {
+    /// Interface to limit
+    #[arg(long, short = 'i')]
+    interface: String,
+    /// Rate limit with tc, e.g. 256kbit
+    #[arg(
+        long,
+        conflicts_with = "nftables",
+        required_unless_present = "nftables"
+    )]
+    rate: Option<String>,
+    /// Drop all traffic with nftables instead of rate limiting
+    #[arg(long)]
+    nftables: bool,
+    /// Print the commands instead of running them
+    #[arg(long)]
+    dry_run: bool,
+}
+
+impl EnforceArgs {
+    fn into_enforcement(self) -> enforce::Enforcement {
+        let method = match self.rate {
+            Some(rate) => enforce::Method::Tc { rate },
+            None => enforce::Method::Nftables,
+        };
+        enforce::Enforcement {
+            interface: self.interface,
+            method,
+            dry_run: self.dry_run,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum QuotaCommand {
+    /// Add a quota
+    Add {
+        name: String,
+        /// Cap for one cycle, e.g. 50GiB or 100GB
+        #[arg(long, value_parser = quota::parse_size)]
+        limit: i64,
+        /// Interface counted against the quota (repeatable; default: all)
+        #[arg(long = "interface", short = 'i')]
+        interfaces: Vec<String>,
+        /// rx, tx or both
+        #[arg(long, default_value = "both")]
+        direction: quota::Direction,
+        /// Day of the month the cycle resets on
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=31))]
+        start_day: u32,
+        /// Start of a one-off period (YYYY-MM-DD) instead of a monthly cycle
+        #[arg(long, requires = "to", conflicts_with = "start_day")]
+        from: Option<NaiveDate>,
+        /// End of the one-off period (exclusive)
+        #[arg(long, requires = "from")]
+        to: Option<NaiveDate>,
+        /// Window whose traffic does not count, e.g. "00:00-06:00" or
+        /// "Sat,Sun 00:00-24:00" (repeatable; UTC)
+        #[arg(long = "free")]
+        free_windows: Vec<tariff::TimeWindow>,
+    },
+    /// List quotas with their current-cycle usage
+    List,
+    /// Remove a quota
+    Remove { name: String },
 }
 
 fn default_db_path() -> Result<PathBuf, Box<dyn std::error::Error + Send + Sync>> {
@@ -40,213 +332,1466 @@ fn default_db_path() -> Result<PathBuf, Box<dyn std::error::Error + Send + Sync>
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let db_path = default_db_path()?;
+    let cli = Cli::parse();
+
+    let db_path = match cli.db {
+        Some(path) => path,
+        None => default_db_path()?,
+    };
     let options = sqlx::sqlite::SqliteConnectOptions::new()
         .filename(&db_path)
         .create_if_missing(true);
     let pool = sqlx::SqlitePool::connect_with(options).await?;
     db::init_db(&pool).await?;
 
-    // Run recorder in background and write to db evry 10s
-    let pool_rec = pool.clone();
-    tokio::spawn(async move {
-        let _ = recorder::run_recorder(pool_rec, 10).await;
-    });
+    let config_path = cli.config.or_else(config::default_path);
+    let config = match &config_path {
+        Some(path) => config::Config::load(path)?,
+        None => config::Config::default(),
+    };
 
-    // Run TUI in a separate thread
-    let pool_tui = pool.clone();
-    let result = tokio::task::spawn_blocking(move || {
-        let rt = tokio::runtime::Runtime::new().expect("runtime");
-        run_tui(&pool_tui, rt.handle().clone())
-    })
-    .await;
+    #[cfg(unix)]
+    let socket_path = cli.socket.or_else(control::default_socket_path);
+    #[cfg(not(unix))]
+    let socket_path: Option<PathBuf> = None;
 
-    result.expect("TUI thread panicked")?;
+    match cli.command {
+        None => run_interactive(pool, config, socket_path).await,
+        Some(Command::Daemon(args)) => {
+            let socket_path = socket_path.filter(|_| !args.no_socket);
+            run_daemon(pool, config, config_path, socket_path, args).await
+        }
+        #[cfg(unix)]
+        Some(Command::Ctl(cmd)) => {
+            ctl_command(socket_path.ok_or("no control socket path")?, cmd).await
+        }
+        Some(Command::Server(args)) => {
+            if args.token.is_none() && !args.listen.ip().is_loopback() {
+                return Err(format!(
+                    "refusing to accept unauthenticated pushes on {}: set --token or listen on a loopback address",
+                    args.listen
+                )
+                .into());
+            }
+            let listener = tokio::net::TcpListener::bind(args.listen).await?;
+            println!("Accepting pushes on http://{}/ingest", args.listen);
+            tokio::select! {
+                res = ingest::serve(listener, pool, args.token) => res?,
+                _ = tokio::signal::ctrl_c() => {}
+            }
+            Ok(())
+        }
+        Some(Command::Quota(cmd)) => quota_command(&pool, cmd).await,
+        Some(Command::Report(args)) => report_command(&pool, args).await,
+        Some(Command::Enforce(EnforceCommand::Apply(args))) => {
+            args.into_enforcement().apply().await
+        }
+        Some(Command::Enforce(EnforceCommand::Remove(args))) => {
+            args.into_enforcement().remove().await
+        }
+        Some(Command::Export(args)) => export_command(&pool, args).await,
+        Some(Command::Import(cmd)) => import_command(&pool, cmd).await,
+    }
+}
 
-    Ok(())
+fn fmt_bytes(bytes: i64) -> String {
+    humansize::format_size(bytes.max(0) as u64, humansize::BINARY)
 }
 
-struct App {
-    tab: usize,
-    hourly: Vec<db::PeriodRow>,
-    daily: Vec<db::PeriodRow>,
-    weekly: Vec<db::PeriodRow>,
-    monthly: Vec<db::PeriodRow>,
-    // Live tab: last 1 min and 5 min totals (rx_mib, tx_mib, total_mib)
-    live_1min: (f64, f64, f64),
-    live_5min: (f64, f64, f64),
-    live_by_interface: Vec<db::LiveInterfaceRow>,
+fn fmt_rate(bytes_per_sec: f64) -> String {
+    format!("{}/s", fmt_bytes(bytes_per_sec.round() as i64))
 }
 
-impl App {
-    fn new() -> Self {
-        Self {
-            tab: 0,
-            hourly: Vec::new(),
-            daily: Vec::new(),
-            weekly: Vec::new(),
-            monthly: Vec::new(),
-            live_1min: (0.0, 0.0, 0.0),
-            live_5min: (0.0, 0.0, 0.0),
-            live_by_interface: Vec::new(),
-        }
+fn describe_cycle(cycle: &quota::Cycle) -> String {
+    match cycle {
+        quota::Cycle::Monthly { start_day } => format!("monthly from day {}", start_day),
+        quota::Cycle::Custom { from, to } => format!("{} → {}", from.date(), to.date()),
     }
+}
 
-    fn refresh_history(&mut self, pool: &sqlx::SqlitePool, rt: &tokio::runtime::Handle) {
-        let p = pool.clone();
-        if let Ok(v) = rt.block_on(async move { db::usage_by_hour(&p, 24).await }) {
-            self.hourly = v;
-        }
-        let p = pool.clone();
-        if let Ok(v) = rt.block_on(async move { db::usage_by_day(&p, 31).await }) {
-            self.daily = v;
+fn describe_free_windows(windows: &[tariff::TimeWindow]) -> String {
+    windows
+        .iter()
+        .map(|w| w.to_string())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+async fn quota_command(
+    pool: &sqlx::SqlitePool,
+    cmd: QuotaCommand,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    match cmd {
+        QuotaCommand::Add {
+            name,
+            limit,
+            interfaces,
+            direction,
+            start_day,
+            from,
+            to,
+            free_windows,
+        } => {
+            let cycle = match (from, to) {
+                (Some(from), Some(to)) => quota::Cycle::Custom {
+                    from: from.and_time(chrono::NaiveTime::MIN),
+                    to: to.and_time(chrono::NaiveTime::MIN),
+                },
+                _ => quota::Cycle::Monthly { start_day },
+            };
+            let q = quota::Quota {
+                name,
+                limit_bytes: limit,
+                interfaces,
+                direction,
+                cycle,
+                free_windows,
+            };
+            db::add_quota(pool, &q).await?;
+            println!("Added quota '{}' ({})", q.name, fmt_bytes(q.limit_bytes));
         }
-        let p = pool.clone();
-        if let Ok(v) = rt.block_on(async move { db::usage_by_week(&p, 12).await }) {
-            self.weekly = v;
+        QuotaCommand::List => {
+            let usages =
+                db::quota_usages(pool, Utc::now().naive_utc(), db::HostFilter::Local).await?;
+            if usages.is_empty() {
+                println!("No quotas defined.");
+            }
+            for u in usages {
+                let ifaces = if u.quota.interfaces.is_empty() {
+                    "all".to_string()
+                } else {
+                    u.quota.interfaces.join(",")
+                };
+                println!(
+                    "{:<16} {:>11} / {:<11} {:>6.1}%  {:<4}  {:<16}  {}",
+                    u.quota.name,
+                    fmt_bytes(u.used_bytes),
+                    fmt_bytes(u.quota.limit_bytes),
+                    u.percent(),
+                    u.quota.direction,
+                    ifaces,
+                    describe_cycle(&u.quota.cycle),
+                );
+                if !u.quota.free_windows.is_empty() {
+                    println!(
+                        "{:<16} free: {} ({} not counted)",
+                        "",
+                        describe_free_windows(&u.quota.free_windows),
+                        fmt_bytes(u.free_bytes)
+                    );
+                }
+            }
         }
-        let p = pool.clone();
-        if let Ok(v) = rt.block_on(async move { db::usage_by_month(&p, 12).await }) {
-            self.monthly = v;
+        QuotaCommand::Remove { name } => {
+            if !db::delete_quota(pool, &name).await? {
+                return Err(format!("no quota named '{}'", name).into());
+            }
+            println!("Removed quota '{}'", name);
         }
     }
+    Ok(())
+}
+
+async fn report_command(
+    pool: &sqlx::SqlitePool,
+    args: ReportArgs,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let now = Utc::now().naive_utc();
+    if let Some(path) = args.html {
+        let month = args.period.unwrap_or(now.date());
+        let report = report::month_report(pool, month, now).await?;
+        std::fs::write(&path, report::render_html(&report))?;
+        println!(
+            "Wrote {} report to {}",
+            report.month.format("%Y-%m"),
+            path.display()
+        );
+        return Ok(());
+    }
+    let summary = report::summary(pool, now, args.interface.as_deref()).await?;
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&summary)?);
+    } else {
+        print_summary(&summary);
+    }
+    if args.check && summary.breached() {
+        io::Write::flush(&mut io::stdout())?;
+        std::process::exit(2);
+    }
+    Ok(())
+}
 
-    fn refresh_live(&mut self, pool: &sqlx::SqlitePool, rt: &tokio::runtime::Handle) {
-        let p = pool.clone();
-        if let Ok((rx, tx, total)) = rt.block_on(async move { db::recent_totals(&p, 1).await }) {
-            self.live_1min = (rx, tx, total);
+fn print_summary(summary: &report::Summary) {
+    if summary.interfaces.is_empty() {
+        println!("No traffic recorded.");
+    }
+    for (i, iface) in summary.interfaces.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!(
+            "{:<14} {:>12} {:>12} {:>12}",
+            iface.interface, "rx", "tx", "total"
+        );
+        for (label, t) in iface.periods() {
+            println!(
+                "  {:<12} {:>12} {:>12} {:>12}",
+                label,
+                fmt_bytes(t.rx_bytes),
+                fmt_bytes(t.tx_bytes),
+                fmt_bytes(t.total_bytes)
+            );
         }
-        let p = pool.clone();
-        if let Ok((rx, tx, total)) = rt.block_on(async move { db::recent_totals(&p, 5).await }) {
-            self.live_5min = (rx, tx, total);
+    }
+    if !summary.quotas.is_empty() {
+        println!();
+    }
+    for q in &summary.quotas {
+        let cap = match q.cap_hit {
+            _ if q.exceeded => "over cap".to_string(),
+            Some(day) => format!("cap hit {}", day),
+            None => "within cap".to_string(),
+        };
+        println!(
+            "{:<16} {:>11} / {:<11} {:>6.1}%  projected {:>11} by {}  {}",
+            q.name,
+            fmt_bytes(q.used_bytes),
+            fmt_bytes(q.limit_bytes),
+            q.percent,
+            fmt_bytes(q.projected_bytes),
+            q.cycle_end.date(),
+            cap,
+        );
+    }
+}
+
+async fn export_command(
+    pool: &sqlx::SqlitePool,
+    args: ExportArgs,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let host = match &args.host {
+        Some(name) => db::resolve_host(pool, name)
+            .await?
+            .ok_or_else(|| format!("unknown host '{}'", name))?,
+        None => db::HostFilter::All,
+    };
+    let opts = export::ExportOptions {
+        format: args.format,
+        granularity: args.granularity.period(),
+        filter: db::UsageFilter {
+            interface: args.interface,
+            host,
+        },
+        from: args.from,
+        to: args.to,
+    };
+    match args.output {
+        Some(path) => {
+            let file = std::fs::File::create(&path)?;
+            export::export(pool, &opts, io::BufWriter::new(file)).await?;
         }
-        let p = pool.clone();
-        if let Ok(v) = rt.block_on(async move { db::recent_by_interface(&p, 1).await }) {
-            self.live_by_interface = v;
+        None => {
+            export::export(pool, &opts, io::BufWriter::new(io::stdout().lock())).await?;
         }
     }
+    Ok(())
 }
 
-fn run_tui(
+async fn import_command(
     pool: &sqlx::SqlitePool,
-    rt: tokio::runtime::Handle,
+    cmd: ImportCommand,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen)?;
-    enable_raw_mode()?;
-    let mut terminal = Terminal::new(ratatui::backend::CrosstermBackend::new(stdout))?;
+    let (mut periods, interface, source) = match cmd {
+        ImportCommand::Vnstat {
+            path,
+            utc,
+            interface,
+        } => {
+            let clock = if utc {
+                vnstat::Clock::Utc
+            } else {
+                vnstat::Clock::Local
+            };
+            (
+                vnstat::load(&path, clock).await?,
+                interface,
+                "vnStat entries",
+            )
+        }
+        ImportCommand::Influx {
+            path,
+            precision,
+            interface,
+        } => {
+            let periods = if path == std::path::Path::new("-") {
+                influx::read(io::stdin().lock(), precision)?
+            } else {
+                let file = std::fs::File::open(&path)?;
+                influx::read(io::BufReader::new(file), precision)?
+            };
+            (periods, interface, "samples")
+        }
+    };
+    if let Some(name) = &interface {
+        periods.retain(|p| &p.interface == name);
+    }
+    let summary = db::import_periods(pool, &periods).await?;
+    println!(
+        "Imported {} rows ({} rx, {} tx) from {} {}",
+        summary.rows,
+        fmt_bytes(summary.rx_bytes),
+        fmt_bytes(summary.tx_bytes),
+        summary.periods,
+        source
+    );
+    Ok(())
+}
 
-    const LIVE_TAB_REFRESH: Duration = Duration::from_secs(1); // real-time when on Live tab
-    const LIVE_BACKGROUND_REFRESH: Duration = Duration::from_secs(2); // when on other tabs
+fn describe_cap_hit(u: &quota::QuotaUsage, f: &forecast::Forecast) -> String {
+    match f.cap_hit {
+        _ if u.used_bytes >= u.quota.limit_bytes => "over cap".to_string(),
+        Some(day) => format!("cap hit {}", day),
+        None => "within cap".to_string(),
+    }
+}
 
-    let mut app = App::new();
-    app.refresh_history(pool, &rt);
-    app.refresh_live(pool, &rt);
-    let mut last_live_refresh = Instant::now();
+async fn run_daemon(
+    pool: sqlx::SqlitePool,
+    config: config::Config,
+    config_path: Option<PathBuf>,
+    socket_path: Option<PathBuf>,
+    args: DaemonArgs,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut recorder = recorder::Recorder::new(pool.clone(), args.interval)
+        .with_alerts(alert::AlertEngine::new(config.alerts));
+    if let Some(push_config) = config.push {
+        recorder = recorder.with_outbox();
+        tokio::spawn(push::Pusher::new(pool.clone(), push_config).run());
+    }
+    if let Some(mqtt_config) = config.mqtt {
+        let publisher = mqtt::Publisher::new(pool.clone(), recorder.state(), mqtt_config)?;
+        tokio::spawn(async move {
+            match publisher.run().await {
+                Ok(()) => eprintln!("mqtt publisher stopped"),
+                Err(e) => eprintln!("mqtt publisher stopped: {}", e),
+            }
+        });
+    }
+    if let Some(addr) = args.metrics {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        tokio::spawn(metrics::serve(listener, recorder.state(), pool.clone()));
+    }
+    if let Some(addr) = args.api {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        tokio::spawn(api::serve(listener, pool.clone()));
+    }
+    #[cfg(unix)]
+    if let Some(path) = &socket_path {
+        let listener = control::ControlServer::bind(path).await?;
+        let mut server = control::ControlServer::new(recorder.handle(), pool);
+        if let Some(config_path) = config_path {
+            server = server.with_config_path(config_path);
+        }
+        tokio::spawn(server.serve(listener));
+    }
+    #[cfg(not(unix))]
+    let _ = (config_path, pool);
 
-    loop {
-        terminal.draw(|f| ui(f, &app))?;
+    let res = tokio::select! {
+        res = recorder.run() => res,
+        _ = tokio::signal::ctrl_c() => Ok(()),
+    };
+    if let Some(path) = &socket_path {
+        let _ = std::fs::remove_file(path);
+    }
+    res
+}
 
-        // Live section (including by-interface): every 1s on Live tab, every 2s otherwise
-        let live_interval = if app.tab == 0 {
-            LIVE_TAB_REFRESH
-        } else {
-            LIVE_BACKGROUND_REFRESH
-        };
-        if last_live_refresh.elapsed() >= live_interval {
-            app.refresh_live(pool, &rt);
-            last_live_refresh = Instant::now();
-        }
+#[cfg(unix)]
+async fn ctl_command(
+    socket_path: PathBuf,
+    cmd: CtlCommand,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    use control::Request;
 
-        if event::poll(Duration::from_millis(200))?
-            && let Event::Key(key) = event::read()?
-        {
-            if key.kind != KeyEventKind::Press {
-                continue;
+    let mut client = control::Client::connect(&socket_path).await.map_err(|e| {
+        format!(
+            "{}: {} (is `ambr daemon` running?)",
+            socket_path.display(),
+            e
+        )
+    })?;
+    let request = match cmd {
+        CtlCommand::Watch => {
+            let mut sub = client.subscribe().await?;
+            while let Some(rates) = sub.next().await? {
+                println!("{}", serde_json::to_string(&rates)?);
             }
-            match key.code {
-                KeyCode::Char('q') | KeyCode::Esc => break,
-                KeyCode::Right | KeyCode::Tab => {
-                    app.tab = (app.tab + 1) % 5;
-                    app.refresh_history(pool, &rt);
-                }
-                KeyCode::Left => {
-                    app.tab = app.tab.checked_sub(1).unwrap_or(4);
-                    app.refresh_history(pool, &rt);
+            return Ok(());
+        }
+        CtlCommand::Live => Request::Live,
+        CtlCommand::Pause => Request::Pause,
+        CtlCommand::Resume => Request::Resume,
+        CtlCommand::Reload => Request::Reload,
+        CtlCommand::Rollup { older_than_days } => Request::Rollup { older_than_days },
+        CtlCommand::Status => Request::Status,
+    };
+    let response = client.request(&request).await?;
+    println!("{}", serde_json::to_string(&response)?);
+    Ok(())
+}
+
+/// Latest rates for the Live tab, from the daemon or the in-process recorder.
+type RateFeed = Arc<Mutex<Option<recorder::LiveRates>>>;
+
+async fn run_interactive(
+    pool: sqlx::SqlitePool,
+    config: config::Config,
+    socket_path: Option<PathBuf>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let feed = RateFeed::default();
+
+    // With a daemon running, it does the recording; only follow its rates.
+    let mut from_daemon = false;
+    #[cfg(unix)]
+    if let Some(path) = &socket_path
+        && let Ok(client) = control::Client::connect(path).await
+    {
+        from_daemon = true;
+        let feed = feed.clone();
+        tokio::spawn(async move {
+            if let Ok(mut sub) = client.subscribe().await {
+                while let Ok(Some(rates)) = sub.next().await {
+                    *feed.lock().unwrap() = Some(rates);
                 }
-                KeyCode::Down => {}
-                KeyCode::Up => {}
-                _ => {}
             }
-        }
+        });
     }
+    #[cfg(not(unix))]
+    let _ = socket_path;
+
+    if !from_daemon {
+        // Run recorder in background and write to db evry 10s. Enforcement
+        // is left to `ambr daemon`: it needs root, and its output would end
+        // up on the TUI's screen. Without it, these rules must not record
+        // themselves as active, or the daemon would never apply the limit.
+        let rules = config
+            .alerts
+            .iter()
+            .cloned()
+            .map(|mut rule| {
+                rule.actions
+                    .retain(|a| !matches!(a, alert::Action::Enforce(_)));
+                rule
+            })
+            .collect();
+        let recorder = recorder::Recorder::new(pool.clone(), 10)
+            .with_alerts(alert::AlertEngine::new(rules).in_memory())
+            .quiet();
+        let handle = recorder.handle();
+        tokio::spawn(async move {
+            let _ = recorder.run().await;
+        });
+        let feed = feed.clone();
+        tokio::spawn(async move {
+            let mut rounds = handle.rounds();
+            while rounds.changed().await.is_ok() {
+                *feed.lock().unwrap() = Some(recorder::LiveRates::from_state(&handle.snapshot()));
+            }
+        });
+    }
+
+    // Run TUI in a separate thread
+    let pool_tui = pool.clone();
+    let tariffs = config.tariffs;
+    let rt = tokio::runtime::Handle::current();
+    let result =
+        tokio::task::spawn_blocking(move || run_tui(&pool_tui, rt, tariffs, feed, from_daemon))
+            .await;
+
+    result.expect("TUI thread panicked")?;
 
-    disable_raw_mode()?;
-    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
-    terminal.show_cursor()?;
     Ok(())
 }
 
-fn ui(frame: &mut Frame, app: &App) {
-    // Full area background
-    frame.render_widget(
-        Paragraph::new("").style(Style::default().bg(theme::BG)),
-        frame.area(),
-    );
+struct App {
+    tab: usize,
+    hourly: Vec<db::PeriodRow>,
+    daily: Vec<db::PeriodRow>,
+    weekly: Vec<db::PeriodRow>,
+    monthly: Vec<db::PeriodRow>,
+    // Live tab: last 1 min and 5 min totals (rx_mib, tx_mib, total_mib)
+    live_1min: (f64, f64, f64),
+    live_5min: (f64, f64, f64),
+    live_by_interface: Vec<db::LiveInterfaceRow>,
+    rates: Option<recorder::LiveRates>,
+    rate_feed: RateFeed,
+    rates_from_daemon: bool,
+    // Hosts that push to this database; empty on a single-machine database
+    hosts: Vec<db::Host>,
+    host: db::HostFilter,
+    quotas: Vec<(quota::QuotaUsage, forecast::Forecast)>,
+    // Cost per period label, one map per tariff
+    tariffs: Vec<tariff::Tariff>,
+    hourly_costs: Vec<HashMap<String, f64>>,
+    daily_costs: Vec<HashMap<String, f64>>,
+    weekly_costs: Vec<HashMap<String, f64>>,
+    monthly_costs: Vec<HashMap<String, f64>>,
+    // Custom tab: periods of the picked range, oldest first
+    range: RangePicker,
+    custom: Vec<db::PeriodRow>,
+    custom_costs: Vec<HashMap<String, f64>>,
+    // Compare tab: one comparison per baseline
+    comparisons: Vec<(compare::Baseline, compare::Comparison)>,
+    // Calendar tab: daily totals of the last 12 months and the selected day
+    calendar: BTreeMap<NaiveDate, db::PeriodRow>,
+    calendar_cursor: NaiveDate,
+    // Profile tab: averages over the last PROFILE_WINDOWS[profile_window] weeks
+    profile: Option<profile::Profile>,
+    profile_window: usize,
+    // Selection and scroll position per tab
+    views: [TableView; TAB_TITLES.len()],
+    // Drill-down from a history tab, outermost period first
+    drill: Vec<DrillLevel>,
+    drill_loading: Option<DrillRequest>,
+    // Background queries per tab, and of the host list
+    cache: [CacheSlot<QueryKey>; TAB_TITLES.len()],
+    hosts_cache: CacheSlot<()>,
+    started: Instant,
+}
 
-    let chunks = Layout::default()
-        .direction(Direction::Vertical)
-        .constraints([
-            Constraint::Length(3),
-            Constraint::Min(0),
-            Constraint::Length(1),
-        ])
-        .split(frame.area());
+/// One period of a history tab broken into its finer periods, per interface.
+struct DrillLevel {
+    // Label of the period drilled into, for the breadcrumb
+    label: String,
+    granularity: db::Granularity,
+    rows: Vec<db::InterfacePeriodRow>,
+    view: TableView,
+}
 
-    let titles = [" Live ", " Hourly ", " Daily ", " Weekly ", " Monthly "];
-    let tab_block = Block::default()
-        .borders(Borders::ALL)
-        .border_style(Style::default().fg(theme::BORDER))
-        .title(Span::styled(
-            " ambr ",
-            Style::default()
-                .fg(theme::TITLE)
-                .add_modifier(Modifier::BOLD),
-        ));
-    let tabs = Tabs::new(titles)
-        .block(tab_block)
-        .style(Style::default().fg(theme::HINT))
-        .highlight_style(
-            Style::default()
-                .fg(theme::BORDER_FOCUS)
-                .add_modifier(Modifier::BOLD),
+/// Field of the Custom tab's range picker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RangeField {
+    From,
+    To,
+    By,
+}
+
+/// Bucket sizes the Custom tab offers, finest first.
+const CUSTOM_GRANULARITIES: [db::Granularity; 4] = [
+    db::Granularity::Hour,
+    db::Granularity::Day,
+    db::Granularity::Week,
+    db::Granularity::Month,
+];
+
+/// The Custom tab's range: whole days, both ends inclusive.
+struct RangePicker {
+    from: NaiveDate,
+    to: NaiveDate,
+    granularity: db::Granularity,
+    field: RangeField,
+    editing: bool,
+}
+
+impl RangePicker {
+    /// This month so far, by day.
+    fn new(today: NaiveDate) -> Self {
+        Self {
+            from: today.with_day(1).unwrap_or(today),
+            to: today,
+            granularity: db::Granularity::Day,
+            field: RangeField::From,
+            editing: false,
+        }
+    }
+
+    /// The range as `[from, to)` timestamps.
+    fn bounds(&self) -> (NaiveDateTime, NaiveDateTime) {
+        let to = self.to.succ_opt().unwrap_or(self.to);
+        (
+            self.from.and_time(chrono::NaiveTime::MIN),
+            to.and_time(chrono::NaiveTime::MIN),
         )
-        .select(app.tab);
-    frame.render_widget(tabs, chunks[0]);
+    }
 
-    let inner = chunks[1];
-    match app.tab {
-        0 => render_live(frame, inner, app),
-        1 => render_table(frame, inner, &app.hourly, " Hourly (MiB) "),
-        2 => render_table(frame, inner, &app.daily, " Daily (MiB) "),
-        3 => render_table(frame, inner, &app.weekly, " Weekly (MiB) "),
-        4 => render_table(frame, inner, &app.monthly, " Monthly (MiB) "),
-        _ => {}
+    /// Applies a key while editing.
+    fn edit(&mut self, code: KeyCode) {
+        match code {
+            KeyCode::Left | KeyCode::BackTab => {
+                self.field = match self.field {
+                    RangeField::From => RangeField::By,
+                    RangeField::To => RangeField::From,
+                    RangeField::By => RangeField::To,
+                };
+            }
+            KeyCode::Right | KeyCode::Tab => {
+                self.field = match self.field {
+                    RangeField::From => RangeField::To,
+                    RangeField::To => RangeField::By,
+                    RangeField::By => RangeField::From,
+                };
+            }
+            KeyCode::Up => self.step(true, false),
+            KeyCode::Down => self.step(false, false),
+            KeyCode::PageUp => self.step(true, true),
+            KeyCode::PageDown => self.step(false, true),
+            KeyCode::Enter | KeyCode::Esc | KeyCode::Char('e') => {
+                self.editing = false;
+            }
+            _ => {}
+        }
     }
 
-    let hint = Paragraph::new(Line::from(Span::styled(
-        " ← → Tab  │  q / Esc  Quit",
-        Style::default().fg(theme::HINT),
-    )));
-    frame.render_widget(hint, chunks[2]);
+    /// Moves the focused date a day or a month, dragging the other end along
+    /// so the range never inverts, or picks a coarser or finer bucket size.
+    fn step(&mut self, forward: bool, month: bool) {
+        let shift = |d: NaiveDate| match (forward, month) {
+            (true, false) => d.succ_opt(),
+            (false, false) => d.pred_opt(),
+            (true, true) => d.checked_add_months(Months::new(1)),
+            (false, true) => d.checked_sub_months(Months::new(1)),
+        };
+        match self.field {
+            RangeField::From => {
+                let Some(d) = shift(self.from) else {
+                    return;
+                };
+                self.from = d;
+                self.to = self.to.max(d);
+            }
+            RangeField::To => {
+                let Some(d) = shift(self.to) else {
+                    return;
+                };
+                self.to = d;
+                self.from = self.from.min(d);
+            }
+            RangeField::By => {
+                let i = CUSTOM_GRANULARITIES
+                    .iter()
+                    .position(|g| *g == self.granularity)
+                    .unwrap_or(1);
+                let i = if forward {
+                    (i + 1).min(CUSTOM_GRANULARITIES.len() - 1)
+                } else {
+                    i.saturating_sub(1)
+                };
+                self.granularity = CUSTOM_GRANULARITIES[i];
+            }
+        }
+    }
 }
 
-fn render_live(frame: &mut Frame, area: Rect, app: &App) {
-    let (rx1, tx1, total1) = app.live_1min;
-    let (rx5, tx5, total5) = app.live_5min;
+/// A change of the selected row.
+#[derive(Debug, Clone, Copy)]
+enum Move {
+    Up(usize),
+    Down(usize),
+    PageUp,
+    PageDown,
+    Home,
+    End,
+}
 
-    let lines = vec![
-        Line::from(""),
+/// Rows one mouse wheel notch moves the selection.
+const WHEEL_STEP: usize = 3;
+
+/// Selected row and scroll offset of one table.
+#[derive(Default)]
+struct TableView {
+    state: TableState,
+    // Rows that fit at the last draw, for paging
+    page: usize,
+}
+
+impl TableView {
+    fn apply(&mut self, m: Move, len: usize) {
+        if len == 0 {
+            self.state.select(None);
+            return;
+        }
+        let current = self.state.selected().unwrap_or(0).min(len - 1);
+        let page = self.page.max(1);
+        let next = match m {
+            Move::Up(n) => current.saturating_sub(n),
+            Move::Down(n) => current + n,
+            Move::PageUp => current.saturating_sub(page),
+            Move::PageDown => current + page,
+            Move::Home => 0,
+            Move::End => len - 1,
+        };
+        self.state.select(Some(next.min(len - 1)));
+    }
+
+    /// Keeps the selection on an existing row after the data changed.
+    fn clamp(&mut self, len: usize) {
+        let selected = self.state.selected().unwrap_or(0);
+        self.state.select((len > 0).then(|| selected.min(len - 1)));
+    }
+}
+
+/// What a tab's data was queried with; a result for any other key is stale.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct QueryKey {
+    tab: usize,
+    host: db::HostFilter,
+    // Custom tab only: the picked range and bucket size
+    range: Option<(NaiveDateTime, NaiveDateTime, db::Granularity)>,
+    // Profile tab only: index into PROFILE_WINDOWS
+    window: Option<usize>,
+}
+
+/// When a tab's data was last fetched, and what is being fetched now.
+struct CacheSlot<K> {
+    fetched: Option<(Instant, K)>,
+    loading: Option<K>,
+}
+
+impl<K> Default for CacheSlot<K> {
+    fn default() -> Self {
+        Self {
+            fetched: None,
+            loading: None,
+        }
+    }
+}
+
+impl<K: PartialEq> CacheSlot<K> {
+    /// Whether `key` has no fetch in flight and no result younger than `ttl`.
+    fn needs_fetch(&self, key: &K, ttl: Duration) -> bool {
+        self.loading.as_ref() != Some(key)
+            && self
+                .fetched
+                .as_ref()
+                .is_none_or(|(at, k)| k != key || at.elapsed() >= ttl)
+    }
+
+    /// Whether the shown data isn't for `key` yet and a fetch is running.
+    fn is_loading(&self, key: &K) -> bool {
+        self.loading.is_some() && self.fetched.as_ref().is_none_or(|(_, k)| k != key)
+    }
+}
+
+/// A drill-down waiting for its rows.
+#[derive(Debug, Clone, PartialEq)]
+struct DrillRequest {
+    key: QueryKey,
+    // Levels already open when Enter was pressed
+    depth: usize,
+    label: String,
+    granularity: db::Granularity,
+}
+
+/// Data of one tab, as fetched by `fetch_tab`.
+enum Fetched {
+    Live {
+        last_1min: (f64, f64, f64),
+        last_5min: (f64, f64, f64),
+        by_interface: Vec<db::LiveInterfaceRow>,
+    },
+    History {
+        rows: Vec<db::PeriodRow>,
+        costs: Vec<HashMap<String, f64>>,
+    },
+    Comparisons(Vec<(compare::Baseline, compare::Comparison)>),
+    Calendar(BTreeMap<NaiveDate, db::PeriodRow>),
+    Profile(Box<profile::Profile>),
+    Quotas(Vec<(quota::QuotaUsage, forecast::Forecast)>),
+}
+
+/// A finished background query, posted to the render loop. `None` when the
+/// query failed; the tab keeps showing what it had.
+enum Fetch {
+    Tab(QueryKey, Option<Fetched>),
+    Hosts(Option<Vec<db::Host>>),
+    Drill(DrillRequest, Option<Vec<db::InterfacePeriodRow>>),
+}
+
+type FetchSender = tokio::sync::mpsc::UnboundedSender<Fetch>;
+
+/// How long a tab's data is shown before it is queried again.
+const LIVE_TTL: Duration = Duration::from_secs(1);
+const HISTORY_TTL: Duration = Duration::from_secs(30);
+const HOSTS_TTL: Duration = Duration::from_secs(60);
+
+/// Bucket size and number of periods of the Hourly to Monthly tabs.
+fn history_query(tab: usize) -> Option<(db::Granularity, u32)> {
+    match tab {
+        1 => Some((db::Granularity::Hour, 24)),
+        2 => Some((db::Granularity::Day, 31)),
+        3 => Some((db::Granularity::Week, 12)),
+        4 => Some((db::Granularity::Month, 12)),
+        _ => None,
+    }
+}
+
+/// Per-tariff costs of this machine's traffic per period of `granularity` in
+/// `[from, to)`.
+async fn fetch_costs(
+    pool: &sqlx::SqlitePool,
+    tariffs: &[tariff::Tariff],
+    granularity: db::Granularity,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+) -> Vec<HashMap<String, f64>> {
+    let mut costs = Vec::new();
+    for t in tariffs {
+        costs.push(
+            tariff::cost_by_period(pool, t, granularity, from, to, db::HostFilter::Local)
+                .await
+                .unwrap_or_default(),
+        );
+    }
+    costs
+}
+
+/// Queries everything the tab of `key` shows.
+async fn fetch_tab(
+    pool: &sqlx::SqlitePool,
+    key: QueryKey,
+    tariffs: &[tariff::Tariff],
+) -> Result<Fetched, sqlx::Error> {
+    let now = Utc::now().naive_utc();
+    let host = key.host;
+    if let Some((granularity, periods)) = history_query(key.tab) {
+        let rows = match granularity {
+            db::Granularity::Hour => db::usage_by_hour(pool, periods, host).await?,
+            db::Granularity::Day => db::usage_by_day(pool, periods, host).await?,
+            db::Granularity::Week => db::usage_by_week(pool, periods, host).await?,
+            _ => db::usage_by_month(pool, periods, host).await?,
+        };
+        let from = granularity.periods_back(now, periods.saturating_sub(1));
+        let to = now + chrono::Duration::days(1);
+        let costs = fetch_costs(pool, tariffs, granularity, from, to).await;
+        return Ok(Fetched::History { rows, costs });
+    }
+    if let Some((from, to, granularity)) = key.range {
+        let filter = db::UsageFilter {
+            interface: None,
+            host,
+        };
+        let rows = db::usage_between(pool, from, to, granularity, &filter).await?;
+        let costs = fetch_costs(pool, tariffs, granularity, from, to).await;
+        return Ok(Fetched::History { rows, costs });
+    }
+    Ok(match key.tab {
+        0 => Fetched::Live {
+            last_1min: db::recent_totals(pool, 1, db::HostFilter::Local).await?,
+            last_5min: db::recent_totals(pool, 5, db::HostFilter::Local).await?,
+            by_interface: db::recent_by_interface(pool, 1, db::HostFilter::Local).await?,
+        },
+        6 => {
+            let mut comparisons = Vec::new();
+            for baseline in compare::Baseline::ALL {
+                comparisons.push((baseline, baseline.compare(pool, now, host).await?));
+            }
+            Fetched::Comparisons(comparisons)
+        }
+        7 => {
+            let (first, _) = calendar_range(now.date());
+            let days = db::usage_by_day(pool, CALENDAR_WEEKS as u32 * 7, host).await?;
+            Fetched::Calendar(
+                days.into_iter()
+                    .filter_map(|r| {
+                        Some((NaiveDate::parse_from_str(&r.period, "%Y-%m-%d").ok()?, r))
+                    })
+                    .filter(|(day, _)| *day >= first)
+                    .collect(),
+            )
+        }
+        8 => {
+            // Up to the last complete hour
+            let to = db::Granularity::Hour.period_start(now);
+            let weeks = PROFILE_WINDOWS[key.window.unwrap_or_default()];
+            let from = to - chrono::Duration::weeks(weeks);
+            Fetched::Profile(Box::new(
+                profile::usage_profile(pool, from, to, host).await?,
+            ))
+        }
+        _ => {
+            let mut quotas = Vec::new();
+            for u in db::quota_usages(pool, now, db::HostFilter::Local).await? {
+                let f = forecast::forecast_usage(pool, &u, now, db::HostFilter::Local).await?;
+                quotas.push((u, f));
+            }
+            Fetched::Quotas(quotas)
+        }
+    })
+}
+
+impl App {
+    fn new(tariffs: Vec<tariff::Tariff>, rate_feed: RateFeed, rates_from_daemon: bool) -> Self {
+        Self {
+            tab: 0,
+            hourly: Vec::new(),
+            daily: Vec::new(),
+            weekly: Vec::new(),
+            monthly: Vec::new(),
+            live_1min: (0.0, 0.0, 0.0),
+            live_5min: (0.0, 0.0, 0.0),
+            live_by_interface: Vec::new(),
+            rates: None,
+            rate_feed,
+            rates_from_daemon,
+            hosts: Vec::new(),
+            host: db::HostFilter::All,
+            quotas: Vec::new(),
+            tariffs,
+            hourly_costs: Vec::new(),
+            daily_costs: Vec::new(),
+            weekly_costs: Vec::new(),
+            monthly_costs: Vec::new(),
+            range: RangePicker::new(Utc::now().date_naive()),
+            custom: Vec::new(),
+            custom_costs: Vec::new(),
+            comparisons: Vec::new(),
+            calendar: BTreeMap::new(),
+            calendar_cursor: Utc::now().date_naive(),
+            profile: None,
+            profile_window: 0,
+            views: Default::default(),
+            drill: Vec::new(),
+            drill_loading: None,
+            cache: Default::default(),
+            hosts_cache: CacheSlot::default(),
+            started: Instant::now(),
+        }
+    }
+
+    /// Period rows and bucket size of a history tab.
+    fn history(&self, tab: usize) -> Option<(&[db::PeriodRow], db::Granularity)> {
+        match tab {
+            1 => Some((&self.hourly, db::Granularity::Hour)),
+            2 => Some((&self.daily, db::Granularity::Day)),
+            3 => Some((&self.weekly, db::Granularity::Week)),
+            4 => Some((&self.monthly, db::Granularity::Month)),
+            5 => Some((&self.custom, self.range.granularity)),
+            _ => None,
+        }
+    }
+
+    /// Label and bucket size of the selected period on the shown table.
+    fn selected_period(&self) -> Option<(String, db::Granularity)> {
+        match self.drill.last() {
+            Some(level) => level
+                .rows
+                .get(level.view.state.selected()?)
+                .map(|r| (r.period.clone(), level.granularity)),
+            None if self.tab == 7 => Some((
+                db::Granularity::Day.label(self.calendar_cursor.and_time(chrono::NaiveTime::MIN)),
+                db::Granularity::Day,
+            )),
+            None => {
+                let (rows, granularity) = self.history(self.tab)?;
+                rows.get(self.views[self.tab].state.selected()?)
+                    .map(|r| (r.period.clone(), granularity))
+            }
+        }
+    }
+
+    /// Whether Enter can break the shown periods down further.
+    fn can_drill(&self) -> bool {
+        match self.drill.last() {
+            Some(level) => level.granularity.finer().is_some(),
+            None => self.tab == 7 || self.history(self.tab).is_some(),
+        }
+    }
+
+    /// Moves the Calendar tab's cursor: a week for ← →, a day for ↑ ↓.
+    fn move_calendar_cursor(&mut self, code: KeyCode) {
+        let (first, last) = calendar_range(Utc::now().date_naive());
+        let day = self.calendar_cursor;
+        let moved = match code {
+            KeyCode::Left => day.checked_sub_days(chrono::Days::new(7)),
+            KeyCode::Right => day.checked_add_days(chrono::Days::new(7)),
+            KeyCode::Up => day.pred_opt(),
+            KeyCode::Down => day.succ_opt(),
+            KeyCode::Home => Some(first),
+            KeyCode::End => Some(last),
+            _ => None,
+        };
+        if let Some(d) = moved {
+            self.calendar_cursor = d.clamp(first, last);
+        }
+    }
+
+    /// Starts breaking the selected period into its finer periods; the
+    /// level opens when its rows arrive.
+    fn drill_in(&mut self, pool: &sqlx::SqlitePool, rt: &tokio::runtime::Handle, tx: &FetchSender) {
+        let Some((label, granularity)) = self.selected_period() else {
+            return;
+        };
+        let (Some(finer), Some((from, to))) =
+            (granularity.finer(), granularity.label_range(&label))
+        else {
+            return;
+        };
+        let request = DrillRequest {
+            key: self.query_key(self.tab),
+            depth: self.drill.len(),
+            label,
+            granularity: finer,
+        };
+        self.drill_loading = Some(request.clone());
+        let (pool, tx, host) = (pool.clone(), tx.clone(), self.host);
+        rt.spawn(async move {
+            let rows = db::usage_by_interface_between(&pool, finer, from, to, host)
+                .await
+                .ok();
+            let _ = tx.send(Fetch::Drill(request, rows));
+        });
+    }
+
+    /// Breadcrumb of the shown table, e.g. "Monthly › 2026-09 › 2026-09-14".
+    fn breadcrumb(&self) -> String {
+        let mut crumbs = vec![TAB_TITLES[self.tab].trim()];
+        crumbs.extend(self.drill.iter().map(|l| l.label.as_str()));
+        crumbs.join(" › ")
+    }
+
+    /// Rows in the current tab's table; the Quota tab has none.
+    fn table_len(&self) -> Option<usize> {
+        if let Some(level) = self.drill.last() {
+            return Some(level.rows.len());
+        }
+        match self.tab {
+            0 => Some(self.live_by_interface.len()),
+            1 => Some(self.hourly.len()),
+            2 => Some(self.daily.len()),
+            3 => Some(self.weekly.len()),
+            4 => Some(self.monthly.len()),
+            5 => Some(self.custom.len()),
+            _ => None,
+        }
+    }
+
+    fn move_selection(&mut self, m: Move) {
+        if let Some(len) = self.table_len() {
+            match self.drill.last_mut() {
+                Some(level) => level.view.apply(m, len),
+                None => self.views[self.tab].apply(m, len),
+            }
+        }
+    }
+
+    /// What the given tab's data depends on right now.
+    fn query_key(&self, tab: usize) -> QueryKey {
+        let (from, to) = self.range.bounds();
+        QueryKey {
+            tab,
+            host: self.host,
+            range: (tab == 5).then_some((from, to, self.range.granularity)),
+            window: (tab == 8).then_some(self.profile_window),
+        }
+    }
+
+    /// Starts background queries for the shown tab and the host list when
+    /// their cached results are missing, stale or for other settings.
+    fn refresh(&mut self, pool: &sqlx::SqlitePool, rt: &tokio::runtime::Handle, tx: &FetchSender) {
+        if let Ok(rates) = self.rate_feed.lock() {
+            self.rates = rates.clone();
+        }
+        if self.hosts_cache.needs_fetch(&(), HOSTS_TTL) {
+            self.hosts_cache.loading = Some(());
+            let (pool, tx) = (pool.clone(), tx.clone());
+            rt.spawn(async move {
+                let _ = tx.send(Fetch::Hosts(db::hosts(&pool).await.ok()));
+            });
+        }
+        let key = self.query_key(self.tab);
+        let ttl = if self.tab == 0 { LIVE_TTL } else { HISTORY_TTL };
+        if self.cache[self.tab].needs_fetch(&key, ttl) {
+            self.cache[self.tab].loading = Some(key);
+            let (pool, tx, tariffs) = (pool.clone(), tx.clone(), self.tariffs.clone());
+            rt.spawn(async move {
+                let data = fetch_tab(&pool, key, &tariffs).await.ok();
+                let _ = tx.send(Fetch::Tab(key, data));
+            });
+        }
+    }
+
+    /// Stores a finished query, unless the tab has moved on to other settings.
+    fn apply(&mut self, fetch: Fetch) {
+        match fetch {
+            Fetch::Hosts(hosts) => {
+                self.hosts_cache.loading = None;
+                self.hosts_cache.fetched = Some((Instant::now(), ()));
+                if let Some(v) = hosts {
+                    self.hosts = v;
+                }
+            }
+            Fetch::Drill(request, rows) => {
+                if self.drill_loading.as_ref() != Some(&request) {
+                    return;
+                }
+                self.drill_loading = None;
+                if request.key != self.query_key(self.tab) || request.depth != self.drill.len() {
+                    return;
+                }
+                if let Some(rows) = rows {
+                    self.drill.push(DrillLevel {
+                        label: request.label,
+                        granularity: request.granularity,
+                        rows,
+                        view: TableView::default(),
+                    });
+                }
+            }
+            Fetch::Tab(key, data) => {
+                let current = self.query_key(key.tab);
+                let slot = &mut self.cache[key.tab];
+                if slot.loading == Some(key) {
+                    slot.loading = None;
+                }
+                if key != current {
+                    return;
+                }
+                slot.fetched = Some((Instant::now(), key));
+                match data {
+                    Some(Fetched::Live {
+                        last_1min,
+                        last_5min,
+                        by_interface,
+                    }) => {
+                        self.live_1min = last_1min;
+                        self.live_5min = last_5min;
+                        self.live_by_interface = by_interface;
+                    }
+                    Some(Fetched::History { rows, costs }) => {
+                        let (rows_slot, costs_slot) = match key.tab {
+                            1 => (&mut self.hourly, &mut self.hourly_costs),
+                            2 => (&mut self.daily, &mut self.daily_costs),
+                            3 => (&mut self.weekly, &mut self.weekly_costs),
+                            4 => (&mut self.monthly, &mut self.monthly_costs),
+                            _ => (&mut self.custom, &mut self.custom_costs),
+                        };
+                        *rows_slot = rows;
+                        *costs_slot = costs;
+                    }
+                    Some(Fetched::Comparisons(v)) => self.comparisons = v,
+                    Some(Fetched::Calendar(v)) => {
+                        self.calendar = v;
+                        let (first, last) = calendar_range(Utc::now().date_naive());
+                        self.calendar_cursor = self.calendar_cursor.clamp(first, last);
+                    }
+                    Some(Fetched::Profile(p)) => self.profile = Some(*p),
+                    Some(Fetched::Quotas(v)) => self.quotas = v,
+                    None => {}
+                }
+            }
+        }
+    }
+
+    /// Whether the shown table is still waiting for its first result.
+    fn loading(&self) -> bool {
+        let key = self.query_key(self.tab);
+        match &self.drill_loading {
+            Some(r) if r.key == key && r.depth == self.drill.len() => true,
+            _ => self.drill.is_empty() && self.cache[self.tab].is_loading(&key),
+        }
+    }
+
+    /// Cycles all hosts → this machine → each pushing host.
+    fn next_host(&mut self) {
+        let mut choices = vec![db::HostFilter::All, db::HostFilter::Local];
+        choices.extend(self.hosts.iter().map(|h| db::HostFilter::Host(h.id)));
+        let i = choices.iter().position(|h| *h == self.host).unwrap_or(0);
+        self.host = choices[(i + 1) % choices.len()];
+    }
+
+    fn host_label(&self) -> String {
+        match self.host {
+            db::HostFilter::All => "all hosts".to_string(),
+            db::HostFilter::Local => "this machine".to_string(),
+            db::HostFilter::Host(id) => self
+                .hosts
+                .iter()
+                .find(|h| h.id == id)
+                .map_or_else(|| format!("host {}", id), |h| h.hostname.clone()),
+        }
+    }
+}
+
+/// Tariffs price this machine's bill, so costs are shown for this machine,
+/// or for all hosts when no other host pushes to the database.
+fn cost_tariffs<'a>(
+    host: db::HostFilter,
+    hosts: &[db::Host],
+    tariffs: &'a [tariff::Tariff],
+) -> &'a [tariff::Tariff] {
+    match host {
+        db::HostFilter::Local => tariffs,
+        db::HostFilter::All if hosts.is_empty() => tariffs,
+        _ => &[],
+    }
+}
+
+fn run_tui(
+    pool: &sqlx::SqlitePool,
+    rt: tokio::runtime::Handle,
+    tariffs: Vec<tariff::Tariff>,
+    rate_feed: RateFeed,
+    rates_from_daemon: bool,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+    enable_raw_mode()?;
+    let mut terminal = Terminal::new(ratatui::backend::CrosstermBackend::new(stdout))?;
+
+    let mut app = App::new(tariffs, rate_feed, rates_from_daemon);
+    // Queries run as tasks on `rt` and post their results here, so the
+    // render loop never waits on the database
+    let (tx, mut results) = tokio::sync::mpsc::unbounded_channel();
+
+    loop {
+        while let Ok(fetch) = results.try_recv() {
+            app.apply(fetch);
+        }
+        app.refresh(pool, &rt, &tx);
+        terminal.draw(|f| ui(f, &mut app))?;
+
+        if !event::poll(Duration::from_millis(200))? {
+            continue;
+        }
+        match event::read()? {
+            Event::Mouse(mouse) => match mouse.kind {
+                MouseEventKind::ScrollUp => app.move_selection(Move::Up(WHEEL_STEP)),
+                MouseEventKind::ScrollDown => app.move_selection(Move::Down(WHEEL_STEP)),
+                _ => {}
+            },
+            Event::Key(key) if key.kind == KeyEventKind::Press && app.range.editing => {
+                app.range.edit(key.code)
+            }
+            Event::Key(key) if key.kind == KeyEventKind::Press => match key.code {
+                KeyCode::Char('e') if app.tab == 5 && app.drill.is_empty() => {
+                    app.range.editing = true;
+                }
+                KeyCode::Char('w') if app.tab == 8 => {
+                    app.profile_window = (app.profile_window + 1) % PROFILE_WINDOWS.len();
+                }
+                KeyCode::Backspace => {
+                    app.drill.pop();
+                }
+                KeyCode::Esc if !app.drill.is_empty() => {
+                    app.drill.pop();
+                }
+                KeyCode::Char('q') | KeyCode::Esc => break,
+                KeyCode::Enter => app.drill_in(pool, &rt, &tx),
+                code @ (KeyCode::Left
+                | KeyCode::Right
+                | KeyCode::Up
+                | KeyCode::Down
+                | KeyCode::Home
+                | KeyCode::End)
+                    if app.tab == 7 && app.drill.is_empty() =>
+                {
+                    app.move_calendar_cursor(code)
+                }
+                KeyCode::Right | KeyCode::Tab => {
+                    app.tab = (app.tab + 1) % TAB_TITLES.len();
+                    app.drill.clear();
+                }
+                KeyCode::Left | KeyCode::BackTab => {
+                    app.tab = app.tab.checked_sub(1).unwrap_or(TAB_TITLES.len() - 1);
+                    app.drill.clear();
+                }
+                KeyCode::Char('h') if !app.hosts.is_empty() => {
+                    app.next_host();
+                    app.drill.clear();
+                }
+                KeyCode::Down => app.move_selection(Move::Down(1)),
+                KeyCode::Up => app.move_selection(Move::Up(1)),
+                KeyCode::PageDown => app.move_selection(Move::PageDown),
+                KeyCode::PageUp => app.move_selection(Move::PageUp),
+                KeyCode::Home => app.move_selection(Move::Home),
+                KeyCode::End => app.move_selection(Move::End),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    disable_raw_mode()?;
+    execute!(
+        terminal.backend_mut(),
+        LeaveAlternateScreen,
+        DisableMouseCapture
+    )?;
+    terminal.show_cursor()?;
+    Ok(())
+}
+
+const TAB_TITLES: [&str; 10] = [
+    " Live ",
+    " Hourly ",
+    " Daily ",
+    " Weekly ",
+    " Monthly ",
+    " Custom ",
+    " Compare ",
+    " Calendar ",
+    " Profile ",
+    " Quota ",
+];
+
+/// Frames of the header's loading indicator.
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// Windows the Profile tab averages over, in weeks.
+const PROFILE_WINDOWS: [i64; 3] = [4, 12, 52];
+
+/// Weeks on the Calendar tab: the current one and the 52 before it.
+const CALENDAR_WEEKS: i64 = 53;
+
+/// First and last day of the Calendar tab: the Monday `CALENDAR_WEEKS - 1`
+/// weeks before the current week, and today.
+fn calendar_range(today: NaiveDate) -> (NaiveDate, NaiveDate) {
+    let monday = today - chrono::Duration::days(today.weekday().num_days_from_monday() as i64);
+    (monday - chrono::Duration::weeks(CALENDAR_WEEKS - 1), today)
+}
+
+fn ui(frame: &mut Frame, app: &mut App) {
+    // Full area background
+    frame.render_widget(
+        Paragraph::new("").style(Style::default().bg(theme::BG)),
+        frame.area(),
+    );
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .split(frame.area());
+
+    let mut title = if app.hosts.is_empty() {
+        " ambr ".to_string()
+    } else {
+        format!(" ambr · {} ", app.host_label())
+    };
+    if app.loading() {
+        let frame = app.started.elapsed().as_millis() as usize / 200 % SPINNER.len();
+        title.push_str(&format!("{} loading ", SPINNER[frame]));
+    }
+    let tab_block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme::BORDER))
+        .title(Span::styled(
+            title,
+            Style::default()
+                .fg(theme::TITLE)
+                .add_modifier(Modifier::BOLD),
+        ));
+    let tabs = Tabs::new(TAB_TITLES)
+        .block(tab_block)
+        .padding("", "")
+        .style(Style::default().fg(theme::HINT))
+        .highlight_style(
+            Style::default()
+                .fg(theme::BORDER_FOCUS)
+                .add_modifier(Modifier::BOLD),
+        )
+        .select(app.tab);
+    frame.render_widget(tabs, chunks[0]);
+
+    let inner = chunks[1];
+    let tariffs = cost_tariffs(app.host, &app.hosts, &app.tariffs);
+    let breadcrumb = app.breadcrumb();
+    match app.tab {
+        _ if !app.drill.is_empty() => {
+            if let Some(level) = app.drill.last_mut() {
+                render_drill(frame, inner, &breadcrumb, level);
+            }
+        }
+        0 => render_live(frame, inner, app),
+        1 => render_table(
+            frame,
+            inner,
+            &app.hourly,
+            " Hourly (MiB) ",
+            tariffs,
+            &app.hourly_costs,
+            &mut app.views[1],
+        ),
+        2 => render_table(
+            frame,
+            inner,
+            &app.daily,
+            " Daily (MiB) ",
+            tariffs,
+            &app.daily_costs,
+            &mut app.views[2],
+        ),
+        3 => render_table(
+            frame,
+            inner,
+            &app.weekly,
+            " Weekly (MiB) ",
+            tariffs,
+            &app.weekly_costs,
+            &mut app.views[3],
+        ),
+        4 => render_table(
+            frame,
+            inner,
+            &app.monthly,
+            " Monthly (MiB) ",
+            tariffs,
+            &app.monthly_costs,
+            &mut app.views[4],
+        ),
+        5 => render_custom(frame, inner, app),
+        6 => render_compare(frame, inner, &app.comparisons),
+        7 => render_calendar(frame, inner, app),
+        8 => render_profile(frame, inner, app),
+        9 => render_quota(frame, inner, &app.quotas),
+        _ => {}
+    }
+
+    let hint = if app.range.editing {
+        " ← → Field  │  ↑ ↓ ±1 day / bucket  │  PgUp PgDn ±1 month  │  Enter / Esc  Done"
+            .to_string()
+    } else {
+        let mut hint = if app.tab == 7 && app.drill.is_empty() {
+            String::from(" Tab ⇧Tab  Tab  │  ← → Week  │  ↑ ↓ Day  │  Home End  First / last")
+        } else {
+            String::from(" ← → Tab  │  ↑ ↓ PgUp PgDn Home End Scroll")
+        };
+        if app.can_drill() {
+            hint.push_str("  │  Enter Open");
+        }
+        if app.tab == 5 && app.drill.is_empty() {
+            hint.push_str("  │  e Range");
+        }
+        if app.tab == 8 {
+            hint.push_str("  │  w Window");
+        }
+        if !app.hosts.is_empty() {
+            hint.push_str("  │  h Host");
+        }
+        if app.drill.is_empty() {
+            hint.push_str("  │  q / Esc  Quit");
+        } else {
+            hint.push_str("  │  ⌫ / Esc  Back  │  q  Quit");
+        }
+        hint
+    };
+    let hint = Paragraph::new(Line::from(Span::styled(
+        hint,
+        Style::default().fg(theme::HINT),
+    )));
+    frame.render_widget(hint, chunks[2]);
+}
+
+fn render_live(frame: &mut Frame, area: Rect, app: &mut App) {
+    let (rx1, tx1, total1) = app.live_1min;
+    let (rx5, tx5, total5) = app.live_5min;
+
+    let mut lines = vec![
+        Line::from(""),
         Line::from(vec![
             Span::styled("Last 1 min  ", Style::default().fg(theme::HINT)),
             Span::styled("↓ ", Style::default().fg(theme::RX)),
@@ -274,6 +1819,26 @@ fn render_live(frame: &mut Frame, area: Rect, app: &App) {
         ]),
         Line::from(""),
     ];
+    if let Some(rates) = &app.rates {
+        let (rx, tx) = rates.total();
+        let source = match (rates.paused, app.rates_from_daemon) {
+            (true, _) => "  (recording paused)",
+            (false, true) => "  (daemon)",
+            (false, false) => "",
+        };
+        lines.push(Line::from(vec![
+            Span::styled("Now         ", Style::default().fg(theme::HINT)),
+            Span::styled("↓ ", Style::default().fg(theme::RX)),
+            Span::styled(
+                format!("{}  ", fmt_rate(rx)),
+                Style::default().fg(theme::RX),
+            ),
+            Span::styled("↑ ", Style::default().fg(theme::TX)),
+            Span::styled(fmt_rate(tx), Style::default().fg(theme::TX)),
+            Span::styled(source, Style::default().fg(theme::HINT)),
+        ]));
+        lines.push(Line::from(""));
+    }
 
     let block = Block::default()
         .borders(Borders::ALL)
@@ -368,18 +1933,71 @@ fn render_live(frame: &mut Frame, area: Rect, app: &App) {
                 " By interface (last 1 min) ",
                 Style::default().fg(theme::TITLE),
             ));
-        let table = Table::new(table_rows, widths)
+        let table = selectable(Table::new(table_rows, widths))
             .header(header)
             .block(table_block);
-        frame.render_widget(table, table_area);
+        let len = app.live_by_interface.len();
+        render_selectable(frame, table_area, table, len, &mut app.views[0]);
+    }
+}
+
+/// Highlights the selected row of a table drawn with `render_selectable`.
+fn selectable(table: Table<'_>) -> Table<'_> {
+    table
+        .row_highlight_style(
+            Style::default()
+                .bg(theme::BORDER)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol("▸ ")
+        .highlight_spacing(HighlightSpacing::Always)
+}
+
+/// Draws a bordered table with a header row at `view`'s selection, with a
+/// scrollbar on the right border when its `len` rows do not fit.
+fn render_selectable(
+    frame: &mut Frame,
+    area: Rect,
+    table: Table<'_>,
+    len: usize,
+    view: &mut TableView,
+) {
+    view.clamp(len);
+    // Borders and the header row
+    view.page = area.height.saturating_sub(3) as usize;
+    frame.render_stateful_widget(table, area, &mut view.state);
+    if len > view.page {
+        let mut state = ScrollbarState::new(len)
+            .viewport_content_length(view.page)
+            .position(view.state.selected().unwrap_or(0));
+        frame.render_stateful_widget(
+            Scrollbar::new(ScrollbarOrientation::VerticalRight)
+                .begin_symbol(None)
+                .end_symbol(None)
+                .style(Style::default().fg(theme::BORDER_FOCUS)),
+            Rect {
+                y: area.y + 2,
+                height: view.page as u16,
+                ..area
+            },
+            &mut state,
+        );
     }
 }
 
-fn render_table(frame: &mut Frame, area: Rect, rows: &[db::PeriodRow], title: &str) {
+fn render_table(
+    frame: &mut Frame,
+    area: Rect,
+    rows: &[db::PeriodRow],
+    title: &str,
+    tariffs: &[tariff::Tariff],
+    costs: &[HashMap<String, f64>],
+    view: &mut TableView,
+) {
     let header_style = Style::default()
         .fg(theme::HEADER)
         .add_modifier(Modifier::BOLD);
-    let header = Row::new(vec![
+    let mut header_cells = vec![
         Cell::from(Span::styled("Period", header_style)),
         Cell::from(Span::styled(
             "↓ Rx (MiB)",
@@ -395,8 +2013,17 @@ fn render_table(frame: &mut Frame, area: Rect, rows: &[db::PeriodRow], title: &s
                 .fg(theme::TOTAL)
                 .add_modifier(Modifier::BOLD),
         )),
-    ]);
-    let table_rows: Vec<Row> = rows
+    ];
+    for t in tariffs {
+        header_cells.push(Cell::from(Span::styled(
+            format!("{} ({})", t.name, t.currency),
+            Style::default()
+                .fg(theme::COST)
+                .add_modifier(Modifier::BOLD),
+        )));
+    }
+    let header = Row::new(header_cells);
+    let table_rows: Vec<Row> = rows
         .iter()
         .enumerate()
         .map(|(i, r)| {
@@ -405,7 +2032,7 @@ fn render_table(frame: &mut Frame, area: Rect, rows: &[db::PeriodRow], title: &s
             } else {
                 theme::BG
             };
-            Row::new(vec![
+            let mut cells = vec![
                 Cell::from(Span::styled(
                     r.period.clone(),
                     Style::default().fg(theme::HEADER),
@@ -415,11 +2042,99 @@ fn render_table(frame: &mut Frame, area: Rect, rows: &[db::PeriodRow], title: &s
                 Cell::from(format!("{:.2}", r.tx_mib)).style(Style::default().fg(theme::TX).bg(bg)),
                 Cell::from(format!("{:.2}", r.total_mib))
                     .style(Style::default().fg(theme::TOTAL).bg(bg)),
+            ];
+            for c in costs {
+                let cost = c.get(&r.period).copied().unwrap_or(0.0);
+                cells.push(
+                    Cell::from(format!("{:.2}", cost))
+                        .style(Style::default().fg(theme::COST).bg(bg)),
+                );
+            }
+            Row::new(cells)
+        })
+        .collect();
+    let mut widths = vec![
+        Constraint::Length(22),
+        Constraint::Length(12),
+        Constraint::Length(12),
+        Constraint::Length(12),
+    ];
+    widths.extend(tariffs.iter().map(|_| Constraint::Length(16)));
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme::BORDER))
+        .title(Span::styled(
+            title,
+            Style::default()
+                .fg(theme::TITLE)
+                .add_modifier(Modifier::BOLD),
+        ));
+    let table = selectable(Table::new(table_rows, widths))
+        .header(header)
+        .block(block);
+    render_selectable(frame, area, table, rows.len(), view);
+}
+
+/// Draws a drill-down level: one row per period and interface, with the
+/// period shown once per group and the row shading alternating per period.
+fn render_drill(frame: &mut Frame, area: Rect, breadcrumb: &str, level: &mut DrillLevel) {
+    let header_style = Style::default()
+        .fg(theme::HEADER)
+        .add_modifier(Modifier::BOLD);
+    let header = Row::new(vec![
+        Cell::from(Span::styled("Period", header_style)),
+        Cell::from(Span::styled("Interface", header_style)),
+        Cell::from(Span::styled(
+            "↓ Rx (MiB)",
+            Style::default().fg(theme::RX).add_modifier(Modifier::BOLD),
+        )),
+        Cell::from(Span::styled(
+            "↑ Tx (MiB)",
+            Style::default().fg(theme::TX).add_modifier(Modifier::BOLD),
+        )),
+        Cell::from(Span::styled(
+            "Total (MiB)",
+            Style::default()
+                .fg(theme::TOTAL)
+                .add_modifier(Modifier::BOLD),
+        )),
+    ]);
+    let mut group = 0;
+    let table_rows: Vec<Row> = level
+        .rows
+        .iter()
+        .enumerate()
+        .map(|(i, r)| {
+            let first = i == 0 || level.rows[i - 1].period != r.period;
+            if first && i > 0 {
+                group += 1;
+            }
+            let bg = if group % 2 == 1 {
+                theme::ROW_ALT
+            } else {
+                theme::BG
+            };
+            let period = if first {
+                r.period.clone()
+            } else {
+                String::new()
+            };
+            Row::new(vec![
+                Cell::from(Span::styled(period, Style::default().fg(theme::HEADER))),
+                Cell::from(Span::styled(
+                    r.interface.clone(),
+                    Style::default().fg(theme::HEADER),
+                )),
+                Cell::from(format!("{:.2}", r.rx_mib)).style(Style::default().fg(theme::RX)),
+                Cell::from(format!("{:.2}", r.tx_mib)).style(Style::default().fg(theme::TX)),
+                Cell::from(format!("{:.2}", r.total_mib)).style(Style::default().fg(theme::TOTAL)),
             ])
+            .style(Style::default().bg(bg))
         })
         .collect();
     let widths = [
         Constraint::Length(22),
+        Constraint::Length(16),
         Constraint::Length(12),
         Constraint::Length(12),
         Constraint::Length(12),
@@ -428,11 +2143,686 @@ fn render_table(frame: &mut Frame, area: Rect, rows: &[db::PeriodRow], title: &s
         .borders(Borders::ALL)
         .border_style(Style::default().fg(theme::BORDER))
         .title(Span::styled(
-            title,
+            format!(" {} · by interface (MiB) ", breadcrumb),
+            Style::default()
+                .fg(theme::TITLE)
+                .add_modifier(Modifier::BOLD),
+        ));
+    let table = selectable(Table::new(table_rows, widths))
+        .header(header)
+        .block(block);
+    let len = level.rows.len();
+    render_selectable(frame, area, table, len, &mut level.view);
+}
+
+/// Draws the Custom tab: the range picker with the range's totals, above
+/// the range's periods.
+fn render_custom(frame: &mut Frame, area: Rect, app: &mut App) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(0)])
+        .split(area);
+
+    let range = &app.range;
+    let field = |f: RangeField, text: String| {
+        let mut style = Style::default().fg(theme::HEADER);
+        if range.editing && range.field == f {
+            style = Style::default()
+                .fg(theme::BG)
+                .bg(theme::BORDER_FOCUS)
+                .add_modifier(Modifier::BOLD);
+        }
+        Span::styled(format!(" {} ", text), style)
+    };
+    let label = |text: String| Span::styled(text, Style::default().fg(theme::HINT));
+    let (rx, tx) = app
+        .custom
+        .iter()
+        .fold((0.0, 0.0), |(rx, tx), r| (rx + r.rx_mib, tx + r.tx_mib));
+    let days = (range.to - range.from).num_days() + 1;
+    let line = Line::from(vec![
+        label(" From".to_string()),
+        field(RangeField::From, range.from.to_string()),
+        label(" To".to_string()),
+        field(RangeField::To, range.to.to_string()),
+        label(" By".to_string()),
+        field(RangeField::By, range.granularity.as_str().to_string()),
+        label(format!(
+            "  │  {} day{}  │  ",
+            days,
+            if days == 1 { "" } else { "s" }
+        )),
+        Span::styled(format!("↓ {:.2}  ", rx), Style::default().fg(theme::RX)),
+        Span::styled(format!("↑ {:.2}  ", tx), Style::default().fg(theme::TX)),
+        Span::styled(
+            format!("◆ {:.2} MiB", rx + tx),
+            Style::default()
+                .fg(theme::TOTAL)
+                .add_modifier(Modifier::BOLD),
+        ),
+    ]);
+    let border = if range.editing {
+        theme::BORDER_FOCUS
+    } else {
+        theme::BORDER
+    };
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(border))
+        .title(Span::styled(
+            " Range ",
+            Style::default()
+                .fg(theme::TITLE)
+                .add_modifier(Modifier::BOLD),
+        ));
+    frame.render_widget(Paragraph::new(line).block(block), chunks[0]);
+
+    let title = format!(" Custom · {} → {} (MiB) ", range.from, range.to);
+    let tariffs = cost_tariffs(app.host, &app.hosts, &app.tariffs);
+    render_table(
+        frame,
+        chunks[1],
+        &app.custom,
+        &title,
+        tariffs,
+        &app.custom_costs,
+        &mut app.views[5],
+    );
+}
+
+/// Draws each comparison as two blocks side by side: the current period per
+/// interface on the left, the previous one with the change on the right.
+fn render_compare(
+    frame: &mut Frame,
+    area: Rect,
+    comparisons: &[(compare::Baseline, compare::Comparison)],
+) {
+    let sections = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            comparisons
+                .iter()
+                .map(|_| Constraint::Ratio(1, comparisons.len() as u32)),
+        )
+        .split(area);
+    let header_style = Style::default()
+        .fg(theme::HEADER)
+        .add_modifier(Modifier::BOLD);
+    let bytes_header = || {
+        [
+            Cell::from(Span::styled(
+                "↓ Rx",
+                Style::default().fg(theme::RX).add_modifier(Modifier::BOLD),
+            )),
+            Cell::from(Span::styled(
+                "↑ Tx",
+                Style::default().fg(theme::TX).add_modifier(Modifier::BOLD),
+            )),
+            Cell::from(Span::styled(
+                "Total",
+                Style::default()
+                    .fg(theme::TOTAL)
+                    .add_modifier(Modifier::BOLD),
+            )),
+        ]
+    };
+    let bytes_cells = |rx: i64, tx: i64, total: i64| {
+        [
+            Cell::from(fmt_bytes(rx)).style(Style::default().fg(theme::RX)),
+            Cell::from(fmt_bytes(tx)).style(Style::default().fg(theme::TX)),
+            Cell::from(fmt_bytes(total)).style(Style::default().fg(theme::TOTAL)),
+        ]
+    };
+    let block = |label: &str, period: &compare::Period| {
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(theme::BORDER))
+            .title(Span::styled(
+                format!(
+                    " {} · {} → {} ",
+                    label,
+                    period.from.format("%Y-%m-%d"),
+                    period.to.format("%m-%d %H:%M")
+                ),
+                Style::default()
+                    .fg(theme::TITLE)
+                    .add_modifier(Modifier::BOLD),
+            ))
+    };
+
+    for ((baseline, c), section) in comparisons.iter().zip(sections.iter()) {
+        let halves = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(44), Constraint::Percentage(56)])
+            .split(*section);
+        let (current_label, previous_label) = baseline.labels();
+        let total = c.total();
+        let rows: Vec<(usize, &compare::InterfaceChange)> = c
+            .interfaces
+            .iter()
+            .chain(std::iter::once(&total))
+            .enumerate()
+            .collect();
+        let row_style = |i: usize| {
+            let style = Style::default().bg(if i % 2 == 1 {
+                theme::ROW_ALT
+            } else {
+                theme::BG
+            });
+            if i == c.interfaces.len() {
+                style.add_modifier(Modifier::BOLD)
+            } else {
+                style
+            }
+        };
+
+        let mut header = vec![Cell::from(Span::styled("Interface", header_style))];
+        header.extend(bytes_header());
+        let current = rows.iter().map(|&(i, r)| {
+            let mut cells = vec![Cell::from(Span::styled(
+                r.interface.clone(),
+                Style::default().fg(theme::HEADER),
+            ))];
+            cells.extend(bytes_cells(r.rx.current, r.tx.current, r.total.current));
+            Row::new(cells).style(row_style(i))
+        });
+        let widths = [
+            Constraint::Min(6),
+            Constraint::Length(10),
+            Constraint::Length(10),
+            Constraint::Length(10),
+        ];
+        frame.render_widget(
+            Table::new(current, widths)
+                .header(Row::new(header))
+                .block(block(current_label, &c.current)),
+            halves[0],
+        );
+
+        let mut header = bytes_header().to_vec();
+        header.push(Cell::from(Span::styled("Δ Total", header_style)));
+        header.push(Cell::from(Span::styled("Change", header_style)));
+        let previous = rows.iter().map(|&(i, r)| {
+            let mut cells = bytes_cells(r.rx.previous, r.tx.previous, r.total.previous).to_vec();
+            cells.extend(change_cells(r.total));
+            Row::new(cells).style(row_style(i))
+        });
+        let widths = [
+            Constraint::Length(10),
+            Constraint::Length(10),
+            Constraint::Length(10),
+            Constraint::Length(11),
+            Constraint::Length(8),
+        ];
+        frame.render_widget(
+            Table::new(previous, widths)
+                .header(Row::new(header))
+                .block(block(previous_label, &c.previous)),
+            halves[1],
+        );
+    }
+}
+
+/// Delta and percentage cells for a change in usage: ▲ in the over-quota red
+/// for more traffic, ▼ in green for less.
+fn change_cells(change: compare::Change) -> [Cell<'static>; 2] {
+    let delta = change.delta();
+    let (arrow, color) = match delta.signum() {
+        1 => ("▲", theme::OVER),
+        -1 => ("▼", theme::TX),
+        _ => ("=", theme::HINT),
+    };
+    let sign = match delta.signum() {
+        1 => "+",
+        -1 => "-",
+        _ => "",
+    };
+    let percent = match change.percent() {
+        Some(p) => format!("{} {:.1}%", arrow, p.abs()),
+        None if delta > 0 => format!("{} new", arrow),
+        None => "–".to_string(),
+    };
+    [
+        Cell::from(format!("{}{}", sign, fmt_bytes(delta.abs()))).style(Style::default().fg(color)),
+        Cell::from(percent).style(Style::default().fg(color)),
+    ]
+}
+
+/// Heatmap cell shades above "no traffic".
+const HEATMAP_LEVELS: usize = 4;
+
+/// GitHub-style calendar: one column per week, one row per weekday (Monday
+/// first), each day shaded by its total relative to the busiest day.
+struct Heatmap<'a> {
+    days: &'a BTreeMap<NaiveDate, db::PeriodRow>,
+    first: NaiveDate,
+    last: NaiveDate,
+    cursor: NaiveDate,
+}
+
+impl Heatmap<'_> {
+    /// Weekday labels plus month labels above the grid.
+    const LABEL_WIDTH: u16 = 4;
+    const HEIGHT: u16 = 8;
+
+    fn level(&self, total_mib: f64, max: f64) -> usize {
+        if total_mib <= 0.0 || max <= 0.0 {
+            return 0;
+        }
+        ((total_mib / max * HEATMAP_LEVELS as f64).ceil() as usize).clamp(1, HEATMAP_LEVELS)
+    }
+}
+
+impl Widget for Heatmap<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let grid_width = area.width.saturating_sub(Self::LABEL_WIDTH);
+        // Two columns per week leaves a gap between cells when there's room
+        let weeks_total = ((self.last - self.first).num_days() / 7 + 1) as u16;
+        let cell = if grid_width >= weeks_total * 2 { 2 } else { 1 };
+        let weeks = (grid_width / cell).min(weeks_total) as i64;
+        if weeks == 0 || area.height < Self::HEIGHT {
+            return;
+        }
+        // Show the latest weeks, but keep the cursor's week on screen
+        let last_monday =
+            self.last - chrono::Duration::days(self.last.weekday().num_days_from_monday() as i64);
+        let cursor_monday = self.cursor
+            - chrono::Duration::days(self.cursor.weekday().num_days_from_monday() as i64);
+        let start = (last_monday - chrono::Duration::weeks(weeks - 1)).min(cursor_monday);
+        let max = self.days.values().map(|r| r.total_mib).fold(0.0, f64::max);
+
+        let label_style = Style::default().fg(theme::HINT);
+        for (row, name) in [(0, "Mon"), (2, "Wed"), (4, "Fri"), (6, "Sun")] {
+            buf.set_string(area.x, area.y + 1 + row, name, label_style);
+        }
+        let mut free_x = area.x + Self::LABEL_WIDTH;
+        for week in 0..weeks {
+            let monday = start + chrono::Duration::weeks(week);
+            let x = area.x + Self::LABEL_WIDTH + week as u16 * cell;
+            // Month label over the first week starting in that month
+            if (week == 0 || monday.day() <= 7) && x >= free_x && x + 3 <= area.right() {
+                buf.set_string(x, area.y, monday.format("%b").to_string(), label_style);
+                free_x = x + 4;
+            }
+            for weekday in 0..7 {
+                let day = monday + chrono::Duration::days(weekday);
+                if day < self.first || day > self.last {
+                    continue;
+                }
+                let total = self.days.get(&day).map_or(0.0, |r| r.total_mib);
+                let level = self.level(total, max);
+                let color = if level == 0 {
+                    theme::ROW_ALT
+                } else {
+                    theme::total_shade(level, HEATMAP_LEVELS)
+                };
+                let mut style = Style::default().fg(color);
+                if day == self.cursor {
+                    style = style.bg(theme::BORDER_FOCUS);
+                }
+                buf.set_string(x, area.y + 1 + weekday as u16, "■", style);
+            }
+        }
+    }
+}
+
+/// Draws the Calendar tab: the heatmap, its legend, and the selected day's
+/// totals next to the whole year's.
+fn render_calendar(frame: &mut Frame, area: Rect, app: &mut App) {
+    let (first, last) = calendar_range(Utc::now().date_naive());
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme::BORDER))
+        .title(Span::styled(
+            format!(" Calendar · {} → {} ", first, last),
             Style::default()
                 .fg(theme::TITLE)
                 .add_modifier(Modifier::BOLD),
         ));
-    let table = Table::new(table_rows, widths).header(header).block(block);
-    frame.render_widget(table, area);
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(Heatmap::HEIGHT),
+            Constraint::Length(3),
+            Constraint::Length(2),
+            Constraint::Min(0),
+        ])
+        .split(inner.inner(Margin::new(1, 0)));
+
+    frame.render_widget(
+        Heatmap {
+            days: &app.calendar,
+            first,
+            last,
+            cursor: app.calendar_cursor,
+        },
+        chunks[0],
+    );
+
+    let max = app
+        .calendar
+        .values()
+        .map(|r| r.total_mib)
+        .fold(0.0, f64::max);
+    let mut legend = vec![Span::styled("Less ", Style::default().fg(theme::HINT))];
+    legend.push(Span::styled("■ ", Style::default().fg(theme::ROW_ALT)));
+    for level in 1..=HEATMAP_LEVELS {
+        legend.push(Span::styled(
+            "■ ",
+            Style::default().fg(theme::total_shade(level, HEATMAP_LEVELS)),
+        ));
+    }
+    legend.push(Span::styled(
+        format!("More   (busiest day {:.2} MiB)", max),
+        Style::default().fg(theme::HINT),
+    ));
+    frame.render_widget(
+        Paragraph::new(vec![Line::default(), Line::from(legend), Line::default()]),
+        chunks[1],
+    );
+
+    let day = app.calendar_cursor;
+    let selected = match app.calendar.get(&day) {
+        Some(r) => vec![
+            Span::styled(
+                format!("{}  ", day.format("%a %Y-%m-%d")),
+                Style::default()
+                    .fg(theme::HEADER)
+                    .add_modifier(Modifier::BOLD),
+            ),
+            Span::styled(
+                format!("↓ {:.2}  ", r.rx_mib),
+                Style::default().fg(theme::RX),
+            ),
+            Span::styled(
+                format!("↑ {:.2}  ", r.tx_mib),
+                Style::default().fg(theme::TX),
+            ),
+            Span::styled(
+                format!("◆ {:.2} MiB", r.total_mib),
+                Style::default()
+                    .fg(theme::TOTAL)
+                    .add_modifier(Modifier::BOLD),
+            ),
+        ],
+        None => vec![
+            Span::styled(
+                format!("{}  ", day.format("%a %Y-%m-%d")),
+                Style::default()
+                    .fg(theme::HEADER)
+                    .add_modifier(Modifier::BOLD),
+            ),
+            Span::styled("no traffic", Style::default().fg(theme::HINT)),
+        ],
+    };
+    let year: f64 = app.calendar.values().map(|r| r.total_mib).sum();
+    let summary = Line::from(Span::styled(
+        format!(
+            "{} days with traffic  │  ◆ {:.2} MiB in all  │  {:.2} MiB a day on average",
+            app.calendar.len(),
+            year,
+            year / app.calendar.len().max(1) as f64
+        ),
+        Style::default().fg(theme::HINT),
+    ));
+    frame.render_widget(
+        Paragraph::new(vec![Line::from(selected), summary]),
+        chunks[2],
+    );
+}
+
+/// Draws the Profile tab: a weekday × hour matrix shaded like the Calendar,
+/// above bar charts of the average per hour of day and per weekday.
+fn render_profile(frame: &mut Frame, area: Rect, app: &mut App) {
+    let weeks = PROFILE_WINDOWS[app.profile_window];
+    let Some(p) = &app.profile else {
+        return;
+    };
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(10), Constraint::Min(0)])
+        .split(area);
+    let title_style = Style::default()
+        .fg(theme::TITLE)
+        .add_modifier(Modifier::BOLD);
+    let hint_style = Style::default().fg(theme::HINT);
+    let mib = |bytes: f64| bytes / (1024.0 * 1024.0);
+    let level = |bytes: f64, max: f64| {
+        if bytes <= 0.0 || max <= 0.0 {
+            0
+        } else {
+            ((bytes / max * HEATMAP_LEVELS as f64).ceil() as usize).clamp(1, HEATMAP_LEVELS)
+        }
+    };
+    let shade = |level: usize| {
+        if level == 0 {
+            theme::ROW_ALT
+        } else {
+            theme::total_shade(level, HEATMAP_LEVELS)
+        }
+    };
+
+    // Matrix: one row per weekday, one three-column cell per hour
+    let max = p.max_cell();
+    let mut lines = vec![Line::from(Span::styled(
+        format!(
+            "    {}",
+            (0..24).map(|h| format!("{:02} ", h)).collect::<String>()
+        ),
+        hint_style,
+    ))];
+    for (day, name) in profile::WEEKDAYS.iter().enumerate() {
+        let mut spans = vec![Span::styled(format!("{} ", name), hint_style)];
+        spans.extend(
+            p.cells[day]
+                .iter()
+                .map(|a| Span::styled("██ ", Style::default().fg(shade(level(a.total(), max))))),
+        );
+        lines.push(Line::from(spans));
+    }
+    let mut legend = vec![Span::styled(" Less ", hint_style)];
+    legend.extend((0..=HEATMAP_LEVELS).map(|l| Span::styled("■ ", Style::default().fg(shade(l)))));
+    legend.push(Span::styled(
+        format!("More · peak {:.2} MiB/h ", mib(max)),
+        hint_style,
+    ));
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme::BORDER))
+        .title(Span::styled(
+            format!(
+                " Profile · average per hour over {} weeks ({} → {}) ",
+                weeks,
+                p.from.format("%Y-%m-%d"),
+                p.to.format("%Y-%m-%d %H:%M")
+            ),
+            title_style,
+        ))
+        .title_bottom(Line::from(legend));
+    frame.render_widget(Paragraph::new(lines).block(block), chunks[0]);
+
+    // Bar charts, each bar shaded by its share of the busiest one
+    let bars = |averages: &[profile::Average], labels: &[String]| -> Vec<Bar<'static>> {
+        let top = averages.iter().map(|a| a.total()).fold(0.0, f64::max);
+        averages
+            .iter()
+            .zip(labels)
+            .map(|(a, label)| {
+                Bar::default()
+                    .value(a.total().round() as u64)
+                    .text_value(String::new())
+                    .label(Line::from(label.clone()))
+                    .style(Style::default().fg(shade(level(a.total(), top))))
+            })
+            .collect()
+    };
+    let chart = |title: String, bars: Vec<Bar<'static>>, width: u16, gap: u16| {
+        BarChart::default()
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(theme::BORDER))
+                    .title(Span::styled(title, title_style)),
+            )
+            .data(BarGroup::new(bars))
+            .bar_width(width)
+            .bar_gap(gap)
+            .label_style(hint_style)
+    };
+    let charts = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Length(24 * 2 + 2), Constraint::Min(0)])
+        .split(chunks[1]);
+
+    let hour = p.busiest_hour();
+    // Every third hour, so the labels don't run together
+    let labels: Vec<String> = (0..24)
+        .map(|h| {
+            if h % 3 == 0 {
+                format!("{:02}", h)
+            } else {
+                String::new()
+            }
+        })
+        .collect();
+    let title = format!(
+        " Per hour of day · peak {:02}:00, {:.2} MiB ",
+        hour,
+        mib(p.hours[hour].total())
+    );
+    frame.render_widget(chart(title, bars(&p.hours, &labels), 2, 0), charts[0]);
+
+    let day = p.busiest_weekday();
+    let labels: Vec<String> = profile::WEEKDAYS.iter().map(|d| d.to_string()).collect();
+    let title = format!(
+        " Per weekday · peak {}, {:.2} MiB/day ",
+        profile::WEEKDAYS[day],
+        mib(p.weekdays[day].total())
+    );
+    frame.render_widget(chart(title, bars(&p.weekdays, &labels), 3, 1), charts[1]);
+}
+
+fn render_quota(frame: &mut Frame, area: Rect, quotas: &[(quota::QuotaUsage, forecast::Forecast)]) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme::BORDER))
+        .title(Span::styled(
+            " Quota (current cycle) ",
+            Style::default()
+                .fg(theme::TITLE)
+                .add_modifier(Modifier::BOLD),
+        ));
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    if quotas.is_empty() {
+        frame.render_widget(
+            Paragraph::new(Line::from(Span::styled(
+                " No quotas defined. Add one with `ambr quota add <name> --limit 50GiB`.",
+                Style::default().fg(theme::HINT),
+            ))),
+            inner,
+        );
+        return;
+    }
+
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(quotas.iter().map(|_| Constraint::Length(5)))
+        .split(inner);
+
+    for ((u, f), row) in quotas.iter().zip(rows.iter()) {
+        let pct = u.percent();
+        let color = if pct >= 100.0 {
+            theme::OVER
+        } else {
+            theme::TOTAL
+        };
+        let ifaces = if u.quota.interfaces.is_empty() {
+            "all interfaces".to_string()
+        } else {
+            u.quota.interfaces.join(", ")
+        };
+        let info = Line::from(vec![
+            Span::styled(
+                format!(" {}  ", u.quota.name),
+                Style::default()
+                    .fg(theme::HEADER)
+                    .add_modifier(Modifier::BOLD),
+            ),
+            Span::styled(
+                format!(
+                    "{} → {}  │  {}  │  {}",
+                    u.cycle_start.date(),
+                    u.cycle_end.date(),
+                    u.quota.direction,
+                    ifaces
+                ),
+                Style::default().fg(theme::HINT),
+            ),
+        ]);
+        let mut detail = vec![
+            Span::styled(
+                format!(" used {}  ", fmt_bytes(u.used_bytes)),
+                Style::default().fg(color),
+            ),
+            Span::styled(
+                format!("remaining {}  ", fmt_bytes(u.remaining_bytes())),
+                Style::default().fg(theme::TX),
+            ),
+            Span::styled(
+                format!("limit {}", fmt_bytes(u.quota.limit_bytes)),
+                Style::default().fg(theme::HINT),
+            ),
+        ];
+        if !u.quota.free_windows.is_empty() {
+            detail.push(Span::styled(
+                format!(
+                    "  │  {} not counted ({})",
+                    fmt_bytes(u.free_bytes),
+                    describe_free_windows(&u.quota.free_windows)
+                ),
+                Style::default().fg(theme::RX),
+            ));
+        }
+        let detail = Line::from(detail);
+        let projected_color = if f.cap_hit.is_some() || u.used_bytes >= u.quota.limit_bytes {
+            theme::OVER
+        } else {
+            theme::HINT
+        };
+        let projection = Line::from(vec![
+            Span::styled(
+                format!(
+                    " projected {} by {}  ",
+                    fmt_bytes(f.projected_bytes),
+                    u.cycle_end.date()
+                ),
+                Style::default().fg(theme::HINT),
+            ),
+            Span::styled(describe_cap_hit(u, f), Style::default().fg(projected_color)),
+        ]);
+        let parts = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(1),
+                Constraint::Length(1),
+                Constraint::Length(1),
+                Constraint::Length(1),
+                Constraint::Length(1),
+            ])
+            .split(*row);
+        frame.render_widget(Paragraph::new(info), parts[0]);
+        frame.render_widget(
+            Gauge::default()
+                .gauge_style(Style::default().fg(color).bg(theme::ROW_ALT))
+                .ratio((pct / 100.0).clamp(0.0, 1.0))
+                .label(format!("{:.1}%", pct)),
+            parts[1],
+        );
+        frame.render_widget(Paragraph::new(detail), parts[2]);
+        frame.render_widget(Paragraph::new(projection), parts[3]);
+    }
 }
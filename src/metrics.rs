@@ -0,0 +1,170 @@
+//! Prometheus exporter for `ambr daemon --metrics <addr>`.
+//!
+//! Interface counters and recorder health come from the recorder's in-memory
+//! state, so a scrape never touches the `traffic` table. Quota gauges are read
+//! from the database at scrape time.
+
+use crate::db;
+use crate::quota::QuotaUsage;
+use crate::recorder::{InterfaceCounters, RecorderState, SharedState};
+use axum::Router;
+use axum::extract::State;
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use chrono::NaiveDateTime;
+use sqlx::SqlitePool;
+use std::fmt::Write;
+
+/// Prometheus text exposition format.
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+type CounterFn = fn(&InterfaceCounters) -> u64;
+type GaugeFn = fn(&QuotaUsage) -> f64;
+
+#[derive(Clone)]
+struct Exporter {
+    state: SharedState,
+    pool: SqlitePool,
+}
+
+/// Serves `GET /metrics` on `listener` until the task is dropped.
+pub async fn serve(
+    listener: tokio::net::TcpListener,
+    state: SharedState,
+    pool: SqlitePool,
+) -> std::io::Result<()> {
+    let app = Router::new()
+        .route("/metrics", get(scrape))
+        .with_state(Exporter { state, pool });
+    axum::serve(listener, app).await
+}
+
+async fn scrape(State(exporter): State<Exporter>) -> impl IntoResponse {
+    let now = chrono::Utc::now().naive_utc();
+    let snapshot = exporter.state.lock().map(|s| s.clone()).unwrap_or_default();
+    // A database hiccup should not take the interface counters down with it.
+    let quotas = db::quota_usages(&exporter.pool, now, db::HostFilter::Local)
+        .await
+        .unwrap_or_default();
+    let body = render(&snapshot, &quotas, now);
+    ([(header::CONTENT_TYPE, CONTENT_TYPE)], body)
+}
+
+/// Escapes a label value (backslash, double quote and newline).
+fn label(v: &str) -> String {
+    v.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn family(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Renders the exposition text for a state snapshot and quota usages.
+pub fn render(state: &RecorderState, quotas: &[QuotaUsage], now: NaiveDateTime) -> String {
+    let mut out = String::new();
+
+    let counters: [(&str, &str, CounterFn); 6] = [
+        ("ambr_rx_bytes_total", "Bytes received.", |c| c.rx_bytes),
+        ("ambr_tx_bytes_total", "Bytes transmitted.", |c| c.tx_bytes),
+        ("ambr_rx_packets_total", "Packets received.", |c| {
+            c.rx_packets
+        }),
+        ("ambr_tx_packets_total", "Packets transmitted.", |c| {
+            c.tx_packets
+        }),
+        ("ambr_rx_errors_total", "Receive errors.", |c| c.rx_errors),
+        ("ambr_tx_errors_total", "Transmit errors.", |c| c.tx_errors),
+    ];
+    for (name, help, value) in counters {
+        family(&mut out, name, "counter", help);
+        for (iface, c) in &state.interfaces {
+            let _ = writeln!(
+                out,
+                "{}{{interface=\"{}\"}} {}",
+                name,
+                label(iface),
+                value(c)
+            );
+        }
+    }
+
+    let gauges: [(&str, &str, GaugeFn); 4] = [
+        (
+            "ambr_quota_used_bytes",
+            "Billable bytes used in the current cycle.",
+            |u| u.used_bytes as f64,
+        ),
+        ("ambr_quota_limit_bytes", "Quota limit per cycle.", |u| {
+            u.quota.limit_bytes as f64
+        }),
+        (
+            "ambr_quota_used_ratio",
+            "Used share of the limit (1 = 100%).",
+            |u| u.percent() / 100.0,
+        ),
+        (
+            "ambr_quota_cycle_end_timestamp_seconds",
+            "When the current cycle resets (Unix time).",
+            |u| u.cycle_end.and_utc().timestamp() as f64,
+        ),
+    ];
+    if !quotas.is_empty() {
+        for (name, help, value) in gauges {
+            family(&mut out, name, "gauge", help);
+            for u in quotas {
+                let _ = writeln!(
+                    out,
+                    "{}{{quota=\"{}\"}} {}",
+                    name,
+                    label(&u.quota.name),
+                    value(u)
+                );
+            }
+        }
+    }
+
+    family(
+        &mut out,
+        "ambr_recorder_rounds_total",
+        "counter",
+        "Sampling rounds completed.",
+    );
+    let _ = writeln!(out, "ambr_recorder_rounds_total {}", state.rounds);
+    family(
+        &mut out,
+        "ambr_recorder_insert_errors_total",
+        "counter",
+        "Samples that could not be written to the database.",
+    );
+    let _ = writeln!(
+        out,
+        "ambr_recorder_insert_errors_total {}",
+        state.insert_errors
+    );
+    family(
+        &mut out,
+        "ambr_recorder_alert_errors_total",
+        "counter",
+        "Alert actions that failed, timed out or were dropped.",
+    );
+    let _ = writeln!(
+        out,
+        "ambr_recorder_alert_errors_total {}",
+        state.alert_errors
+    );
+    if let Some(last) = state.last_sample {
+        family(
+            &mut out,
+            "ambr_recorder_last_sample_age_seconds",
+            "gauge",
+            "Seconds since the last sampling round.",
+        );
+        let age = (now - last).num_milliseconds().max(0) as f64 / 1000.0;
+        let _ = writeln!(out, "ambr_recorder_last_sample_age_seconds {}", age);
+    }
+    out
+}
@@ -0,0 +1,430 @@
+//! MQTT publisher for `ambr daemon`.
+//!
+//! With an `[mqtt]` section in `config.toml`, the daemon publishes a JSON
+//! state message per interface every `interval_secs`: the current rates from
+//! the recorder and today's and this month's totals (UTC periods) from the
+//! database. Home Assistant discovery messages are published on connect, and
+//! for interfaces that appear later, so the sensors show up on their own.
+//! `<base>/status` carries `online`/`offline`, the latter as the last will.
+//!
+//! ```toml
+//! [mqtt]
+//! host = "broker.lan"
+//! username = "ambr"
+//! password = "s3cret"
+//! ```
+//!
+//! ```text
+//! ambr/<hostname>/status          online
+//! ambr/<hostname>/eth0/state      {"rx_rate":1250.0,"tx_rate":80.5,"today_rx":...}
+//! homeassistant/sensor/<node>/eth0_rx_rate/config
+//! ```
+
+use crate::db::{self, Granularity, HostFilter};
+use crate::push;
+use crate::recorder::{LiveRates, SharedState};
+use rumqttc::{AsyncClient, Event, EventLoop, LastWill, MqttOptions, Packet, QoS, Transport};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::SqlitePool;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+use std::time::Duration;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// Requests the client may queue while the event loop is busy; one round is
+/// seven messages per interface at most.
+const QUEUE: usize = 1024;
+
+fn default_interval() -> u64 {
+    30
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_discovery_prefix() -> String {
+    "homeassistant".to_string()
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MqttConfig {
+    /// Broker host name or address.
+    pub host: String,
+    /// Defaults to 1883, or 8883 with `tls`.
+    pub port: Option<u16>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    #[serde(default)]
+    pub tls: bool,
+    /// PEM CA certificate for the broker; the system roots otherwise.
+    pub ca_file: Option<PathBuf>,
+    /// PEM client certificate and key, for brokers that require them.
+    pub client_cert: Option<PathBuf>,
+    pub client_key: Option<PathBuf>,
+    /// Defaults to `ambr-<node>`.
+    pub client_id: Option<String>,
+    /// Topic everything is published under; defaults to `ambr/<node>`.
+    pub topic_prefix: Option<String>,
+    /// Seconds between state messages.
+    #[serde(default = "default_interval")]
+    pub interval_secs: u64,
+    /// Publish Home Assistant discovery messages.
+    #[serde(default = "default_true")]
+    pub discovery: bool,
+    #[serde(default = "default_discovery_prefix")]
+    pub discovery_prefix: String,
+}
+
+impl MqttConfig {
+    pub fn port(&self) -> u16 {
+        self.port.unwrap_or(if self.tls { 8883 } else { 1883 })
+    }
+}
+
+/// Makes `s` safe as a single topic level and as part of an object id:
+/// anything outside `[A-Za-z0-9_-]` becomes `_`.
+pub fn topic_segment(s: &str) -> String {
+    s.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// One message to publish.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub topic: String,
+    pub payload: String,
+    pub retain: bool,
+}
+
+/// Sensors per interface: state key, name, unit, device and state class.
+const SENSORS: [(&str, &str, &str, &str, &str); 6] = [
+    (
+        "rx_rate",
+        "download rate",
+        "B/s",
+        "data_rate",
+        "measurement",
+    ),
+    ("tx_rate", "upload rate", "B/s", "data_rate", "measurement"),
+    (
+        "today_rx",
+        "downloaded today",
+        "B",
+        "data_size",
+        "total_increasing",
+    ),
+    (
+        "today_tx",
+        "uploaded today",
+        "B",
+        "data_size",
+        "total_increasing",
+    ),
+    (
+        "month_rx",
+        "downloaded this month",
+        "B",
+        "data_size",
+        "total_increasing",
+    ),
+    (
+        "month_tx",
+        "uploaded this month",
+        "B",
+        "data_size",
+        "total_increasing",
+    ),
+];
+
+/// Topic layout for one machine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Topics {
+    pub hostname: String,
+    /// `hostname` as a topic level.
+    pub node: String,
+    pub base: String,
+    pub discovery_prefix: Option<String>,
+}
+
+impl Topics {
+    pub fn new(config: &MqttConfig, hostname: &str) -> Self {
+        let node = topic_segment(hostname);
+        Topics {
+            hostname: hostname.to_string(),
+            base: config
+                .topic_prefix
+                .as_deref()
+                .map(|p| p.trim_end_matches('/').to_string())
+                .unwrap_or_else(|| format!("ambr/{}", node)),
+            discovery_prefix: config
+                .discovery
+                .then(|| config.discovery_prefix.trim_end_matches('/').to_string()),
+            node,
+        }
+    }
+
+    pub fn status(&self) -> String {
+        format!("{}/status", self.base)
+    }
+
+    pub fn state(&self, interface: &str) -> String {
+        format!("{}/{}/state", self.base, topic_segment(interface))
+    }
+
+    /// Home Assistant discovery messages for `interface` (none with
+    /// discovery off).
+    pub fn discovery(&self, interface: &str) -> Vec<Message> {
+        let Some(prefix) = &self.discovery_prefix else {
+            return Vec::new();
+        };
+        let iface = topic_segment(interface);
+        SENSORS
+            .iter()
+            .map(|(key, name, unit, device_class, state_class)| {
+                let payload = json!({
+                    "name": format!("{} {}", interface, name),
+                    "unique_id": format!("ambr_{}_{}_{}", self.node, iface, key),
+                    "state_topic": self.state(interface),
+                    "value_template": format!("{{{{ value_json.{} }}}}", key),
+                    "unit_of_measurement": unit,
+                    "device_class": device_class,
+                    "state_class": state_class,
+                    "availability_topic": self.status(),
+                    "device": {
+                        "identifiers": [format!("ambr_{}", self.node)],
+                        "name": format!("ambr {}", self.hostname),
+                        "manufacturer": "ambr",
+                        "sw_version": env!("CARGO_PKG_VERSION"),
+                    },
+                });
+                Message {
+                    topic: format!("{}/sensor/{}/{}_{}/config", prefix, self.node, iface, key),
+                    payload: payload.to_string(),
+                    retain: true,
+                }
+            })
+            .collect()
+    }
+
+    /// State messages, by interface, for every interface with a rate or a
+    /// total. Interfaces without a rate this round (e.g. gone down) report zero.
+    pub fn states(
+        &self,
+        rates: &LiveRates,
+        today: &BTreeMap<String, (i64, i64)>,
+        month: &BTreeMap<String, (i64, i64)>,
+    ) -> Vec<(String, Message)> {
+        let mut names: BTreeSet<&str> = rates
+            .interfaces
+            .iter()
+            .map(|r| r.interface.as_str())
+            .collect();
+        names.extend(today.keys().map(String::as_str));
+        names.extend(month.keys().map(String::as_str));
+        names
+            .into_iter()
+            .map(|name| {
+                let rate = rates.interfaces.iter().find(|r| r.interface == name);
+                let (today_rx, today_tx) = today.get(name).copied().unwrap_or_default();
+                let (month_rx, month_tx) = month.get(name).copied().unwrap_or_default();
+                let payload = json!({
+                    "rx_rate": rate.map_or(0.0, |r| r.rx_bytes_per_sec),
+                    "tx_rate": rate.map_or(0.0, |r| r.tx_bytes_per_sec),
+                    "today_rx": today_rx,
+                    "today_tx": today_tx,
+                    "month_rx": month_rx,
+                    "month_tx": month_tx,
+                    "at": rates.at,
+                });
+                let message = Message {
+                    topic: self.state(name),
+                    payload: payload.to_string(),
+                    retain: true,
+                };
+                (name.to_string(), message)
+            })
+            .collect()
+    }
+}
+
+fn read(path: &PathBuf) -> Result<Vec<u8>, Error> {
+    std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e).into())
+}
+
+/// Connection options for `config`, with `topics.status()` as the last will.
+pub fn options(config: &MqttConfig, topics: &Topics) -> Result<MqttOptions, Error> {
+    let client_id = config
+        .client_id
+        .clone()
+        .unwrap_or_else(|| format!("ambr-{}", topics.node));
+    let mut options = MqttOptions::new(client_id, &config.host, config.port());
+    options.set_keep_alive(Duration::from_secs(30));
+    options.set_last_will(LastWill::new(
+        topics.status(),
+        "offline",
+        QoS::AtLeastOnce,
+        true,
+    ));
+    if let Some(username) = &config.username {
+        options.set_credentials(username, config.password.clone().unwrap_or_default());
+    }
+    if config.tls {
+        let client_auth = match (&config.client_cert, &config.client_key) {
+            (Some(cert), Some(key)) => Some((read(cert)?, read(key)?)),
+            (None, None) => None,
+            _ => return Err("mqtt: client_cert and client_key go together".into()),
+        };
+        let transport = match &config.ca_file {
+            Some(ca) => Transport::tls(read(ca)?, client_auth, None),
+            None if client_auth.is_none() => Transport::tls_with_default_config(),
+            None => return Err("mqtt: client certificates need ca_file".into()),
+        };
+        options.set_transport(transport);
+    } else if config.ca_file.is_some() || config.client_cert.is_some() {
+        return Err("mqtt: ca_file and client certificates need tls = true".into());
+    }
+    Ok(options)
+}
+
+pub struct Publisher {
+    pool: SqlitePool,
+    state: SharedState,
+    config: MqttConfig,
+    topics: Topics,
+    options: MqttOptions,
+}
+
+impl Publisher {
+    /// Fails on unreadable certificate files or inconsistent TLS settings.
+    pub fn new(pool: SqlitePool, state: SharedState, config: MqttConfig) -> Result<Self, Error> {
+        let hostname = push::HostIdentity::local().hostname;
+        let topics = Topics::new(&config, &hostname);
+        let options = options(&config, &topics)?;
+        Ok(Self {
+            pool,
+            state,
+            config,
+            topics,
+            options,
+        })
+    }
+
+    /// This round's state messages, from the recorder state and the local
+    /// host's rows.
+    pub async fn states(&self) -> Result<Vec<(String, Message)>, Error> {
+        let rates = self
+            .state
+            .lock()
+            .map(|s| LiveRates::from_state(&s))
+            .map_err(|_| "recorder state poisoned")?;
+        let now = chrono::Utc::now().naive_utc();
+        let today = Granularity::Day.period_start(now);
+        let month = Granularity::Month.period_start(now);
+        let today = db::bytes_by_interface(&self.pool, today, None, HostFilter::Local).await?;
+        let month = db::bytes_by_interface(&self.pool, month, None, HostFilter::Local).await?;
+        Ok(self.topics.states(&rates, &today, &month))
+    }
+
+    /// Queues this round's messages, announcing interfaces not yet in
+    /// `announced` first. An interface counts as announced once all of its
+    /// discovery messages are queued, so a full queue only delays discovery
+    /// to the next round. Messages that do not fit the queue are dropped; the
+    /// next round carries fresh ones.
+    async fn publish(
+        &self,
+        client: &AsyncClient,
+        announced: &mut BTreeSet<String>,
+    ) -> Result<(), Error> {
+        let states = self.states().await?;
+        for (iface, _) in &states {
+            if announced.contains(iface) {
+                continue;
+            }
+            for m in self.topics.discovery(iface) {
+                queue(client, m)?;
+            }
+            announced.insert(iface.clone());
+        }
+        for (_, state) in states {
+            queue(client, state)?;
+        }
+        Ok(())
+    }
+
+    /// Connects and publishes every `interval_secs` until the task is dropped,
+    /// reconnecting with backoff while the broker is unreachable. Failed
+    /// publishes, e.g. with the request queue full, are logged and skipped.
+    pub async fn run(self) -> Result<(), Error> {
+        let (client, mut events): (AsyncClient, EventLoop) =
+            AsyncClient::new(self.options.clone(), QUEUE);
+        let mut tick = tokio::time::interval(Duration::from_secs(self.config.interval_secs.max(1)));
+        tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        let mut connected = false;
+        let mut failures = 0;
+        let mut announced = BTreeSet::new();
+        loop {
+            tokio::select! {
+                event = events.poll() => match event {
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        connected = true;
+                        failures = 0;
+                        // A broker restart may have lost retained messages.
+                        announced.clear();
+                        let online = client.try_publish(
+                            self.topics.status(),
+                            QoS::AtLeastOnce,
+                            true,
+                            "online",
+                        );
+                        if let Err(e) = online {
+                            eprintln!("mqtt publish failed: {}", e);
+                        }
+                        if let Err(e) = self.publish(&client, &mut announced).await {
+                            eprintln!("mqtt publish failed: {}", e);
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        connected = false;
+                        failures += 1;
+                        let wait = push::backoff(failures);
+                        eprintln!(
+                            "mqtt {}:{}: {} (retry in {:?})",
+                            self.config.host,
+                            self.config.port(),
+                            e,
+                            wait
+                        );
+                        tokio::time::sleep(wait).await;
+                    }
+                },
+                _ = tick.tick(), if connected => {
+                    if let Err(e) = self.publish(&client, &mut announced).await {
+                        eprintln!("mqtt publish failed: {}", e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Queues `m` without waiting; fails when the request queue is full.
+fn queue(client: &AsyncClient, m: Message) -> Result<(), rumqttc::ClientError> {
+    let qos = if m.retain {
+        QoS::AtLeastOnce
+    } else {
+        QoS::AtMostOnce
+    };
+    client.try_publish(m.topic, qos, m.retain, m.payload)
+}
@@ -0,0 +1,128 @@
+//! Usage profiles: average traffic per hour of day and per weekday.
+//!
+//! An average divides the bytes recorded in a slot by how many times that
+//! slot occurs in the window, so quiet hours count as zero rather than being
+//! skipped. The window starts at the first recorded sample (rounded down to
+//! the day), so a database younger than the window isn't diluted by days
+//! before recording began.
+
+use crate::db::{self, Granularity, HostFilter};
+use chrono::{Datelike, Duration, NaiveDateTime, Timelike};
+use serde::Serialize;
+use sqlx::SqlitePool;
+
+pub const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// Average bytes in one slot.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Average {
+    pub rx_bytes: f64,
+    pub tx_bytes: f64,
+}
+
+impl Average {
+    pub fn total(&self) -> f64 {
+        self.rx_bytes + self.tx_bytes
+    }
+
+    fn of((rx, tx): (i64, i64), count: f64) -> Self {
+        if count <= 0.0 {
+            return Average::default();
+        }
+        Average {
+            rx_bytes: rx as f64 / count,
+            tx_bytes: tx as f64 / count,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Profile {
+    /// The window actually averaged over, `[from, to)`.
+    pub from: NaiveDateTime,
+    pub to: NaiveDateTime,
+    /// Bytes per hour, `[weekday][hour]`, Monday first.
+    pub cells: [[Average; 24]; 7],
+    /// Bytes per hour by hour of day, over all weekdays.
+    pub hours: [Average; 24],
+    /// Bytes per day by weekday, Monday first.
+    pub weekdays: [Average; 7],
+}
+
+impl Profile {
+    /// Hour of day with the most traffic on average.
+    pub fn busiest_hour(&self) -> usize {
+        busiest(&self.hours)
+    }
+
+    /// Weekday (Monday = 0) with the most traffic on average.
+    pub fn busiest_weekday(&self) -> usize {
+        busiest(&self.weekdays)
+    }
+
+    /// Largest average of any weekday and hour.
+    pub fn max_cell(&self) -> f64 {
+        self.cells
+            .iter()
+            .flatten()
+            .map(Average::total)
+            .fold(0.0, f64::max)
+    }
+}
+
+fn busiest(averages: &[Average]) -> usize {
+    averages
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.total().total_cmp(&b.1.total()))
+        .map_or(0, |(i, _)| i)
+}
+
+/// Average usage per weekday and hour of day over `[from, to)`, which should
+/// start and end on an hour.
+pub async fn usage_profile(
+    pool: &SqlitePool,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    host: HostFilter,
+) -> Result<Profile, sqlx::Error> {
+    let (bytes, first) = db::bytes_by_weekday_hour(pool, from, to, host).await?;
+    let from = first.map_or(to, |f| from.max(Granularity::Day.period_start(f)));
+
+    // How often each weekday and hour occurs in the window
+    let mut counts = [[0u32; 24]; 7];
+    let mut t = Granularity::Hour.period_start(from);
+    while t < to {
+        counts[t.weekday().num_days_from_monday() as usize][t.hour() as usize] += 1;
+        t += Duration::hours(1);
+    }
+
+    let mut cells = [[Average::default(); 24]; 7];
+    let mut hours = [Average::default(); 24];
+    let mut weekdays = [Average::default(); 7];
+    for hour in 0..24 {
+        let sum = (0..7).fold((0, 0), |(rx, tx), d| {
+            (rx + bytes[d][hour].0, tx + bytes[d][hour].1)
+        });
+        let count: u32 = (0..7).map(|d| counts[d][hour]).sum();
+        hours[hour] = Average::of(sum, count as f64);
+    }
+    for day in 0..7 {
+        for hour in 0..24 {
+            cells[day][hour] = Average::of(bytes[day][hour], counts[day][hour] as f64);
+        }
+        let sum = bytes[day]
+            .iter()
+            .fold((0, 0), |(rx, tx), b| (rx + b.0, tx + b.1));
+        let days = counts[day].iter().sum::<u32>() as f64 / 24.0;
+        weekdays[day] = Average::of(sum, days);
+    }
+
+    Ok(Profile {
+        from,
+        to,
+        cells,
+        hours,
+        weekdays,
+    })
+}
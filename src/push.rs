@@ -0,0 +1,200 @@
+//! Forwarding samples to a central `ambr server`.
+//!
+//! With a `[push]` section in `config.toml`, the daemon queues every delta it
+//! stores in the `outbox` table as well. A `Pusher` sends the queue in
+//! batches of gzip'd JSON and deletes what the collector acknowledged, so
+//! samples recorded while offline go out once it is reachable again. Failed
+//! sends are retried with exponential backoff.
+//!
+//! ```toml
+//! [push]
+//! url = "http://collector.lan:9193/ingest"
+//! token = "s3cret"
+//! ```
+
+use crate::db::{self, OutboxRow};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// First retry delay; doubled after every failure up to `MAX_BACKOFF`.
+pub const MIN_BACKOFF: Duration = Duration::from_secs(5);
+pub const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+fn default_interval() -> u64 {
+    60
+}
+
+fn default_batch_size() -> u32 {
+    1000
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PushConfig {
+    /// Collector endpoint, e.g. `http://host:9193/ingest`.
+    pub url: String,
+    /// Sent as a bearer token.
+    pub token: Option<String>,
+    /// Seconds between sends while the collector is reachable.
+    #[serde(default = "default_interval")]
+    pub interval_secs: u64,
+    /// Samples per request.
+    #[serde(default = "default_batch_size")]
+    pub batch_size: u32,
+    /// Overrides the name the collector shows for this machine.
+    pub hostname: Option<String>,
+    /// Overrides `/etc/machine-id`, e.g. for two daemons on one machine.
+    pub machine_id: Option<String>,
+    /// Free-form tags stored with the host, e.g. `{ site = "office" }`.
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+/// Who is pushing. The collector keys hosts by `machine_id`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostIdentity {
+    pub hostname: String,
+    pub machine_id: String,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+impl HostIdentity {
+    /// This machine: its hostname and systemd/D-Bus machine id, with the
+    /// hostname standing in where there is no machine id.
+    pub fn local() -> Self {
+        let hostname = sysinfo::System::host_name().unwrap_or_else(|| "localhost".to_string());
+        let machine_id = ["/etc/machine-id", "/var/lib/dbus/machine-id"]
+            .iter()
+            .find_map(|p| std::fs::read_to_string(p).ok())
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .unwrap_or_else(|| hostname.clone());
+        HostIdentity {
+            hostname,
+            machine_id,
+            labels: BTreeMap::new(),
+        }
+    }
+
+    /// `local()` with the overrides from `config` applied.
+    pub fn from_config(config: &PushConfig) -> Self {
+        let local = Self::local();
+        HostIdentity {
+            hostname: config.hostname.clone().unwrap_or(local.hostname),
+            machine_id: config.machine_id.clone().unwrap_or(local.machine_id),
+            labels: config.labels.clone(),
+        }
+    }
+}
+
+/// Request body of `POST /ingest` (before gzip).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Batch {
+    pub host: HostIdentity,
+    /// The pushing database's `db::database_id`; sequence numbers are only
+    /// comparable within one.
+    #[serde(default)]
+    pub database_id: Option<String>,
+    pub samples: Vec<OutboxRow>,
+}
+
+/// Response of `POST /ingest`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IngestReply {
+    pub stored: u64,
+    /// Samples the collector already had (a retried batch).
+    pub skipped: u64,
+}
+
+/// Delay before the next attempt after `failures` failed ones in a row.
+pub fn backoff(failures: u32) -> Duration {
+    let factor = 1u32 << failures.saturating_sub(1).min(16);
+    (MIN_BACKOFF * factor).min(MAX_BACKOFF)
+}
+
+/// Gzip'd JSON encoding of `batch`.
+pub fn encode(batch: &Batch) -> Result<Vec<u8>, Error> {
+    let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+    serde_json::to_writer(&mut gz, batch)?;
+    Ok(gz.finish()?)
+}
+
+pub struct Pusher {
+    pool: SqlitePool,
+    config: PushConfig,
+    host: HostIdentity,
+    http: reqwest::Client,
+}
+
+impl Pusher {
+    pub fn new(pool: SqlitePool, config: PushConfig) -> Self {
+        let host = HostIdentity::from_config(&config);
+        Self {
+            pool,
+            config,
+            host,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// Sends queued samples until the outbox is empty; returns how many were
+    /// acknowledged. Stops at the first failure, leaving the rest queued.
+    pub async fn flush(&self) -> Result<u64, Error> {
+        let mut sent = 0;
+        loop {
+            let samples = db::outbox_batch(&self.pool, self.config.batch_size.max(1)).await?;
+            let Some(last) = samples.last().map(|s| s.seq) else {
+                return Ok(sent);
+            };
+            let body = encode(&Batch {
+                host: self.host.clone(),
+                database_id: Some(db::database_id(&self.pool).await?),
+                samples,
+            })?;
+            let mut req = self
+                .http
+                .post(&self.config.url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(reqwest::header::CONTENT_ENCODING, "gzip")
+                .timeout(Duration::from_secs(30))
+                .body(body);
+            if let Some(token) = &self.config.token {
+                req = req.bearer_auth(token);
+            }
+            let resp = req.send().await?.error_for_status()?;
+            resp.json::<IngestReply>().await?;
+            sent += db::outbox_ack(&self.pool, last).await?;
+        }
+    }
+
+    /// Flushes every `interval_secs`, backing off while the collector fails.
+    pub async fn run(self) -> Result<(), Error> {
+        let interval = Duration::from_secs(self.config.interval_secs.max(1));
+        let mut failures = 0;
+        loop {
+            let wait = match self.flush().await {
+                Ok(_) => {
+                    failures = 0;
+                    interval
+                }
+                Err(e) => {
+                    failures += 1;
+                    let wait = backoff(failures);
+                    eprintln!(
+                        "push to {} failed: {} (retry in {:?})",
+                        self.config.url, e, wait
+                    );
+                    wait
+                }
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
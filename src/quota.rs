@@ -0,0 +1,176 @@
+//! Data cap quotas and billing cycles.
+//!
+//! Quotas are stored in the `quotas` table (see `db::add_quota`). Like the
+//! history tabs, cycle boundaries are computed in UTC.
+
+use crate::tariff::TimeWindow;
+use chrono::{Datelike, Months, NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Deserializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// Which side of the traffic counts against a quota.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Rx,
+    Tx,
+    Both,
+}
+
+impl Direction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Rx => "rx",
+            Direction::Tx => "tx",
+            Direction::Both => "both",
+        }
+    }
+
+    /// Bytes that count for this direction.
+    pub fn pick(&self, rx: i64, tx: i64) -> i64 {
+        match self {
+            Direction::Rx => rx,
+            Direction::Tx => tx,
+            Direction::Both => rx + tx,
+        }
+    }
+}
+
+impl FromStr for Direction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "rx" | "down" | "in" => Ok(Direction::Rx),
+            "tx" | "up" | "out" => Ok(Direction::Tx),
+            "both" | "total" => Ok(Direction::Both),
+            _ => Err(format!(
+                "unknown direction '{}' (expected rx, tx or both)",
+                s
+            )),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Direction {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        String::deserialize(d)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// How a quota's counting period is defined.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Cycle {
+    /// Resets every month on `start_day` (1–31; clamped to the month's last day).
+    Monthly { start_day: u32 },
+    /// A single fixed period `[from, to)`.
+    Custom {
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Quota {
+    pub name: String,
+    pub limit_bytes: i64,
+    /// Interfaces counted; empty means all interfaces.
+    pub interfaces: Vec<String>,
+    pub direction: Direction,
+    pub cycle: Cycle,
+    /// Windows whose traffic does not count, e.g. free nights (UTC).
+    pub free_windows: Vec<TimeWindow>,
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    let first = NaiveDate::from_ymd_opt(year, month, 1).expect("valid month");
+    let next = first + Months::new(1);
+    (next - first).num_days() as u32
+}
+
+/// Midnight of `start_day` in the given month, clamped to the month's last day.
+fn cycle_day(year: i32, month: u32, start_day: u32) -> NaiveDateTime {
+    let day = start_day.clamp(1, days_in_month(year, month));
+    NaiveDate::from_ymd_opt(year, month, day)
+        .expect("valid day")
+        .and_hms_opt(0, 0, 0)
+        .expect("valid time")
+}
+
+impl Quota {
+    /// The billing cycle `[start, end)` that contains `now`.
+    pub fn cycle_at(&self, now: NaiveDateTime) -> (NaiveDateTime, NaiveDateTime) {
+        match &self.cycle {
+            Cycle::Custom { from, to } => (*from, *to),
+            Cycle::Monthly { start_day } => {
+                let this_month = cycle_day(now.year(), now.month(), *start_day);
+                let start = if this_month <= now {
+                    this_month
+                } else {
+                    let prev = now.date().with_day(1).expect("day 1") - Months::new(1);
+                    cycle_day(prev.year(), prev.month(), *start_day)
+                };
+                let next = start.date().with_day(1).expect("day 1") + Months::new(1);
+                (start, cycle_day(next.year(), next.month(), *start_day))
+            }
+        }
+    }
+}
+
+/// A quota's usage within one billing cycle.
+#[derive(Debug, Clone)]
+pub struct QuotaUsage {
+    pub quota: Quota,
+    pub cycle_start: NaiveDateTime,
+    pub cycle_end: NaiveDateTime,
+    /// Billable bytes, i.e. outside the quota's free windows.
+    pub used_bytes: i64,
+    /// Bytes that fell inside a free window.
+    pub free_bytes: i64,
+}
+
+impl QuotaUsage {
+    pub fn remaining_bytes(&self) -> i64 {
+        (self.quota.limit_bytes - self.used_bytes).max(0)
+    }
+
+    /// Used share of the limit, in percent (may exceed 100).
+    pub fn percent(&self) -> f64 {
+        if self.quota.limit_bytes <= 0 {
+            return 0.0;
+        }
+        self.used_bytes as f64 / self.quota.limit_bytes as f64 * 100.0
+    }
+}
+
+/// Parses a byte size such as `500MB`, `50GiB`, `1.5 TB` or `1048576`.
+/// Decimal (kB, MB, GB, TB) and binary (KiB, MiB, GiB, TiB) suffixes are accepted.
+pub fn parse_size(s: &str) -> Result<i64, String> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (num, unit) = s.split_at(split);
+    let value: f64 = num.parse().map_err(|_| format!("invalid size '{}'", s))?;
+    let multiplier: f64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1.0,
+        "k" | "kb" => 1e3,
+        "m" | "mb" => 1e6,
+        "g" | "gb" => 1e9,
+        "t" | "tb" => 1e12,
+        "kib" => 1024.0,
+        "mib" => 1024.0 * 1024.0,
+        "gib" => 1024.0 * 1024.0 * 1024.0,
+        "tib" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        other => return Err(format!("unknown size unit '{}'", other)),
+    };
+    Ok((value * multiplier).round() as i64)
+}
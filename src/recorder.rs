@@ -1,31 +1,311 @@
+use crate::alert::{AlertEngine, AlertJob, Tick};
 use crate::db;
-use std::collections::HashMap;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use sysinfo::Networks;
+use tokio::sync::watch;
 
 pub async fn run_recorder(
     pool: sqlx::SqlitePool,
     interval_secs: u64,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let mut last: HashMap<String, (u64, u64)> = HashMap::new();
-    let interval = Duration::from_secs(interval_secs);
-
-    loop {
-        tokio::time::sleep(interval).await;
-        let networks = Networks::new_with_refreshed_list();
-
-        for (name, data) in &networks {
-            let rx = data.total_received();
-            let tx = data.total_transmitted();
-            if let Some(&(prev_rx, prev_tx)) = last.get(name.as_str()) {
-                let rx_delta = rx.saturating_sub(prev_rx) as i64;
-                let tx_delta = tx.saturating_sub(prev_tx) as i64;
-
-                if rx_delta >= 0 && tx_delta >= 0 {
-                    let _ = db::save_delta(&pool, name, &rx_delta, &tx_delta).await;
+    Recorder::new(pool, interval_secs).run().await
+}
+
+/// Cumulative OS counters for one interface, as of the last sample.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InterfaceCounters {
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub rx_packets: u64,
+    pub tx_packets: u64,
+    pub rx_errors: u64,
+    pub tx_errors: u64,
+}
+
+/// What the recorder knows without asking the database.
+#[derive(Debug, Clone, Default)]
+pub struct RecorderState {
+    pub interfaces: BTreeMap<String, InterfaceCounters>,
+    /// `(rx, tx)` bytes per interface in the last round.
+    pub last_deltas: BTreeMap<String, (i64, i64)>,
+    pub interval_secs: u64,
+    /// When the last sampling round finished (UTC).
+    pub last_sample: Option<NaiveDateTime>,
+    pub rounds: u64,
+    /// Failed `traffic` inserts since start.
+    pub insert_errors: u64,
+    /// Alert actions that failed, timed out or were dropped since start.
+    pub alert_errors: u64,
+    /// While set, counters are still sampled but nothing is stored and no
+    /// alerts are evaluated.
+    pub paused: bool,
+}
+
+/// Per-interface rate over the last round.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InterfaceRate {
+    pub interface: String,
+    pub rx_bytes_per_sec: f64,
+    pub tx_bytes_per_sec: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LiveRates {
+    /// End of the round the rates cover; `None` before the first round.
+    pub at: Option<NaiveDateTime>,
+    pub interval_secs: u64,
+    pub paused: bool,
+    pub interfaces: Vec<InterfaceRate>,
+}
+
+impl LiveRates {
+    pub fn from_state(state: &RecorderState) -> Self {
+        let secs = state.interval_secs.max(1) as f64;
+        LiveRates {
+            at: state.last_sample,
+            interval_secs: state.interval_secs,
+            paused: state.paused,
+            interfaces: state
+                .last_deltas
+                .iter()
+                .map(|(name, (rx, tx))| InterfaceRate {
+                    interface: name.clone(),
+                    rx_bytes_per_sec: *rx as f64 / secs,
+                    tx_bytes_per_sec: *tx as f64 / secs,
+                })
+                .collect(),
+        }
+    }
+
+    /// Sum over all interfaces as `(rx, tx)` bytes per second.
+    pub fn total(&self) -> (f64, f64) {
+        self.interfaces.iter().fold((0.0, 0.0), |(rx, tx), r| {
+            (rx + r.rx_bytes_per_sec, tx + r.tx_bytes_per_sec)
+        })
+    }
+}
+
+/// Recorder state shared with readers such as the metrics endpoint.
+pub type SharedState = Arc<Mutex<RecorderState>>;
+
+type SharedAlerts = Arc<tokio::sync::Mutex<Option<AlertEngine>>>;
+
+/// Alert jobs waiting for the action worker. Beyond that, jobs without
+/// enforcement are dropped (and counted); enforcement jobs wait for room.
+const ALERT_QUEUE: usize = 64;
+
+/// Samples interface counters every `interval_secs` and stores the deltas.
+pub struct Recorder {
+    pool: sqlx::SqlitePool,
+    interval_secs: u64,
+    alerts: SharedAlerts,
+    state: SharedState,
+    rounds: watch::Sender<u64>,
+    outbox: bool,
+    quiet: bool,
+}
+
+/// Controls a running recorder from other tasks (see `control`).
+#[derive(Clone)]
+pub struct RecorderHandle {
+    state: SharedState,
+    alerts: SharedAlerts,
+    rounds: watch::Receiver<u64>,
+}
+
+impl RecorderHandle {
+    pub fn snapshot(&self) -> RecorderState {
+        self.state.lock().map(|s| s.clone()).unwrap_or_default()
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        if let Ok(mut state) = self.state.lock() {
+            state.paused = paused;
+        }
+    }
+
+    /// Swaps in a new rule set; takes effect from the next round.
+    pub async fn replace_alerts(&self, engine: AlertEngine) {
+        *self.alerts.lock().await = Some(engine);
+    }
+
+    /// Receiver that changes after every sampling round.
+    pub fn rounds(&self) -> watch::Receiver<u64> {
+        self.rounds.clone()
+    }
+}
+
+impl Recorder {
+    pub fn new(pool: sqlx::SqlitePool, interval_secs: u64) -> Self {
+        Self {
+            pool,
+            interval_secs,
+            alerts: SharedAlerts::default(),
+            state: SharedState::default(),
+            rounds: watch::Sender::new(0),
+            outbox: false,
+            quiet: false,
+        }
+    }
+
+    pub fn handle(&self) -> RecorderHandle {
+        RecorderHandle {
+            state: self.state.clone(),
+            alerts: self.alerts.clone(),
+            rounds: self.rounds.subscribe(),
+        }
+    }
+
+    /// Handle to the live state, updated after every sampling round.
+    pub fn state(&self) -> SharedState {
+        self.state.clone()
+    }
+
+    /// Evaluates alert rules after each sampling round.
+    pub fn with_alerts(self, engine: AlertEngine) -> Self {
+        Self {
+            alerts: Arc::new(tokio::sync::Mutex::new(Some(engine))),
+            ..self
+        }
+    }
+
+    /// Also queues every stored sample in the outbox for `push`.
+    pub fn with_outbox(self) -> Self {
+        Self {
+            outbox: true,
+            ..self
+        }
+    }
+
+    /// Counts failed alert actions without printing them, for when stderr
+    /// is a terminal someone else draws on.
+    pub fn quiet(self) -> Self {
+        Self {
+            quiet: true,
+            ..self
+        }
+    }
+
+    /// Runs alert jobs one at a time, in the order their events happened, so
+    /// a slow action never holds up sampling and enforcement is never undone
+    /// before it was applied.
+    fn spawn_alert_worker(&self) -> tokio::sync::mpsc::Sender<AlertJob> {
+        let (jobs, mut queue) = tokio::sync::mpsc::channel::<AlertJob>(ALERT_QUEUE);
+        let state = self.state.clone();
+        let quiet = self.quiet;
+        tokio::spawn(async move {
+            while let Some(job) = queue.recv().await {
+                let rule = job.event.rule.clone();
+                let errors = job.run().await;
+                if errors.is_empty() {
+                    continue;
+                }
+                if let Ok(mut state) = state.lock() {
+                    state.alert_errors += errors.len() as u64;
+                }
+                if !quiet {
+                    for e in errors {
+                        eprintln!("alert {}: {}", rule, e);
+                    }
+                }
+            }
+        });
+        jobs
+    }
+
+    pub async fn run(self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let pool = self.pool.clone();
+        let mut last: HashMap<String, (u64, u64)> = HashMap::new();
+        let interval = Duration::from_secs(self.interval_secs);
+        let alert_jobs = self.spawn_alert_worker();
+
+        loop {
+            tokio::time::sleep(interval).await;
+            let networks = Networks::new_with_refreshed_list();
+            let paused = self.state.lock().map(|s| s.paused).unwrap_or(false);
+            let mut deltas = Vec::new();
+            let mut counters = BTreeMap::new();
+            let mut insert_errors = 0;
+            let at = chrono::Utc::now().naive_utc();
+
+            for (name, data) in &networks {
+                let rx = data.total_received();
+                let tx = data.total_transmitted();
+                if let Some(&(prev_rx, prev_tx)) = last.get(name.as_str()) {
+                    let rx_delta = rx.saturating_sub(prev_rx) as i64;
+                    let tx_delta = tx.saturating_sub(prev_tx) as i64;
+
+                    if rx_delta >= 0 && tx_delta >= 0 {
+                        if !paused {
+                            let saved = if self.outbox {
+                                db::save_delta_queued(&pool, name, &rx_delta, &tx_delta, at).await
+                            } else {
+                                db::save_delta(&pool, name, &rx_delta, &tx_delta).await
+                            };
+                            if saved.is_err() {
+                                insert_errors += 1;
+                            }
+                        }
+                        deltas.push((name.clone(), rx_delta, tx_delta));
+                    }
+                }
+                last.insert(name.clone(), (rx, tx));
+                counters.insert(
+                    name.clone(),
+                    InterfaceCounters {
+                        rx_bytes: rx,
+                        tx_bytes: tx,
+                        rx_packets: data.total_packets_received(),
+                        tx_packets: data.total_packets_transmitted(),
+                        rx_errors: data.total_errors_on_received(),
+                        tx_errors: data.total_errors_on_transmitted(),
+                    },
+                );
+            }
+
+            let now = chrono::Utc::now().naive_utc();
+            if let Ok(mut state) = self.state.lock() {
+                state.interfaces = counters;
+                state.last_deltas = deltas
+                    .iter()
+                    .map(|(name, rx, tx)| (name.clone(), (*rx, *tx)))
+                    .collect();
+                state.interval_secs = self.interval_secs;
+                state.last_sample = Some(now);
+                state.rounds += 1;
+                state.insert_errors += insert_errors;
+            }
+            self.rounds.send_modify(|r| *r += 1);
+
+            if paused {
+                continue;
+            }
+            if let Some(engine) = self.alerts.lock().await.as_mut() {
+                let tick = Tick {
+                    at: now,
+                    interval_secs: self.interval_secs,
+                    deltas,
+                };
+                for event in engine.evaluate(&pool, &tick).await {
+                    let job = engine.job(&event);
+                    if job.enforces() {
+                        // A lost job could leave an interface blocked, so
+                        // sampling waits until the worker catches up.
+                        let _ = alert_jobs.send(job).await;
+                    } else if alert_jobs.try_send(job).is_err() {
+                        if let Ok(mut state) = self.state.lock() {
+                            state.alert_errors += 1;
+                        }
+                        if !self.quiet {
+                            eprintln!("alert {}: action queue full, dropped", event.rule);
+                        }
+                    }
                 }
             }
-            last.insert(name.clone(), (rx, tx));
         }
     }
 }
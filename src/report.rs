@@ -0,0 +1,596 @@
+//! `ambr report`: usage summaries.
+//!
+//! `summary` is the plain-text / JSON report: today, yesterday, this month and
+//! last month (UTC) per interface, plus quota status with projections.
+//!
+//! `month_report` gathers one calendar month for `--html`: daily totals per
+//! interface via `db::for_each_export_row` and the quota cycles as of the
+//! month's end. `render_html` turns that into a single HTML file with inline
+//! CSS and SVG charts, so it can be mailed or opened offline.
+
+use crate::db::{self, Granularity, HostFilter, UsageFilter};
+use crate::forecast;
+use crate::quota::QuotaUsage;
+use chrono::{Datelike, Duration, Months, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+const RX: &str = "#00bfd8";
+const TX: &str = "#00e676";
+const TOTAL: &str = "#ffb72b";
+const OVER: &str = "#ff5555";
+const GRID: &str = "#d0d7de";
+
+/// Rows in the "top days" table.
+pub const TOP_DAYS: usize = 5;
+
+/// Parses a month as `YYYY-MM` into its first day.
+pub fn parse_month(s: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(&format!("{}-01", s.trim()), "%Y-%m-%d")
+        .map_err(|_| format!("invalid month '{}' (expected YYYY-MM)", s))
+}
+
+/// Byte counts for one period.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Totals {
+    pub rx_bytes: i64,
+    pub tx_bytes: i64,
+    pub total_bytes: i64,
+}
+
+impl Totals {
+    fn new((rx_bytes, tx_bytes): (i64, i64)) -> Self {
+        Totals {
+            rx_bytes,
+            tx_bytes,
+            total_bytes: rx_bytes + tx_bytes,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InterfacePeriods {
+    pub interface: String,
+    pub today: Totals,
+    pub yesterday: Totals,
+    pub this_month: Totals,
+    pub last_month: Totals,
+}
+
+impl InterfacePeriods {
+    /// The periods with their labels, in report order.
+    pub fn periods(&self) -> [(&'static str, Totals); 4] {
+        [
+            ("today", self.today),
+            ("yesterday", self.yesterday),
+            ("this month", self.this_month),
+            ("last month", self.last_month),
+        ]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QuotaStatus {
+    pub name: String,
+    pub used_bytes: i64,
+    pub limit_bytes: i64,
+    pub free_bytes: i64,
+    pub percent: f64,
+    pub cycle_start: NaiveDateTime,
+    pub cycle_end: NaiveDateTime,
+    pub projected_bytes: i64,
+    pub cap_hit: Option<NaiveDate>,
+    /// The limit is used up.
+    pub exceeded: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Summary {
+    pub generated: NaiveDateTime,
+    /// By name.
+    pub interfaces: Vec<InterfacePeriods>,
+    pub quotas: Vec<QuotaStatus>,
+}
+
+impl Summary {
+    /// Whether any quota is used up.
+    pub fn breached(&self) -> bool {
+        self.quotas.iter().any(|q| q.exceeded)
+    }
+}
+
+/// Today, yesterday, this month and last month per interface (all of them, or
+/// just `interface`), and every quota as of `now`.
+pub async fn summary(
+    pool: &SqlitePool,
+    now: NaiveDateTime,
+    interface: Option<&str>,
+) -> Result<Summary, sqlx::Error> {
+    let today = Granularity::Day.period_start(now);
+    let yesterday = Granularity::Day.periods_back(now, 1);
+    let this_month = Granularity::Month.period_start(now);
+    let last_month = Granularity::Month.periods_back(now, 1);
+    let ranges = [
+        (today, None),
+        (yesterday, Some(today)),
+        (this_month, None),
+        (last_month, Some(this_month)),
+    ];
+    let mut totals = Vec::with_capacity(ranges.len());
+    for (from, to) in ranges {
+        totals.push(db::bytes_by_interface(pool, from, to, HostFilter::All).await?);
+    }
+
+    let names: BTreeSet<&String> = totals.iter().flat_map(|t| t.keys()).collect();
+    let period =
+        |i: usize, name: &str| Totals::new(totals[i].get(name).copied().unwrap_or_default());
+    let interfaces = names
+        .into_iter()
+        .filter(|name| interface.is_none_or(|i| i == name.as_str()))
+        .map(|name| InterfacePeriods {
+            interface: name.clone(),
+            today: period(0, name),
+            yesterday: period(1, name),
+            this_month: period(2, name),
+            last_month: period(3, name),
+        })
+        .collect();
+
+    let mut quotas = Vec::new();
+    for u in db::quota_usages(pool, now, HostFilter::Local).await? {
+        let f = forecast::forecast_usage(pool, &u, now, HostFilter::Local).await?;
+        quotas.push(QuotaStatus {
+            percent: u.percent(),
+            exceeded: quota_exceeded(&u),
+            name: u.quota.name,
+            used_bytes: u.used_bytes,
+            limit_bytes: u.quota.limit_bytes,
+            free_bytes: u.free_bytes,
+            cycle_start: u.cycle_start,
+            cycle_end: u.cycle_end,
+            projected_bytes: f.projected_bytes,
+            cap_hit: f.cap_hit,
+        });
+    }
+
+    Ok(Summary {
+        generated: now.with_nanosecond(0).unwrap_or(now),
+        interfaces,
+        quotas,
+    })
+}
+
+fn quota_exceeded(u: &QuotaUsage) -> bool {
+    u.used_bytes >= u.quota.limit_bytes
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DayTotal {
+    pub date: NaiveDate,
+    pub rx_bytes: i64,
+    pub tx_bytes: i64,
+}
+
+impl DayTotal {
+    pub fn total(&self) -> i64 {
+        self.rx_bytes + self.tx_bytes
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceTotal {
+    pub interface: String,
+    pub rx_bytes: i64,
+    pub tx_bytes: i64,
+    /// Day with the most traffic on this interface.
+    pub busiest_day: Option<DayTotal>,
+}
+
+impl InterfaceTotal {
+    pub fn total(&self) -> i64 {
+        self.rx_bytes + self.tx_bytes
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MonthReport {
+    /// First day of the month.
+    pub month: NaiveDate,
+    pub generated: NaiveDateTime,
+    /// Every day of the month, zero where nothing was recorded.
+    pub days: Vec<DayTotal>,
+    /// Busiest interface first.
+    pub interfaces: Vec<InterfaceTotal>,
+    /// Quota cycles containing the month's last second (or now, for the
+    /// current month).
+    pub quotas: Vec<QuotaUsage>,
+}
+
+impl MonthReport {
+    /// `(rx, tx)` bytes over the month.
+    pub fn totals(&self) -> (i64, i64) {
+        self.days
+            .iter()
+            .fold((0, 0), |(rx, tx), d| (rx + d.rx_bytes, tx + d.tx_bytes))
+    }
+
+    /// Up to `n` days with traffic, busiest first.
+    pub fn top_days(&self, n: usize) -> Vec<DayTotal> {
+        let mut days: Vec<DayTotal> = self
+            .days
+            .iter()
+            .filter(|d| d.total() > 0)
+            .copied()
+            .collect();
+        days.sort_by(|a, b| b.total().cmp(&a.total()).then(a.date.cmp(&b.date)));
+        days.truncate(n);
+        days
+    }
+}
+
+/// Collects the report for the month starting on `month`.
+pub async fn month_report(
+    pool: &SqlitePool,
+    month: NaiveDate,
+    now: NaiveDateTime,
+) -> Result<MonthReport, sqlx::Error> {
+    let month = month.with_day(1).unwrap_or(month);
+    let from = month.and_time(NaiveTime::MIN);
+    let to = from + Months::new(1);
+
+    let mut per_day: BTreeMap<NaiveDate, (i64, i64)> = BTreeMap::new();
+    let mut per_iface: BTreeMap<String, Vec<DayTotal>> = BTreeMap::new();
+    db::for_each_export_row(
+        pool,
+        Some(Granularity::Day),
+        &UsageFilter::default(),
+        Some(from),
+        Some(to),
+        |row| {
+            let date = db::parse_time(&row.time)
+                .map_err(|e| sqlx::Error::Decode(e.into()))?
+                .date();
+            let day = per_day.entry(date).or_default();
+            day.0 += row.rx_bytes;
+            day.1 += row.tx_bytes;
+            per_iface.entry(row.interface).or_default().push(DayTotal {
+                date,
+                rx_bytes: row.rx_bytes,
+                tx_bytes: row.tx_bytes,
+            });
+            Ok(())
+        },
+    )
+    .await?;
+
+    let days = month
+        .iter_days()
+        .take_while(|d| *d < to.date())
+        .map(|date| {
+            let (rx_bytes, tx_bytes) = per_day.get(&date).copied().unwrap_or_default();
+            DayTotal {
+                date,
+                rx_bytes,
+                tx_bytes,
+            }
+        })
+        .collect();
+
+    let mut interfaces: Vec<InterfaceTotal> = per_iface
+        .into_iter()
+        .map(|(interface, days)| InterfaceTotal {
+            rx_bytes: days.iter().map(|d| d.rx_bytes).sum(),
+            tx_bytes: days.iter().map(|d| d.tx_bytes).sum(),
+            busiest_day: days
+                .iter()
+                .copied()
+                .max_by_key(|d| (d.total(), -d.date.num_days_from_ce())),
+            interface,
+        })
+        .collect();
+    interfaces.sort_by(|a, b| {
+        b.total()
+            .cmp(&a.total())
+            .then(a.interface.cmp(&b.interface))
+    });
+
+    let at = now.min(to - Duration::seconds(1));
+    Ok(MonthReport {
+        month,
+        generated: now,
+        days,
+        interfaces,
+        quotas: db::quota_usages(pool, at, HostFilter::Local).await?,
+    })
+}
+
+fn fmt_bytes(bytes: i64) -> String {
+    humansize::format_size(bytes.max(0) as u64, humansize::BINARY)
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn percent(part: i64, whole: i64) -> f64 {
+    if whole <= 0 {
+        0.0
+    } else {
+        part as f64 / whole as f64 * 100.0
+    }
+}
+
+const STYLE: &str = "\
+body{font-family:-apple-system,'Segoe UI',Helvetica,Arial,sans-serif;color:#1f2328;\
+max-width:800px;margin:2em auto;padding:0 1em;line-height:1.4}\
+h1{margin-bottom:0}h2{margin-top:2em;border-bottom:1px solid #d0d7de;padding-bottom:.2em}\
+.sub{color:#656d76;margin-top:.2em}\
+.cards{display:flex;gap:1em;flex-wrap:wrap}\
+.card{flex:1;min-width:150px;border:1px solid #d0d7de;border-radius:6px;padding:.6em 1em}\
+.card .label{color:#656d76;font-size:.9em}.card .value{font-size:1.5em;font-weight:600}\
+table{border-collapse:collapse;width:100%}\
+th,td{padding:.35em .6em;border-bottom:1px solid #d0d7de;text-align:right}\
+th:first-child,td:first-child{text-align:left}\
+th{background:#f6f8fa}.over{color:#cf222e;font-weight:600}\
+.legend span{display:inline-block;width:.8em;height:.8em;margin:0 .3em 0 1em;vertical-align:middle}\
+svg text{font-size:11px;fill:#656d76}\
+footer{margin-top:3em;color:#656d76;font-size:.85em}";
+
+/// Stacked rx/tx bars, one per day.
+fn daily_chart(out: &mut String, days: &[DayTotal]) {
+    const W: f64 = 760.0;
+    const H: f64 = 220.0;
+    const LEFT: f64 = 70.0;
+    const BOTTOM: f64 = 20.0;
+    let max = days.iter().map(DayTotal::total).max().unwrap_or(0);
+    let _ = write!(
+        out,
+        "<svg viewBox=\"0 0 {W} {H}\" width=\"100%\" role=\"img\" aria-label=\"Daily usage\">"
+    );
+    if max == 0 {
+        let _ = write!(
+            out,
+            "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">No traffic recorded</text></svg>",
+            W / 2.0,
+            H / 2.0
+        );
+        return;
+    }
+    let plot_h = H - BOTTOM - 10.0;
+    let y = |bytes: i64| 10.0 + plot_h * (1.0 - bytes as f64 / max as f64);
+    for i in 0..=4 {
+        let v = max * i / 4;
+        let _ = write!(
+            out,
+            "<line x1=\"{LEFT}\" x2=\"{W}\" y1=\"{0:.1}\" y2=\"{0:.1}\" stroke=\"{GRID}\"/>\
+             <text x=\"{1}\" y=\"{2:.1}\" text-anchor=\"end\">{3}</text>",
+            y(v),
+            LEFT - 6.0,
+            y(v) + 4.0,
+            escape(&fmt_bytes(v))
+        );
+    }
+    let slot = (W - LEFT) / days.len().max(1) as f64;
+    let bar = (slot * 0.7).max(1.0);
+    for (i, d) in days.iter().enumerate() {
+        let x = LEFT + slot * i as f64 + (slot - bar) / 2.0;
+        let (y_rx, y_total) = (y(d.rx_bytes), y(d.total()));
+        if d.total() > 0 {
+            let _ = write!(
+                out,
+                "<g><title>{}: {} (rx {}, tx {})</title>\
+                 <rect x=\"{x:.1}\" y=\"{y_rx:.1}\" width=\"{bar:.1}\" height=\"{:.1}\" fill=\"{RX}\"/>\
+                 <rect x=\"{x:.1}\" y=\"{y_total:.1}\" width=\"{bar:.1}\" height=\"{:.1}\" fill=\"{TX}\"/></g>",
+                d.date,
+                escape(&fmt_bytes(d.total())),
+                escape(&fmt_bytes(d.rx_bytes)),
+                escape(&fmt_bytes(d.tx_bytes)),
+                y(0) - y_rx,
+                y_rx - y_total,
+            );
+        }
+        if d.date.day() == 1 || d.date.day() % 5 == 0 {
+            let _ = write!(
+                out,
+                "<text x=\"{:.1}\" y=\"{}\" text-anchor=\"middle\">{}</text>",
+                x + bar / 2.0,
+                H - 4.0,
+                d.date.day()
+            );
+        }
+    }
+    out.push_str("</svg>");
+}
+
+/// Horizontal bars of each interface's share of the month.
+fn interface_chart(out: &mut String, interfaces: &[InterfaceTotal]) {
+    const W: f64 = 760.0;
+    const ROW: f64 = 24.0;
+    const LEFT: f64 = 120.0;
+    const RIGHT: f64 = 90.0;
+    let max = interfaces
+        .iter()
+        .map(InterfaceTotal::total)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let h = ROW * interfaces.len() as f64;
+    let _ = write!(
+        out,
+        "<svg viewBox=\"0 0 {W} {h}\" width=\"100%\" role=\"img\" aria-label=\"Usage by interface\">"
+    );
+    for (i, iface) in interfaces.iter().enumerate() {
+        let top = ROW * i as f64;
+        let scale = (W - LEFT - RIGHT) / max as f64;
+        let (rx_w, tx_w) = (iface.rx_bytes as f64 * scale, iface.tx_bytes as f64 * scale);
+        let _ = write!(
+            out,
+            "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"end\">{}</text>\
+             <rect x=\"{LEFT}\" y=\"{:.1}\" width=\"{rx_w:.1}\" height=\"{:.1}\" fill=\"{RX}\"/>\
+             <rect x=\"{:.1}\" y=\"{:.1}\" width=\"{tx_w:.1}\" height=\"{:.1}\" fill=\"{TX}\"/>\
+             <text x=\"{:.1}\" y=\"{:.1}\">{}</text>",
+            LEFT - 6.0,
+            top + 16.0,
+            escape(&iface.interface),
+            top + 4.0,
+            ROW - 8.0,
+            LEFT + rx_w,
+            top + 4.0,
+            ROW - 8.0,
+            LEFT + rx_w + tx_w + 6.0,
+            top + 16.0,
+            escape(&fmt_bytes(iface.total())),
+        );
+    }
+    out.push_str("</svg>");
+}
+
+/// A used/limit gauge for one quota.
+fn quota_gauge(out: &mut String, usage: &QuotaUsage) {
+    const W: f64 = 760.0;
+    let pct = usage.percent();
+    let fill = if pct >= 100.0 { OVER } else { TOTAL };
+    let _ = write!(
+        out,
+        "<svg viewBox=\"0 0 {W} 14\" width=\"100%\" role=\"img\" aria-label=\"{pct:.1}% used\">\
+         <rect width=\"{W}\" height=\"14\" rx=\"3\" fill=\"#eaeef2\"/>\
+         <rect width=\"{:.1}\" height=\"14\" rx=\"3\" fill=\"{fill}\"/></svg>",
+        W * pct.min(100.0) / 100.0
+    );
+}
+
+/// Renders `report` as a self-contained HTML document.
+pub fn render_html(report: &MonthReport) -> String {
+    let title = format!("Network usage – {}", report.month.format("%B %Y"));
+    let (rx, tx) = report.totals();
+    let total = rx + tx;
+    let active = report.days.iter().filter(|d| d.total() > 0).count();
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{0}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n<h1>{0}</h1>\n\
+         <p class=\"sub\">{1} to {2} (UTC)</p>\n",
+        escape(&title),
+        report.month,
+        report.days.last().map_or(report.month, |d| d.date),
+    );
+
+    out.push_str("<div class=\"cards\">");
+    for (label, value) in [
+        ("Total", fmt_bytes(total)),
+        ("Downloaded", fmt_bytes(rx)),
+        ("Uploaded", fmt_bytes(tx)),
+        (
+            "Daily average",
+            fmt_bytes(if active == 0 {
+                0
+            } else {
+                total / active as i64
+            }),
+        ),
+    ] {
+        let _ = write!(
+            out,
+            "<div class=\"card\"><div class=\"label\">{}</div><div class=\"value\">{}</div></div>",
+            label,
+            escape(&value)
+        );
+    }
+    out.push_str("</div>\n");
+
+    let _ = write!(
+        out,
+        "<h2>Daily usage</h2>\n<p class=\"legend\"><span style=\"background:{RX}\"></span>Downloaded\
+         <span style=\"background:{TX}\"></span>Uploaded</p>\n"
+    );
+    daily_chart(&mut out, &report.days);
+
+    out.push_str("\n<h2>By interface</h2>\n");
+    if report.interfaces.is_empty() {
+        out.push_str("<p>No traffic recorded this month.</p>\n");
+    } else {
+        interface_chart(&mut out, &report.interfaces);
+        out.push_str(
+            "\n<table>\n<tr><th>Interface</th><th>Downloaded</th><th>Uploaded</th>\
+             <th>Total</th><th>Share</th><th>Busiest day</th></tr>\n",
+        );
+        for iface in &report.interfaces {
+            let busiest = iface.busiest_day.map_or(String::new(), |d| {
+                format!("{} ({})", d.date.format("%b %-d"), fmt_bytes(d.total()))
+            });
+            let _ = writeln!(
+                out,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:.1}%</td><td>{}</td></tr>",
+                escape(&iface.interface),
+                escape(&fmt_bytes(iface.rx_bytes)),
+                escape(&fmt_bytes(iface.tx_bytes)),
+                escape(&fmt_bytes(iface.total())),
+                percent(iface.total(), total),
+                escape(&busiest),
+            );
+        }
+        out.push_str("</table>\n");
+    }
+
+    let top = report.top_days(TOP_DAYS);
+    if !top.is_empty() {
+        out.push_str(
+            "<h2>Top days</h2>\n<table>\n<tr><th>Day</th><th>Downloaded</th>\
+             <th>Uploaded</th><th>Total</th></tr>\n",
+        );
+        for d in top {
+            let _ = writeln!(
+                out,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                d.date.format("%a %b %-d"),
+                escape(&fmt_bytes(d.rx_bytes)),
+                escape(&fmt_bytes(d.tx_bytes)),
+                escape(&fmt_bytes(d.total())),
+            );
+        }
+        out.push_str("</table>\n");
+    }
+
+    if !report.quotas.is_empty() {
+        out.push_str("<h2>Quotas</h2>\n");
+        for u in &report.quotas {
+            let over = quota_exceeded(u);
+            let _ = writeln!(
+                out,
+                "<p><strong>{}</strong>: {} of {} <span class=\"{}\">({:.1}%)</span>, \
+                 cycle {} to {}</p>",
+                escape(&u.quota.name),
+                escape(&fmt_bytes(u.used_bytes)),
+                escape(&fmt_bytes(u.quota.limit_bytes)),
+                if over { "over" } else { "" },
+                u.percent(),
+                u.cycle_start.date(),
+                (u.cycle_end - Duration::seconds(1)).date(),
+            );
+            quota_gauge(&mut out, u);
+        }
+    }
+
+    let _ = write!(
+        out,
+        "\n<footer>Generated by ambr {} on {} UTC.</footer>\n</body>\n</html>\n",
+        env!("CARGO_PKG_VERSION"),
+        report.generated.format("%Y-%m-%d %H:%M"),
+    );
+    out
+}
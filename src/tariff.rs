@@ -0,0 +1,288 @@
+//! Cost estimation with time-of-day tariffs.
+//!
+//! Costs are computed from hourly buckets. A bucket that straddles a window
+//! boundary is split pro rata by time, so 07:00–08:00 with peak starting at
+//! 07:30 is charged half off-peak and half peak. Like the rest of ambr,
+//! windows are in UTC.
+
+use crate::alert::de_size;
+use crate::db::{self, Granularity, HostFilter};
+use crate::quota::Direction;
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Deserializer};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+const GIB: f64 = 1024.0 * 1024.0 * 1024.0;
+const DAY_SECS: u32 = 24 * 60 * 60;
+const WEEKDAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+/// A daily time window on selected weekdays, e.g. `Mon-Fri 18:00-23:00`,
+/// `Sat,Sun 00:00-24:00` or `22:00-06:00` (every day; wraps past midnight).
+/// For windows that wrap, the weekday is the day the window starts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeWindow {
+    /// Monday first.
+    pub days: [bool; 7],
+    /// Seconds after midnight.
+    pub start: u32,
+    /// Seconds after midnight; `<= start` means the window ends the next day.
+    pub end: u32,
+}
+
+fn parse_weekday(s: &str) -> Result<usize, String> {
+    let key = s.trim().to_ascii_lowercase();
+    WEEKDAYS
+        .iter()
+        .position(|d| key.starts_with(d))
+        .ok_or_else(|| format!("unknown weekday '{}'", s))
+}
+
+fn parse_clock(s: &str) -> Result<u32, String> {
+    let (h, m) = s
+        .trim()
+        .split_once(':')
+        .ok_or_else(|| format!("expected HH:MM, got '{}'", s))?;
+    let h: u32 = h.parse().map_err(|_| format!("invalid hour in '{}'", s))?;
+    let m: u32 = m
+        .parse()
+        .map_err(|_| format!("invalid minute in '{}'", s))?;
+    if m >= 60 || h > 24 || (h == 24 && m != 0) {
+        return Err(format!("invalid time '{}'", s));
+    }
+    Ok(h * 3600 + m * 60)
+}
+
+impl FromStr for TimeWindow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (days_part, time_part) = match s.rsplit_once(' ') {
+            Some((d, t)) => (Some(d), t),
+            None => (None, s),
+        };
+
+        let mut days = [days_part.is_none(); 7];
+        for item in days_part
+            .unwrap_or("")
+            .split(',')
+            .filter(|p| !p.trim().is_empty())
+        {
+            match item.split_once('-') {
+                Some((a, b)) => {
+                    let (a, b) = (parse_weekday(a)?, parse_weekday(b)?);
+                    let mut d = a;
+                    loop {
+                        days[d] = true;
+                        if d == b {
+                            break;
+                        }
+                        d = (d + 1) % 7;
+                    }
+                }
+                None => days[parse_weekday(item)?] = true,
+            }
+        }
+
+        let (start, end) = time_part
+            .split_once('-')
+            .ok_or_else(|| format!("expected HH:MM-HH:MM, got '{}'", time_part))?;
+        let (start, end) = (parse_clock(start)?, parse_clock(end)?);
+        if start >= DAY_SECS {
+            return Err(format!("window cannot start at 24:00 ('{}')", s));
+        }
+        Ok(TimeWindow { days, start, end })
+    }
+}
+
+impl fmt::Display for TimeWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.days.iter().all(|d| *d) {
+            let names: Vec<&str> = (0..7)
+                .filter(|d| self.days[*d])
+                .map(|d| WEEKDAYS[d])
+                .collect();
+            write!(f, "{} ", names.join(","))?;
+        }
+        write!(
+            f,
+            "{:02}:{:02}-{:02}:{:02}",
+            self.start / 3600,
+            self.start / 60 % 60,
+            self.end / 3600,
+            self.end / 60 % 60
+        )
+    }
+}
+
+impl<'de> Deserialize<'de> for TimeWindow {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        String::deserialize(d)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl TimeWindow {
+    /// Occurrences of the window as `[start, end)` intervals that may touch `[from, to)`.
+    fn occurrences(
+        &self,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> impl Iterator<Item = (NaiveDateTime, NaiveDateTime)> + '_ {
+        let first = from.date() - Duration::days(1);
+        let days = (to.date() - first).num_days() + 1;
+        (0..days).filter_map(move |i| {
+            let day = first + Duration::days(i);
+            if !self.days[day.weekday().num_days_from_monday() as usize] {
+                return None;
+            }
+            let midnight = day.and_time(chrono::NaiveTime::MIN);
+            let start = midnight + Duration::seconds(self.start as i64);
+            let end_day = if self.end <= self.start { 1 } else { 0 };
+            let end = midnight + Duration::days(end_day) + Duration::seconds(self.end as i64);
+            Some((start, end))
+        })
+    }
+
+    pub fn contains(&self, t: NaiveDateTime) -> bool {
+        self.occurrences(t, t).any(|(s, e)| s <= t && t < e)
+    }
+
+    /// Window boundaries strictly inside `(from, to)`.
+    fn boundaries(&self, from: NaiveDateTime, to: NaiveDateTime) -> Vec<NaiveDateTime> {
+        self.occurrences(from, to)
+            .flat_map(|(s, e)| [s, e])
+            .filter(|t| *t > from && *t < to)
+            .collect()
+    }
+}
+
+/// Splits `[from, to)` at every boundary of `windows`.
+pub(crate) fn split_at_boundaries(
+    windows: &[&TimeWindow],
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+    let mut points: Vec<NaiveDateTime> = windows
+        .iter()
+        .flat_map(|w| w.boundaries(from, to))
+        .collect();
+    points.push(from);
+    points.push(to);
+    points.sort();
+    points.dedup();
+    points.windows(2).map(|p| (p[0], p[1])).collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TariffWindow {
+    pub when: TimeWindow,
+    pub price_per_gib: f64,
+}
+
+fn default_direction() -> Direction {
+    Direction::Both
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Tariff {
+    pub name: String,
+    pub currency: String,
+    /// Price outside every window.
+    pub price_per_gib: f64,
+    /// Interfaces billed by this tariff; empty means all.
+    #[serde(default)]
+    pub interfaces: Vec<String>,
+    #[serde(default = "default_direction")]
+    pub direction: Direction,
+    /// Bytes per calendar month that are not charged. Only charged traffic
+    /// uses it up, so free-window traffic does not.
+    #[serde(default, deserialize_with = "de_size")]
+    pub free_allowance: i64,
+    /// Special rates; the first matching window wins.
+    #[serde(default, rename = "window")]
+    pub windows: Vec<TariffWindow>,
+}
+
+impl Tariff {
+    /// Price per GiB at `t`.
+    pub fn price_at(&self, t: NaiveDateTime) -> f64 {
+        self.windows
+            .iter()
+            .find(|w| w.when.contains(t))
+            .map(|w| w.price_per_gib)
+            .unwrap_or(self.price_per_gib)
+    }
+
+    /// Costs of consecutive hourly buckets `(hour_start, bytes)`, oldest first.
+    /// The free allowance resets at the start of each calendar month.
+    pub fn hourly_costs(&self, buckets: &[(NaiveDateTime, i64)]) -> Vec<f64> {
+        let windows: Vec<&TimeWindow> = self.windows.iter().map(|w| &w.when).collect();
+        let mut month = None;
+        let mut allowance = 0.0;
+
+        buckets
+            .iter()
+            .map(|&(start, bytes)| {
+                let this_month = (start.year(), start.month());
+                if month != Some(this_month) {
+                    month = Some(this_month);
+                    allowance = self.free_allowance as f64;
+                }
+                let end = start + Duration::hours(1);
+                split_at_boundaries(&windows, start, end)
+                    .into_iter()
+                    .map(|(from, to)| {
+                        let share = (to - from).num_seconds() as f64 / 3600.0;
+                        let seg_bytes = bytes as f64 * share;
+                        let price = self.price_at(from);
+                        if price <= 0.0 {
+                            return 0.0;
+                        }
+                        let free = seg_bytes.min(allowance);
+                        allowance -= free;
+                        (seg_bytes - free) / GIB * price
+                    })
+                    .sum()
+            })
+            .collect()
+    }
+}
+
+/// Start of the month containing `t`.
+fn month_start(t: NaiveDateTime) -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(t.year(), t.month(), 1)
+        .expect("day 1")
+        .and_time(chrono::NaiveTime::MIN)
+}
+
+/// Cost per period label (same labels as `usage_by_*`) for traffic in
+/// `[from, to)`; pass a period start as `from` to get whole periods.
+pub async fn cost_by_period(
+    pool: &SqlitePool,
+    tariff: &Tariff,
+    granularity: Granularity,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    host: HostFilter,
+) -> Result<HashMap<String, f64>, sqlx::Error> {
+    // Start at the month boundary so the free allowance is tracked correctly.
+    let rows = db::hourly_bytes(pool, month_start(from), to, &tariff.interfaces, host).await?;
+    let buckets: Vec<(NaiveDateTime, i64)> = rows
+        .iter()
+        .map(|(h, rx, tx)| (*h, tariff.direction.pick(*rx, *tx)))
+        .collect();
+    let costs = tariff.hourly_costs(&buckets);
+
+    let mut out = HashMap::new();
+    for ((hour, _), cost) in buckets.iter().zip(costs) {
+        if *hour + Duration::hours(1) > from {
+            *out.entry(granularity.label(*hour)).or_insert(0.0) += cost;
+        }
+    }
+    Ok(out)
+}
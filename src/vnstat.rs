@@ -0,0 +1,259 @@
+//! History import from vnStat 2.x.
+//!
+//! Reads either vnStat's SQLite database (`/var/lib/vnstat/vnstat.db`) or the
+//! output of `vnstat --json`. Every five-minute, hour, day, month and year
+//! entry becomes a `db::PeriodTotal`, and `db::import_periods` stores the
+//! finest ones first: a month entry only adds what its days (and ambr's own
+//! samples) do not already account for, which is what is left once vnStat
+//! has pruned the finer tables.
+//!
+//! vnStat writes local time unless `UseUTC` is set; `Clock` says which.
+
+use crate::db::{self, PeriodTotal};
+use chrono::{DateTime, Duration, Months, NaiveDate, NaiveDateTime, TimeZone};
+use serde::Deserialize;
+use sqlx::SqlitePool;
+use sqlx::sqlite::SqliteConnectOptions;
+use std::io::Read;
+use std::path::Path;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// Where vnStat keeps its database by default.
+pub const DEFAULT_DB_PATH: &str = "/var/lib/vnstat/vnstat.db";
+
+/// Time zone of the dates vnStat stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Clock {
+    /// This machine's local time (vnStat's default).
+    Local,
+    /// vnStat ran with `UseUTC 1`.
+    Utc,
+}
+
+impl Clock {
+    fn to_utc(self, t: NaiveDateTime) -> NaiveDateTime {
+        match self {
+            Clock::Utc => t,
+            // In a DST gap the wall-clock time never happened; keep it as is.
+            Clock::Local => chrono::Local
+                .from_local_datetime(&t)
+                .earliest()
+                .map(|d| d.naive_utc())
+                .unwrap_or(t),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Level {
+    FiveMinute,
+    Hour,
+    Day,
+    Month,
+    Year,
+}
+
+const LEVELS: [Level; 5] = [
+    Level::FiveMinute,
+    Level::Hour,
+    Level::Day,
+    Level::Month,
+    Level::Year,
+];
+
+impl Level {
+    fn table(self) -> &'static str {
+        match self {
+            Level::FiveMinute => "fiveminute",
+            Level::Hour => "hour",
+            Level::Day => "day",
+            Level::Month => "month",
+            Level::Year => "year",
+        }
+    }
+
+    fn end(self, start: NaiveDateTime) -> NaiveDateTime {
+        match self {
+            Level::FiveMinute => start + Duration::minutes(5),
+            Level::Hour => start + Duration::hours(1),
+            Level::Day => start + Duration::days(1),
+            Level::Month => start + Months::new(1),
+            Level::Year => start + Months::new(12),
+        }
+    }
+}
+
+fn period(
+    interface: &str,
+    level: Level,
+    local_start: NaiveDateTime,
+    clock: Clock,
+    rx_bytes: i64,
+    tx_bytes: i64,
+) -> PeriodTotal {
+    PeriodTotal {
+        interface: interface.to_string(),
+        start: clock.to_utc(local_start),
+        end: clock.to_utc(level.end(local_start)),
+        rx_bytes,
+        tx_bytes,
+    }
+}
+
+/// Reads every entry of a vnStat 2.x database.
+pub async fn read_db(path: &Path, clock: Clock) -> Result<Vec<PeriodTotal>, Error> {
+    let options = SqliteConnectOptions::new().filename(path).read_only(true);
+    let pool = SqlitePool::connect_with(options).await?;
+    let (tables,): (i64,) = sqlx::query_as(
+        "SELECT COUNT(*) FROM sqlite_master \
+         WHERE type = 'table' AND name IN ('info', 'interface', 'day')",
+    )
+    .fetch_one(&pool)
+    .await?;
+    if tables != 3 {
+        return Err(format!("{} is not a vnStat 2.x database", path.display()).into());
+    }
+
+    let mut out = Vec::new();
+    for level in LEVELS {
+        let rows: Vec<(String, String, i64, i64)> = sqlx::query_as(&format!(
+            "SELECT i.name, CAST(t.date AS TEXT), t.rx, t.tx \
+             FROM {} AS t JOIN interface AS i ON i.id = t.interface \
+             ORDER BY t.date",
+            level.table()
+        ))
+        .fetch_all(&pool)
+        .await?;
+        for (interface, date, rx, tx) in rows {
+            let start = db::parse_time(&date)?;
+            out.push(period(&interface, level, start, clock, rx, tx));
+        }
+    }
+    pool.close().await;
+    Ok(out)
+}
+
+#[derive(Deserialize)]
+struct JsonRoot {
+    jsonversion: String,
+    interfaces: Vec<JsonInterface>,
+}
+
+#[derive(Deserialize)]
+struct JsonInterface {
+    name: String,
+    traffic: JsonTraffic,
+}
+
+#[derive(Deserialize)]
+struct JsonTraffic {
+    #[serde(default)]
+    fiveminute: Vec<JsonEntry>,
+    #[serde(default)]
+    hour: Vec<JsonEntry>,
+    #[serde(default)]
+    day: Vec<JsonEntry>,
+    #[serde(default)]
+    month: Vec<JsonEntry>,
+    #[serde(default)]
+    year: Vec<JsonEntry>,
+}
+
+#[derive(Deserialize)]
+struct JsonEntry {
+    date: JsonDate,
+    time: Option<JsonTime>,
+    /// Unix time of the period start; vnStat 2.x sets it on most entries.
+    timestamp: Option<i64>,
+    rx: i64,
+    tx: i64,
+}
+
+fn first() -> u32 {
+    1
+}
+
+#[derive(Deserialize)]
+struct JsonDate {
+    year: i32,
+    #[serde(default = "first")]
+    month: u32,
+    #[serde(default = "first")]
+    day: u32,
+}
+
+#[derive(Deserialize)]
+struct JsonTime {
+    hour: u32,
+    minute: u32,
+}
+
+impl JsonEntry {
+    fn local_start(&self) -> Option<NaiveDateTime> {
+        let (hour, minute) = self.time.as_ref().map_or((0, 0), |t| (t.hour, t.minute));
+        NaiveDate::from_ymd_opt(self.date.year, self.date.month, self.date.day)?
+            .and_hms_opt(hour, minute, 0)
+    }
+}
+
+/// Reads `vnstat --json` output (JSON version 2, byte counts).
+pub fn parse_json(s: &str, clock: Clock) -> Result<Vec<PeriodTotal>, Error> {
+    let root: JsonRoot = serde_json::from_str(s)?;
+    if root.jsonversion != "2" {
+        return Err(format!(
+            "unsupported vnStat JSON version {} (need 2, from vnStat 2.x)",
+            root.jsonversion
+        )
+        .into());
+    }
+
+    let mut out = Vec::new();
+    for iface in &root.interfaces {
+        let t = &iface.traffic;
+        let levels = [
+            (Level::FiveMinute, &t.fiveminute),
+            (Level::Hour, &t.hour),
+            (Level::Day, &t.day),
+            (Level::Month, &t.month),
+            (Level::Year, &t.year),
+        ];
+        for (level, entries) in levels {
+            for e in entries.iter() {
+                let local = e
+                    .local_start()
+                    .ok_or_else(|| format!("invalid date in {} entry", level.table()))?;
+                let mut p = period(&iface.name, level, local, clock, e.rx, e.tx);
+                // The timestamp is exact even across DST changes; keep the length.
+                if let Some(start) = e
+                    .timestamp
+                    .and_then(|ts| DateTime::from_timestamp(ts, 0))
+                    .map(|d| d.naive_utc())
+                {
+                    p.end = start + (level.end(local) - local);
+                    p.start = start;
+                }
+                out.push(p);
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Reads `path` as a vnStat database or JSON file (`-` is stdin, JSON).
+pub async fn load(path: &Path, clock: Clock) -> Result<Vec<PeriodTotal>, Error> {
+    if path == Path::new("-") {
+        let mut s = String::new();
+        std::io::stdin().read_to_string(&mut s)?;
+        return parse_json(&s, clock);
+    }
+    let mut magic = Vec::new();
+    std::fs::File::open(path)?
+        .take(16)
+        .read_to_end(&mut magic)?;
+    if magic == b"SQLite format 3\0" {
+        read_db(path, clock).await
+    } else {
+        parse_json(&std::fs::read_to_string(path)?, clock)
+    }
+}
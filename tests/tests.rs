@@ -1,10 +1,15 @@
 //! Integration tests for ambr (db layer with in-memory SQLite).
 
 use ambr::db;
+use chrono::{NaiveDate, NaiveDateTime};
 use sqlx::SqlitePool;
 
 const MIB: f64 = 1024.0 * 1024.0;
 
+fn ts(s: &str) -> NaiveDateTime {
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap()
+}
+
 async fn test_pool() -> SqlitePool {
     let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
     db::init_db(&pool).await.unwrap();
@@ -15,7 +20,9 @@ async fn test_pool() -> SqlitePool {
 async fn test_init_db() {
     let pool = test_pool().await;
     db::save_delta(&pool, "eth0", &1000, &2000).await.unwrap();
-    let (rx, tx, total) = db::recent_totals(&pool, 60).await.unwrap();
+    let (rx, tx, total) = db::recent_totals(&pool, 60, db::HostFilter::Local)
+        .await
+        .unwrap();
     assert!(rx > 0.0 && tx > 0.0 && total > 0.0);
 }
 
@@ -25,7 +32,9 @@ async fn test_save_delta_and_recent_totals() {
     db::save_delta(&pool, "lo", &1024, &2048).await.unwrap();
     db::save_delta(&pool, "lo", &512, &256).await.unwrap();
 
-    let (rx, tx, total) = db::recent_totals(&pool, 60).await.unwrap();
+    let (rx, tx, total) = db::recent_totals(&pool, 60, db::HostFilter::Local)
+        .await
+        .unwrap();
     let expected_rx = (1024 + 512) as f64 / MIB;
     let expected_tx = (2048 + 256) as f64 / MIB;
     assert!((rx - expected_rx).abs() < 1e-6);
@@ -39,7 +48,9 @@ async fn test_recent_by_interface() {
     db::save_delta(&pool, "eth0", &1000, &500).await.unwrap();
     db::save_delta(&pool, "wlan0", &2000, &1000).await.unwrap();
 
-    let rows = db::recent_by_interface(&pool, 60).await.unwrap();
+    let rows = db::recent_by_interface(&pool, 60, db::HostFilter::Local)
+        .await
+        .unwrap();
     assert_eq!(rows.len(), 2);
     let names: Vec<_> = rows.iter().map(|r| r.interface.as_str()).collect();
     assert!(names.contains(&"eth0"));
@@ -54,7 +65,9 @@ async fn test_usage_by_day() {
     db::save_delta(&pool, "eth0", &100_000, &50_000)
         .await
         .unwrap();
-    let rows = db::usage_by_day(&pool, 10).await.unwrap();
+    let rows = db::usage_by_day(&pool, 10, db::HostFilter::All)
+        .await
+        .unwrap();
     assert!(!rows.is_empty());
     assert!((rows[0].total_mib - (rows[0].rx_mib + rows[0].tx_mib)).abs() < 1e-6);
 }
@@ -63,7 +76,9 @@ async fn test_usage_by_day() {
 async fn test_usage_by_hour() {
     let pool = test_pool().await;
     db::save_delta(&pool, "eth0", &2000, &1000).await.unwrap();
-    let rows = db::usage_by_hour(&pool, 24).await.unwrap();
+    let rows = db::usage_by_hour(&pool, 24, db::HostFilter::All)
+        .await
+        .unwrap();
     assert!(!rows.is_empty());
 }
 
@@ -71,7 +86,9 @@ async fn test_usage_by_hour() {
 async fn test_usage_by_month() {
     let pool = test_pool().await;
     db::save_delta(&pool, "eth0", &5000, &3000).await.unwrap();
-    let rows = db::usage_by_month(&pool, 12).await.unwrap();
+    let rows = db::usage_by_month(&pool, 12, db::HostFilter::All)
+        .await
+        .unwrap();
     assert!(!rows.is_empty());
 }
 
@@ -79,6 +96,2709 @@ async fn test_usage_by_month() {
 async fn test_usage_by_week() {
     let pool = test_pool().await;
     db::save_delta(&pool, "eth0", &4000, &2000).await.unwrap();
-    let rows = db::usage_by_week(&pool, 8).await.unwrap();
+    let rows = db::usage_by_week(&pool, 8, db::HostFilter::All)
+        .await
+        .unwrap();
     assert!(!rows.is_empty());
 }
+
+#[test]
+fn test_granularity_labels_roundtrip() {
+    use db::Granularity::*;
+    // 2026-01-02 falls in week 00, whose Monday is in 2025
+    for t in [ts("2026-09-14 13:47:21"), ts("2026-01-02 00:00:00")] {
+        for g in [Minute, Hour, Day, Week, Month] {
+            let start = g.period_start(t);
+            assert_eq!(g.parse_label(&g.label(t)), Some(start), "{:?} {}", g, t);
+            assert!(g.next(start) > t);
+        }
+    }
+    assert_eq!(
+        Month.next(ts("2026-01-01 00:00:00")),
+        ts("2026-02-01 00:00:00")
+    );
+    assert_eq!(
+        Week.parse_label("2026-W37"),
+        Some(ts("2026-09-14 00:00:00"))
+    );
+    assert_eq!(Month.finer(), Some(Day));
+    assert_eq!(Week.finer(), Some(Day));
+    assert_eq!(Hour.finer(), Some(Minute));
+    assert_eq!(Minute.finer(), None);
+    assert_eq!("minutely".parse::<db::Granularity>(), Ok(Minute));
+    assert_eq!(Day.parse_label("not a day"), None);
+
+    // Weeks are cut where %W restarts, so a drill-down matches its row
+    assert_eq!(
+        Week.label_range("2026-W00"),
+        Some((ts("2026-01-01 00:00:00"), ts("2026-01-05 00:00:00")))
+    );
+    assert_eq!(
+        Week.label_range("2025-W52"),
+        Some((ts("2025-12-29 00:00:00"), ts("2026-01-01 00:00:00")))
+    );
+    assert_eq!(
+        Week.label_range("2026-W37"),
+        Some((ts("2026-09-14 00:00:00"), ts("2026-09-21 00:00:00")))
+    );
+    assert_eq!(
+        Month.label_range("2026-02"),
+        Some((ts("2026-02-01 00:00:00"), ts("2026-03-01 00:00:00")))
+    );
+}
+
+#[tokio::test]
+async fn test_usage_between_range_and_filter() {
+    let pool = test_pool().await;
+    let samples = [
+        ("eth0", 1, "2026-09-02 23:59:59"),
+        ("eth0", 2, "2026-09-03 00:00:00"),
+        ("wlan0", 4, "2026-09-03 12:00:00"),
+        ("eth0", 8, "2026-09-10 08:00:00"),
+        ("eth0", 16, "2026-09-17 23:59:59"),
+        ("eth0", 32, "2026-09-18 00:00:00"),
+    ];
+    for (iface, mib, at) in samples {
+        db::save_delta_at(&pool, iface, &(mib * MIB as i64), &(MIB as i64), ts(at))
+            .await
+            .unwrap();
+    }
+    let (from, to) = (ts("2026-09-03 00:00:00"), ts("2026-09-18 00:00:00"));
+
+    // The 3rd to the 17th inclusive, oldest first; days without traffic are absent
+    let all = db::UsageFilter::default();
+    let days = db::usage_between(&pool, from, to, db::Granularity::Day, &all)
+        .await
+        .unwrap();
+    let got: Vec<_> = days.iter().map(|r| (r.period.as_str(), r.rx_mib)).collect();
+    assert_eq!(
+        got,
+        [
+            ("2026-09-03", 6.0),
+            ("2026-09-10", 8.0),
+            ("2026-09-17", 16.0)
+        ]
+    );
+    assert_eq!(days[0].tx_mib, 2.0);
+    assert_eq!(days[0].total_mib, 8.0);
+
+    let month = db::usage_between(&pool, from, to, db::Granularity::Month, &all)
+        .await
+        .unwrap();
+    assert_eq!(month.len(), 1);
+    assert_eq!(
+        (month[0].period.as_str(), month[0].rx_mib),
+        ("2026-09", 30.0)
+    );
+
+    let wlan = db::UsageFilter {
+        interface: Some("wlan0".to_string()),
+        ..Default::default()
+    };
+    let hours = db::usage_between(&pool, from, to, db::Granularity::Hour, &wlan)
+        .await
+        .unwrap();
+    assert_eq!(hours.len(), 1);
+    assert_eq!(
+        (hours[0].period.as_str(), hours[0].rx_mib),
+        ("2026-09-03 12:00", 4.0)
+    );
+}
+
+#[tokio::test]
+async fn test_usage_by_interface_between() {
+    let pool = test_pool().await;
+    let samples = [
+        ("eth0", 1, "2026-08-31 23:59:59"),
+        ("eth0", 2, "2026-09-01 00:00:00"),
+        ("wlan0", 4, "2026-09-01 00:00:30"),
+        ("eth0", 8, "2026-09-01 00:00:45"),
+        ("eth0", 16, "2026-09-14 13:47:21"),
+        ("eth0", 32, "2026-10-01 00:00:00"),
+    ];
+    for (iface, mib, at) in samples {
+        db::save_delta_at(&pool, iface, &(mib * MIB as i64), &0, ts(at))
+            .await
+            .unwrap();
+    }
+
+    // The days of September, split by interface; both edges of [from, to) hold
+    let days = db::usage_by_interface_between(
+        &pool,
+        db::Granularity::Day,
+        ts("2026-09-01 00:00:00"),
+        ts("2026-10-01 00:00:00"),
+        db::HostFilter::All,
+    )
+    .await
+    .unwrap();
+    let got: Vec<_> = days
+        .iter()
+        .map(|r| (r.period.as_str(), r.interface.as_str(), r.rx_mib))
+        .collect();
+    assert_eq!(
+        got,
+        [
+            ("2026-09-01", "eth0", 10.0),
+            ("2026-09-01", "wlan0", 4.0),
+            ("2026-09-14", "eth0", 16.0),
+        ]
+    );
+    assert_eq!(days[0].total_mib, days[0].rx_mib + days[0].tx_mib);
+
+    // One more step down: the minutes of the first hour of September
+    let minutes = db::usage_by_interface_between(
+        &pool,
+        db::Granularity::Minute,
+        ts("2026-09-01 00:00:00"),
+        ts("2026-09-01 01:00:00"),
+        db::HostFilter::All,
+    )
+    .await
+    .unwrap();
+    let got: Vec<_> = minutes
+        .iter()
+        .map(|r| (r.period.as_str(), r.interface.as_str()))
+        .collect();
+    assert_eq!(
+        got,
+        [("2026-09-01 00:00", "eth0"), ("2026-09-01 00:00", "wlan0")]
+    );
+
+    // A week across new year splits into two rows; each drills into its own days
+    for at in ["2025-12-30 12:00:00", "2026-01-02 12:00:00"] {
+        db::save_delta_at(&pool, "eth0", &(MIB as i64), &0, ts(at))
+            .await
+            .unwrap();
+    }
+    for (week, day) in [("2025-W52", "2025-12-30"), ("2026-W00", "2026-01-02")] {
+        let (from, to) = db::Granularity::Week.label_range(week).unwrap();
+        let days = db::usage_by_interface_between(
+            &pool,
+            db::Granularity::Day,
+            from,
+            to,
+            db::HostFilter::All,
+        )
+        .await
+        .unwrap();
+        let got: Vec<_> = days.iter().map(|r| r.period.as_str()).collect();
+        assert_eq!(got, [day], "{week}");
+    }
+}
+
+#[tokio::test]
+async fn test_ptr_cache_roundtrip() {
+    let pool = test_pool().await;
+    assert!(
+        db::cached_ptr(&pool, "192.0.2.1", 60)
+            .await
+            .unwrap()
+            .is_none()
+    );
+
+    db::store_ptr(&pool, "192.0.2.1", Some("host.example"))
+        .await
+        .unwrap();
+    db::store_ptr(&pool, "192.0.2.2", None).await.unwrap();
+
+    let hit = db::cached_ptr(&pool, "192.0.2.1", 60)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(hit.name.as_deref(), Some("host.example"));
+    let miss = db::cached_ptr(&pool, "192.0.2.2", 60)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(miss.name.is_none());
+
+    // Cached names are served without hitting the resolver.
+    let enricher = ambr::enrich::Enricher::new(pool.clone());
+    let name = enricher.ptr("192.0.2.1".parse().unwrap()).await.unwrap();
+    assert_eq!(name.as_deref(), Some("host.example"));
+}
+
+#[tokio::test]
+async fn test_ip2asn_lookup_and_label() {
+    use ambr::enrich::{AsnDb, Enricher};
+
+    let tsv = "1.0.0.0\t1.0.0.255\t13335\tUS\tCLOUDFLARENET\n\
+               10.0.0.0\t10.255.255.255\t0\tNone\tNot routed\n\
+               198.38.96.0\t198.38.127.255\t2906\tUS\tNetflix\n\
+               2a00:86c0::\t2a00:86c0:ffff:ffff:ffff:ffff:ffff:ffff\t2906\tUS\tNetflix\n";
+    let asn = AsnDb::from_ip2asn_tsv(tsv.as_bytes()).unwrap();
+
+    let hit = asn.lookup("198.38.100.7".parse().unwrap()).unwrap();
+    assert_eq!(hit.asn, 2906);
+    assert_eq!(hit.to_string(), "Netflix / AS2906");
+    assert_eq!(
+        asn.lookup("2a00:86c0::1".parse().unwrap()).unwrap().asn,
+        2906
+    );
+    assert!(asn.lookup("10.1.2.3".parse().unwrap()).is_none());
+    assert!(asn.lookup("198.38.128.0".parse().unwrap()).is_none());
+
+    let pool = test_pool().await;
+    db::store_ptr(&pool, "198.38.100.7", Some("ipv4-c001.nflxvideo.net"))
+        .await
+        .unwrap();
+    let enricher = Enricher::new(pool).with_asn_db(asn);
+    let label = enricher
+        .label("198.38.100.7".parse().unwrap())
+        .await
+        .unwrap();
+    assert_eq!(label.ptr.as_deref(), Some("ipv4-c001.nflxvideo.net"));
+    assert_eq!(label.to_string(), "Netflix / AS2906");
+}
+
+fn monthly_quota(name: &str, limit: i64, start_day: u32) -> ambr::quota::Quota {
+    ambr::quota::Quota {
+        name: name.to_string(),
+        limit_bytes: limit,
+        interfaces: Vec::new(),
+        direction: ambr::quota::Direction::Both,
+        cycle: ambr::quota::Cycle::Monthly { start_day },
+        free_windows: Vec::new(),
+    }
+}
+
+#[test]
+fn test_quota_cycle_boundaries() {
+    let q = monthly_quota("cap", 1, 15);
+    assert_eq!(
+        q.cycle_at(ts("2026-03-20 12:00:00")),
+        (ts("2026-03-15 00:00:00"), ts("2026-04-15 00:00:00"))
+    );
+    assert_eq!(
+        q.cycle_at(ts("2026-03-14 23:59:59")),
+        (ts("2026-02-15 00:00:00"), ts("2026-03-15 00:00:00"))
+    );
+    assert_eq!(
+        q.cycle_at(ts("2026-01-03 00:00:00")),
+        (ts("2025-12-15 00:00:00"), ts("2026-01-15 00:00:00"))
+    );
+
+    // Day 31 clamps to the end of short months.
+    let q = monthly_quota("cap", 1, 31);
+    assert_eq!(
+        q.cycle_at(ts("2026-03-05 00:00:00")),
+        (ts("2026-02-28 00:00:00"), ts("2026-03-31 00:00:00"))
+    );
+}
+
+#[test]
+fn test_parse_size() {
+    use ambr::quota::parse_size;
+    assert_eq!(parse_size("1048576"), Ok(1_048_576));
+    assert_eq!(parse_size("500MB"), Ok(500_000_000));
+    assert_eq!(parse_size("2 GiB"), Ok(2 * 1024 * 1024 * 1024));
+    assert_eq!(parse_size("1.5kib"), Ok(1536));
+    assert!(parse_size("12 parsecs").is_err());
+    assert!(parse_size("GiB").is_err());
+}
+
+#[tokio::test]
+async fn test_quota_usage_current_cycle() {
+    use ambr::quota::{Cycle, Direction, Quota};
+
+    let pool = test_pool().await;
+    db::save_delta_at(&pool, "wwan0", &1_000, &200, ts("2026-03-14 23:59:50"))
+        .await
+        .unwrap();
+    db::save_delta_at(&pool, "wwan0", &3_000, &400, ts("2026-03-15 00:00:00"))
+        .await
+        .unwrap();
+    db::save_delta_at(&pool, "eth0", &5_000, &600, ts("2026-03-20 08:00:00"))
+        .await
+        .unwrap();
+
+    let mobile = Quota {
+        name: "mobile".to_string(),
+        limit_bytes: 10_000,
+        interfaces: vec!["wwan0".to_string()],
+        direction: Direction::Rx,
+        cycle: Cycle::Monthly { start_day: 15 },
+        free_windows: Vec::new(),
+    };
+    db::add_quota(&pool, &mobile).await.unwrap();
+    db::add_quota(&pool, &monthly_quota("all", 20_000, 1))
+        .await
+        .unwrap();
+    let custom = Quota {
+        name: "trip".to_string(),
+        limit_bytes: 1_000,
+        interfaces: Vec::new(),
+        direction: Direction::Tx,
+        cycle: Cycle::Custom {
+            from: ts("2026-03-10 00:00:00"),
+            to: ts("2026-03-16 00:00:00"),
+        },
+        free_windows: Vec::new(),
+    };
+    db::add_quota(&pool, &custom).await.unwrap();
+    assert!(db::add_quota(&pool, &mobile).await.is_err());
+
+    let quotas = db::list_quotas(&pool).await.unwrap();
+    assert_eq!(quotas.len(), 3);
+    assert_eq!(quotas[1], mobile);
+    assert_eq!(quotas[2], custom);
+
+    let now = ts("2026-03-25 12:00:00");
+    let usages = db::quota_usages(&pool, now, db::HostFilter::Local)
+        .await
+        .unwrap();
+    let by_name = |n: &str| usages.iter().find(|u| u.quota.name == n).unwrap();
+
+    let m = by_name("mobile");
+    assert_eq!(m.used_bytes, 3_000);
+    assert_eq!(m.remaining_bytes(), 7_000);
+    assert!((m.percent() - 30.0).abs() < 1e-9);
+    assert_eq!(m.cycle_start, ts("2026-03-15 00:00:00"));
+
+    assert_eq!(by_name("all").used_bytes, 1_200 + 3_400 + 5_600);
+    let t = by_name("trip");
+    assert_eq!(t.used_bytes, 600);
+    assert_eq!(
+        t.cycle_end.date(),
+        NaiveDate::from_ymd_opt(2026, 3, 16).unwrap()
+    );
+
+    assert!(db::delete_quota(&pool, "trip").await.unwrap());
+    assert!(!db::delete_quota(&pool, "trip").await.unwrap());
+    assert_eq!(db::list_quotas(&pool).await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_split_usage_on_raw_timestamps() {
+    let pool = test_pool().await;
+    let night: ambr::tariff::TimeWindow = "Mon-Fri 22:00-06:00".parse().unwrap();
+    // 2026-03-02 is a Monday; both 05:59:50 and 06:00:00 fall in the same hour.
+    for (at, rx) in [
+        ("2026-03-02 05:59:50", 1), // Sunday night's window has not started
+        ("2026-03-02 22:00:00", 10),
+        ("2026-03-03 05:59:50", 100), // Monday's window, past midnight
+        ("2026-03-03 06:00:00", 1_000),
+        ("2026-03-07 03:00:00", 10_000), // Friday's window on Saturday
+    ] {
+        db::save_delta_at(&pool, "wwan0", &rx, &0, ts(at))
+            .await
+            .unwrap();
+    }
+
+    let split = db::split_usage(
+        &pool,
+        ts("2026-03-01 00:00:00"),
+        ts("2026-04-01 00:00:00"),
+        &[],
+        std::slice::from_ref(&night),
+        db::HostFilter::Local,
+    )
+    .await
+    .unwrap();
+    assert_eq!(split.counted_rx, 1 + 1_000);
+    assert_eq!(split.free_rx, 10 + 100 + 10_000);
+
+    let none = db::split_usage(
+        &pool,
+        ts("2026-03-01 00:00:00"),
+        ts("2026-04-01 00:00:00"),
+        &["eth0".to_string()],
+        &[night],
+        db::HostFilter::Local,
+    )
+    .await
+    .unwrap();
+    assert_eq!(none, db::SplitUsage::default());
+}
+
+#[tokio::test]
+async fn test_quota_free_windows_not_counted() {
+    let pool = test_pool().await;
+    let mut q = monthly_quota("nights-free", 10_000, 1);
+    q.free_windows = vec![
+        "01:00-07:00".parse().unwrap(),
+        "Sat,Sun 00:00-24:00".parse().unwrap(),
+    ];
+    db::add_quota(&pool, &q).await.unwrap();
+    assert_eq!(db::list_quotas(&pool).await.unwrap(), vec![q.clone()]);
+
+    for (at, rx) in [
+        ("2026-03-02 00:59:50", 1_000),
+        ("2026-03-02 01:00:00", 2_000),
+        ("2026-03-02 06:59:50", 3_000),
+        ("2026-03-02 07:00:00", 400),
+        ("2026-03-07 12:00:00", 5_000),
+    ] {
+        db::save_delta_at(&pool, "wwan0", &rx, &0, ts(at))
+            .await
+            .unwrap();
+    }
+    let u = db::quota_usage(&pool, &q, ts("2026-03-10 00:00:00"), db::HostFilter::Local)
+        .await
+        .unwrap();
+    assert_eq!(u.used_bytes, 1_400);
+    assert_eq!(u.free_bytes, 10_000);
+    assert!((u.percent() - 14.0).abs() < 1e-9);
+
+    let daily = db::quota_daily_usage(
+        &pool,
+        &q,
+        ts("2026-03-01 00:00:00"),
+        ts("2026-03-10 00:00:00"),
+        db::HostFilter::Local,
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        daily,
+        vec![(day("2026-03-02"), 1_400), (day("2026-03-07"), 0)]
+    );
+}
+
+#[tokio::test]
+async fn test_init_db_adds_free_windows_column() {
+    let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+    sqlx::query(
+        "CREATE TABLE quotas (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE,
+            limit_bytes INTEGER NOT NULL,
+            interfaces TEXT NOT NULL DEFAULT '',
+            direction TEXT NOT NULL DEFAULT 'both',
+            cycle_start_day INTEGER,
+            period_start DATETIME,
+            period_end DATETIME
+        )",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    sqlx::query("INSERT INTO quotas (name, limit_bytes, cycle_start_day) VALUES ('old', 5, 1)")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    db::init_db(&pool).await.unwrap();
+    db::init_db(&pool).await.unwrap();
+    let quotas = db::list_quotas(&pool).await.unwrap();
+    assert_eq!(quotas, vec![monthly_quota("old", 5, 1)]);
+}
+
+fn day(s: &str) -> NaiveDate {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+}
+
+#[test]
+fn test_forecast_linear_flat_usage() {
+    use ambr::forecast::project;
+
+    let start = day("2026-02-20");
+    let history: Vec<_> = (0..14)
+        .map(|i| (start + chrono::Duration::days(i), 1_000))
+        .collect();
+    let f = project(
+        &history,
+        5_000,
+        20_000,
+        ts("2026-03-06 12:00:00"),
+        ts("2026-03-01 00:00:00"),
+        ts("2026-03-31 00:00:00"),
+    );
+    // Half of today plus 24 more days at 1000/day.
+    assert_eq!(f.projected_bytes, 5_000 + 500 + 24_000);
+    assert_eq!(f.cap_hit, Some(day("2026-03-21")));
+
+    let f = project(
+        &history,
+        5_000,
+        1_000_000,
+        ts("2026-03-06 12:00:00"),
+        ts("2026-03-01 00:00:00"),
+        ts("2026-03-31 00:00:00"),
+    );
+    assert_eq!(f.cap_hit, None);
+
+    // Already over the limit: there is no day left to hit it on.
+    let f = project(
+        &history,
+        25_000,
+        20_000,
+        ts("2026-03-06 12:00:00"),
+        ts("2026-03-01 00:00:00"),
+        ts("2026-03-31 00:00:00"),
+    );
+    assert_eq!(f.projected_bytes, 25_000 + 500 + 24_000);
+    assert_eq!(f.cap_hit, None);
+}
+
+#[test]
+fn test_forecast_future_cycle() {
+    use ambr::forecast::project;
+
+    let start = day("2026-02-20");
+    let history: Vec<_> = (0..14)
+        .map(|i| (start + chrono::Duration::days(i), 1_000))
+        .collect();
+    // A custom cycle that starts in four days only counts its own ten days.
+    let f = project(
+        &history,
+        0,
+        5_000,
+        ts("2026-03-06 12:00:00"),
+        ts("2026-03-10 00:00:00"),
+        ts("2026-03-20 00:00:00"),
+    );
+    assert_eq!(f.projected_bytes, 10_000);
+    assert_eq!(f.cap_hit, Some(day("2026-03-14")));
+}
+
+#[test]
+fn test_forecast_weekday_seasonality() {
+    use ambr::forecast::project;
+    use chrono::Datelike;
+
+    // Four weeks starting on a Monday: 1000 on weekdays, 3000 on weekends.
+    let start = day("2026-02-02");
+    let history: Vec<_> = (0..28)
+        .map(|i| {
+            let d = start + chrono::Duration::days(i);
+            let v = if d.weekday().num_days_from_monday() >= 5 {
+                3_000
+            } else {
+                1_000
+            };
+            (d, v)
+        })
+        .collect();
+
+    let saturday = project(
+        &history,
+        0,
+        i64::MAX,
+        ts("2026-03-07 00:00:00"),
+        ts("2026-03-01 00:00:00"),
+        ts("2026-03-08 00:00:00"),
+    );
+    assert!((saturday.projected_bytes - 3_000).abs() < 100);
+
+    let monday = project(
+        &history,
+        0,
+        i64::MAX,
+        ts("2026-03-09 00:00:00"),
+        ts("2026-03-01 00:00:00"),
+        ts("2026-03-10 00:00:00"),
+    );
+    assert!((monday.projected_bytes - 1_000).abs() < 100);
+}
+
+#[tokio::test]
+async fn test_forecast_usage_from_db() {
+    let pool = test_pool().await;
+    for d in 1..=10 {
+        let at = ts(&format!("2026-03-{:02} 10:00:00", d));
+        db::save_delta_at(&pool, "eth0", &600, &400, at)
+            .await
+            .unwrap();
+    }
+    let q = monthly_quota("cap", 20_000, 1);
+    let now = ts("2026-03-11 00:00:00");
+    let usage = db::quota_usage(&pool, &q, now, db::HostFilter::Local)
+        .await
+        .unwrap();
+    assert_eq!(usage.used_bytes, 10_000);
+
+    let f = ambr::forecast::forecast_usage(&pool, &usage, now, db::HostFilter::Local)
+        .await
+        .unwrap();
+    // 21 remaining days at 1000/day.
+    assert!((f.projected_bytes - 31_000).abs() < 500);
+    assert_eq!(f.cap_hit, Some(day("2026-03-20")));
+}
+
+fn tick(at: &str, deltas: &[(&str, i64, i64)]) -> ambr::alert::Tick {
+    ambr::alert::Tick {
+        at: ts(at),
+        interval_secs: 10,
+        deltas: deltas
+            .iter()
+            .map(|(n, rx, tx)| (n.to_string(), *rx, *tx))
+            .collect(),
+    }
+}
+
+fn temp_path(name: &str) -> std::path::PathBuf {
+    let p = std::env::temp_dir().join(format!("ambr-test-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_file(&p);
+    p
+}
+
+#[test]
+fn test_config_parses_alert_rules() {
+    use ambr::alert::{Action, Condition};
+    use ambr::quota::Direction;
+
+    let cfg = ambr::config::Config::parse(
+        r#"
+        [[alert]]
+        name = "mobile-80"
+        kind = "quota"
+        quota = "mobile"
+        percent = 80
+
+          [[alert.action]]
+          type = "notify"
+
+          [[alert.action]]
+          type = "webhook"
+          url = "http://127.0.0.1:9/hook"
+
+        [[alert]]
+        name = "big-hour"
+        kind = "volume"
+        bytes = "2GiB"
+        window_minutes = 60
+        hysteresis = 0.1
+
+        [[alert]]
+        name = "upload"
+        kind = "rate"
+        bytes_per_sec = 1000000
+        for_minutes = 5
+        direction = "tx"
+        interface = "wwan0"
+
+          [[alert.action]]
+          type = "command"
+          command = "echo $AMBR_MESSAGE"
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(cfg.alerts.len(), 3);
+    assert_eq!(
+        cfg.alerts[0].condition,
+        Condition::Quota {
+            quota: "mobile".to_string(),
+            percent: 80.0
+        }
+    );
+    assert_eq!(cfg.alerts[0].actions.len(), 2);
+    assert_eq!(cfg.alerts[0].actions[0], Action::Notify);
+    assert_eq!(
+        cfg.alerts[1].condition,
+        Condition::Volume {
+            bytes: 2 * 1024 * 1024 * 1024,
+            window_minutes: 60,
+            direction: Direction::Both,
+            interface: None,
+        }
+    );
+    assert!((cfg.alerts[1].hysteresis - 0.1).abs() < 1e-9);
+    assert!((cfg.alerts[2].hysteresis - 0.05).abs() < 1e-9);
+    assert!(matches!(
+        cfg.alerts[2].condition,
+        Condition::Rate {
+            direction: Direction::Tx,
+            for_minutes: 5,
+            ..
+        }
+    ));
+
+    assert!(ambr::config::Config::parse("[[alert]]\nname = \"x\"\nkind = \"bogus\"").is_err());
+    assert!(ambr::config::Config::parse("").unwrap().alerts.is_empty());
+}
+
+#[tokio::test]
+async fn test_alert_quota_fires_once_per_crossing() {
+    use ambr::alert::{AlertEngine, AlertState, Condition, Rule};
+
+    let pool = test_pool().await;
+    db::add_quota(&pool, &monthly_quota("cap", 1_000, 1))
+        .await
+        .unwrap();
+    let mut engine = AlertEngine::new(vec![Rule {
+        name: "cap-80".to_string(),
+        condition: Condition::Quota {
+            quota: "cap".to_string(),
+            percent: 80.0,
+        },
+        hysteresis: 0.05,
+        actions: Vec::new(),
+    }]);
+
+    db::save_delta_at(&pool, "eth0", &500, &0, ts("2026-03-02 10:00:00"))
+        .await
+        .unwrap();
+    assert!(
+        engine
+            .evaluate(&pool, &tick("2026-03-02 10:00:05", &[]))
+            .await
+            .is_empty()
+    );
+
+    db::save_delta_at(&pool, "eth0", &400, &0, ts("2026-03-02 11:00:00"))
+        .await
+        .unwrap();
+    let events = engine
+        .evaluate(&pool, &tick("2026-03-02 11:00:05", &[]))
+        .await;
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].state, AlertState::Fired);
+    assert!((events[0].value - 90.0).abs() < 1e-9);
+
+    // Still above: no repeat.
+    db::save_delta_at(&pool, "eth0", &50, &0, ts("2026-03-02 12:00:00"))
+        .await
+        .unwrap();
+    assert!(
+        engine
+            .evaluate(&pool, &tick("2026-03-02 12:00:05", &[]))
+            .await
+            .is_empty()
+    );
+
+    // New cycle: usage resets, the rule clears and can fire again.
+    let events = engine
+        .evaluate(&pool, &tick("2026-04-01 00:00:05", &[]))
+        .await;
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].state, AlertState::Cleared);
+    db::save_delta_at(&pool, "eth0", &900, &0, ts("2026-04-01 01:00:00"))
+        .await
+        .unwrap();
+    let events = engine
+        .evaluate(&pool, &tick("2026-04-01 01:00:05", &[]))
+        .await;
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].state, AlertState::Fired);
+}
+
+#[tokio::test]
+async fn test_alert_volume_window_and_hysteresis() {
+    use ambr::alert::{AlertEngine, AlertState, Condition, Rule};
+    use ambr::quota::Direction;
+
+    let pool = test_pool().await;
+    let mut engine = AlertEngine::new(vec![Rule {
+        name: "big-hour".to_string(),
+        condition: Condition::Volume {
+            bytes: 1_000,
+            window_minutes: 60,
+            direction: Direction::Tx,
+            interface: Some("eth0".to_string()),
+        },
+        hysteresis: 0.5,
+        actions: Vec::new(),
+    }]);
+
+    db::save_delta_at(&pool, "wlan0", &0, &5_000, ts("2026-03-02 10:00:00"))
+        .await
+        .unwrap();
+    db::save_delta_at(&pool, "eth0", &5_000, &600, ts("2026-03-02 10:00:00"))
+        .await
+        .unwrap();
+    assert!(
+        engine
+            .evaluate(&pool, &tick("2026-03-02 10:00:05", &[]))
+            .await
+            .is_empty()
+    );
+
+    db::save_delta_at(&pool, "eth0", &0, &600, ts("2026-03-02 10:30:00"))
+        .await
+        .unwrap();
+    let events = engine
+        .evaluate(&pool, &tick("2026-03-02 10:30:05", &[]))
+        .await;
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].state, AlertState::Fired);
+
+    // 600 left in the window: below the threshold but above the 50% re-arm level.
+    assert!(
+        engine
+            .evaluate(&pool, &tick("2026-03-02 11:00:10", &[]))
+            .await
+            .is_empty()
+    );
+    let events = engine
+        .evaluate(&pool, &tick("2026-03-02 11:30:10", &[]))
+        .await;
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].state, AlertState::Cleared);
+}
+
+#[tokio::test]
+async fn test_alert_rate_sustained_and_actions() {
+    use ambr::alert::{Action, AlertEngine, AlertState, Condition, Rule};
+    use ambr::quota::Direction;
+
+    let pool = test_pool().await;
+    let log = temp_path("alert.log");
+    let out = temp_path("alert.cmd");
+    let mut engine = AlertEngine::new(vec![Rule {
+        name: "upload".to_string(),
+        condition: Condition::Rate {
+            bytes_per_sec: 100,
+            for_minutes: 1,
+            direction: Direction::Tx,
+            interface: None,
+        },
+        hysteresis: 0.05,
+        actions: vec![
+            Action::Log { path: log.clone() },
+            Action::Command {
+                command: format!("echo \"$AMBR_ALERT $AMBR_STATE\" > {}", out.display()),
+            },
+        ],
+    }]);
+
+    // 2000 B per 10 s = 200 B/s, but only for 50 s, then a dip.
+    for s in ["00", "10", "20", "30", "40", "50"] {
+        let t = tick(&format!("2026-03-02 10:00:{}", s), &[("eth0", 0, 2_000)]);
+        assert!(engine.evaluate(&pool, &t).await.is_empty());
+    }
+    let t = tick("2026-03-02 10:01:00", &[("eth0", 0, 500)]);
+    assert!(engine.evaluate(&pool, &t).await.is_empty());
+
+    // Sustained for a full minute across two interfaces.
+    let mut fired = Vec::new();
+    for m in 1..=7 {
+        let at = format!("2026-03-02 10:01:{:02}", m * 8);
+        let t = tick(&at, &[("eth0", 0, 600), ("wlan0", 0, 600)]);
+        fired.extend(engine.evaluate(&pool, &t).await);
+    }
+    let t = tick("2026-03-02 10:02:10", &[("eth0", 0, 1_200)]);
+    fired.extend(engine.evaluate(&pool, &t).await);
+    assert_eq!(fired.len(), 1);
+    assert_eq!(fired[0].state, AlertState::Fired);
+    assert_eq!(fired[0].at, ts("2026-03-02 10:02:10"));
+
+    assert!(engine.dispatch(&fired[0]).await.is_empty());
+    let logged = std::fs::read_to_string(&log).unwrap();
+    assert!(logged.contains("upload fired"));
+    assert_eq!(
+        std::fs::read_to_string(&out).unwrap().trim(),
+        "upload fired"
+    );
+    let _ = std::fs::remove_file(&log);
+    let _ = std::fs::remove_file(&out);
+}
+
+#[tokio::test]
+async fn test_alert_job_reports_each_failed_action() {
+    use ambr::alert::{Action, AlertEngine, AlertState, Condition, Rule};
+    use ambr::quota::Direction;
+
+    let pool = test_pool().await;
+    let log = temp_path("alert-job.log");
+    let mut engine = AlertEngine::new(vec![Rule {
+        name: "burst".to_string(),
+        condition: Condition::Rate {
+            bytes_per_sec: 100,
+            for_minutes: 0,
+            direction: Direction::Both,
+            interface: None,
+        },
+        hysteresis: 0.05,
+        actions: vec![
+            Action::Command {
+                command: "exit 3".to_string(),
+            },
+            Action::Log { path: log.clone() },
+        ],
+    }]);
+    let fired = engine
+        .evaluate(&pool, &tick("2026-03-02 10:00:00", &[("eth0", 5_000, 0)]))
+        .await;
+    assert_eq!(fired[0].state, AlertState::Fired);
+
+    // The job owns everything it needs, so it can run on another task.
+    let job = engine.job(&fired[0]);
+    let errors = tokio::spawn(job.run()).await.unwrap();
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].to_string().starts_with("command action:"));
+    assert!(
+        std::fs::read_to_string(&log)
+            .unwrap()
+            .contains("burst fired")
+    );
+    let _ = std::fs::remove_file(&log);
+}
+
+#[test]
+fn test_enforcement_commands_and_config() {
+    use ambr::alert::Action;
+    use ambr::enforce::{Enforcement, Method, shell_words};
+
+    let cfg = ambr::config::Config::parse(
+        r#"
+        [[alert]]
+        name = "cap"
+        kind = "quota"
+        quota = "mobile"
+        percent = 100
+
+          [[alert.action]]
+          type = "enforce"
+          interface = "wwan0"
+          method = "tc"
+          rate = "256kbit"
+          dry_run = true
+
+          [[alert.action]]
+          type = "enforce"
+          interface = "wwan0"
+          method = "nftables"
+        "#,
+    )
+    .unwrap();
+    let tc = Enforcement {
+        interface: "wwan0".to_string(),
+        method: Method::Tc {
+            rate: "256kbit".to_string(),
+        },
+        dry_run: true,
+    };
+    assert_eq!(cfg.alerts[0].actions[0], Action::Enforce(tc.clone()));
+    let Action::Enforce(nft) = &cfg.alerts[0].actions[1] else {
+        panic!("expected enforce action");
+    };
+    assert_eq!(nft.method, Method::Nftables);
+    assert!(!nft.dry_run);
+
+    let apply: Vec<String> = tc.apply_commands().iter().map(|c| shell_words(c)).collect();
+    assert_eq!(
+        apply[0],
+        "tc qdisc replace dev wwan0 root tbf rate 256kbit burst 32kbit latency 400ms"
+    );
+    assert!(apply.iter().any(|c| c.contains("police rate 256kbit")));
+    let remove: Vec<String> = tc
+        .remove_commands()
+        .iter()
+        .map(|c| shell_words(c))
+        .collect();
+    assert_eq!(
+        remove,
+        [
+            "tc qdisc del dev wwan0 root",
+            "tc qdisc del dev wwan0 ingress"
+        ]
+    );
+
+    let apply: Vec<String> = nft
+        .apply_commands()
+        .iter()
+        .map(|c| shell_words(c))
+        .collect();
+    assert!(apply.contains(&"nft add rule inet ambr_wwan0 output oifname wwan0 drop".to_string()));
+    assert_eq!(
+        shell_words(&nft.remove_commands()[0]),
+        "nft delete table inet ambr_wwan0"
+    );
+}
+
+#[tokio::test]
+async fn test_enforcement_dry_run_follows_alert_state() {
+    use ambr::alert::{Action, AlertEngine, AlertState, Condition, Rule};
+    use ambr::enforce::{Enforcement, Method};
+
+    let pool = test_pool().await;
+    db::add_quota(&pool, &monthly_quota("cap", 1_000, 1))
+        .await
+        .unwrap();
+    db::save_delta_at(&pool, "wwan0", &1_500, &0, ts("2026-03-02 10:00:00"))
+        .await
+        .unwrap();
+    let mut engine = AlertEngine::new(vec![Rule {
+        name: "cap".to_string(),
+        condition: Condition::Quota {
+            quota: "cap".to_string(),
+            percent: 100.0,
+        },
+        hysteresis: 0.05,
+        actions: vec![Action::Enforce(Enforcement {
+            interface: "wwan0".to_string(),
+            method: Method::Nftables,
+            dry_run: true,
+        })],
+    }]);
+
+    let fired = engine
+        .evaluate(&pool, &tick("2026-03-02 10:00:05", &[]))
+        .await;
+    assert_eq!(fired[0].state, AlertState::Fired);
+    assert!(engine.dispatch(&fired[0]).await.is_empty());
+
+    let cleared = engine
+        .evaluate(&pool, &tick("2026-04-01 00:00:05", &[]))
+        .await;
+    assert_eq!(cleared[0].state, AlertState::Cleared);
+    assert!(engine.dispatch(&cleared[0]).await.is_empty());
+}
+
+#[tokio::test]
+async fn test_enforcement_lifted_after_restart() {
+    use ambr::alert::{Action, AlertEngine, AlertState, Condition, Rule};
+    use ambr::enforce::{Enforcement, Method};
+
+    let pool = test_pool().await;
+    db::add_quota(&pool, &monthly_quota("cap", 1_000, 1))
+        .await
+        .unwrap();
+    db::save_delta_at(&pool, "wwan0", &1_500, &0, ts("2026-03-02 10:00:00"))
+        .await
+        .unwrap();
+    let rules = |actions: Vec<Action>| {
+        vec![Rule {
+            name: "cap".to_string(),
+            condition: Condition::Quota {
+                quota: "cap".to_string(),
+                percent: 100.0,
+            },
+            hysteresis: 0.05,
+            actions,
+        }]
+    };
+    let enforce = vec![Action::Enforce(Enforcement {
+        interface: "wwan0".to_string(),
+        method: Method::Nftables,
+        dry_run: true,
+    })];
+
+    // The limit is applied, then the daemon restarts and finds the rule
+    // still active, so it is cleared (and the limit lifted) at the reset.
+    let mut engine = AlertEngine::new(rules(enforce.clone()));
+    let fired = engine
+        .evaluate(&pool, &tick("2026-03-02 10:00:05", &[]))
+        .await;
+    assert_eq!(fired[0].state, AlertState::Fired);
+    drop(engine);
+
+    let mut engine = AlertEngine::new(rules(enforce.clone()));
+    let cleared = engine
+        .evaluate(&pool, &tick("2026-04-01 00:00:05", &[]))
+        .await;
+    assert_eq!(cleared.len(), 1);
+    assert_eq!(cleared[0].state, AlertState::Cleared);
+    assert!(engine.dispatch(&cleared[0]).await.is_empty());
+    assert!(
+        engine
+            .evaluate(&pool, &tick("2026-04-01 00:00:15", &[]))
+            .await
+            .is_empty()
+    );
+    // Every new cycle lifts whatever may be left over.
+    let next = engine
+        .evaluate(&pool, &tick("2026-05-01 00:00:05", &[]))
+        .await;
+    assert_eq!(next[0].state, AlertState::Reset);
+
+    // Restarting while over the limit fires once, and not again after the
+    // next restart.
+    let mut engine = AlertEngine::new(rules(enforce.clone()));
+    let fired = engine
+        .evaluate(&pool, &tick("2026-03-20 12:00:00", &[]))
+        .await;
+    assert_eq!(fired[0].state, AlertState::Fired);
+    let mut engine = AlertEngine::new(rules(enforce.clone()));
+    assert!(
+        engine
+            .evaluate(&pool, &tick("2026-03-20 12:00:10", &[]))
+            .await
+            .is_empty()
+    );
+
+    // Without a stored state (say, the daemon crashed before storing it),
+    // the first evaluation below the limit lifts any leftover limit.
+    let pool = test_pool().await;
+    db::add_quota(&pool, &monthly_quota("cap", 1_000, 1))
+        .await
+        .unwrap();
+    let mut engine = AlertEngine::new(rules(enforce));
+    let reset = engine
+        .evaluate(&pool, &tick("2026-04-01 00:00:05", &[]))
+        .await;
+    assert_eq!(reset.len(), 1);
+    assert_eq!(reset[0].state, AlertState::Reset);
+    assert!(engine.dispatch(&reset[0]).await.is_empty());
+
+    // Rules without enforcement have nothing to lift.
+    let mut engine = AlertEngine::new(rules(vec![]));
+    assert!(
+        engine
+            .evaluate(&pool, &tick("2026-04-02 00:00:05", &[]))
+            .await
+            .is_empty()
+    );
+
+    // An in-memory engine neither reads nor stores the state.
+    db::save_delta_at(&pool, "wwan0", &1_500, &0, ts("2026-04-02 10:00:00"))
+        .await
+        .unwrap();
+    let mut engine = AlertEngine::new(rules(vec![])).in_memory();
+    let fired = engine
+        .evaluate(&pool, &tick("2026-04-02 10:00:05", &[]))
+        .await;
+    assert_eq!(fired[0].state, AlertState::Fired);
+    assert!(!db::alert_active(&pool, "cap").await.unwrap());
+}
+
+#[test]
+fn test_tariff_time_window_parse_and_contains() {
+    use ambr::tariff::TimeWindow;
+
+    let peak: TimeWindow = "Mon-Fri 07:30-23:00".parse().unwrap();
+    assert_eq!(peak.to_string(), "mon,tue,wed,thu,fri 07:30-23:00");
+    assert!(peak.contains(ts("2026-03-02 07:30:00"))); // Monday
+    assert!(!peak.contains(ts("2026-03-02 07:29:59")));
+    assert!(!peak.contains(ts("2026-03-02 23:00:00")));
+    assert!(!peak.contains(ts("2026-03-07 12:00:00"))); // Saturday
+
+    // Wraps past midnight; the weekday is the day the window opens.
+    let night: TimeWindow = "Fri 22:00-06:00".parse().unwrap();
+    assert!(night.contains(ts("2026-03-07 03:00:00"))); // Saturday morning
+    assert!(!night.contains(ts("2026-03-06 03:00:00"))); // Friday morning
+
+    let weekend: TimeWindow = "Sat,Sun 00:00-24:00".parse().unwrap();
+    assert!(weekend.contains(ts("2026-03-08 23:59:59")));
+    assert!(!weekend.contains(ts("2026-03-09 00:00:00")));
+
+    let wrapped: TimeWindow = "Fri-Mon 12:00-13:00".parse().unwrap();
+    assert_eq!(wrapped.days, [true, false, false, false, true, true, true]);
+
+    assert!("Mon 25:00-26:00".parse::<TimeWindow>().is_err());
+    assert!("Funday 10:00-11:00".parse::<TimeWindow>().is_err());
+    assert!("10:00".parse::<TimeWindow>().is_err());
+}
+
+const GIB: i64 = 1024 * 1024 * 1024;
+
+fn home_tariff() -> ambr::tariff::Tariff {
+    let cfg = ambr::config::Config::parse(
+        r#"
+        [[tariff]]
+        name = "home"
+        currency = "EUR"
+        price_per_gib = 1.0
+        interfaces = ["eth0"]
+        free_allowance = "1GiB"
+
+          [[tariff.window]]
+          when = "Sat,Sun 00:00-24:00"
+          price_per_gib = 0
+
+          [[tariff.window]]
+          when = "Mon-Fri 07:30-23:00"
+          price_per_gib = 4.0
+        "#,
+    )
+    .unwrap();
+    assert_eq!(cfg.tariffs.len(), 1);
+    cfg.tariffs.into_iter().next().unwrap()
+}
+
+#[test]
+fn test_tariff_prorates_boundaries_and_allowance() {
+    let mut t = home_tariff();
+    assert_eq!(t.direction, ambr::quota::Direction::Both);
+    assert!((t.price_at(ts("2026-03-02 08:00:00")) - 4.0).abs() < 1e-9);
+    assert!((t.price_at(ts("2026-03-07 08:00:00")) - 0.0).abs() < 1e-9);
+    assert!((t.price_at(ts("2026-03-02 06:00:00")) - 1.0).abs() < 1e-9);
+
+    // Without allowance: 07:00-08:00 Monday is half off-peak, half peak.
+    t.free_allowance = 0;
+    let costs = t.hourly_costs(&[(ts("2026-03-02 07:00:00"), 2 * GIB)]);
+    assert!((costs[0] - 5.0).abs() < 1e-9, "{:?}", costs);
+
+    // Weekend traffic is free and leaves the allowance untouched; the
+    // allowance resets with the calendar month.
+    let t = home_tariff();
+    let costs = t.hourly_costs(&[
+        (ts("2026-03-07 10:00:00"), 5 * GIB),
+        (ts("2026-03-09 10:00:00"), 2 * GIB),
+        (ts("2026-03-09 11:00:00"), GIB),
+        (ts("2026-04-01 10:00:00"), GIB),
+    ]);
+    let expected = [0.0, 4.0, 4.0, 0.0];
+    for (c, e) in costs.iter().zip(expected) {
+        assert!((c - e).abs() < 1e-9, "{:?}", costs);
+    }
+}
+
+#[tokio::test]
+async fn test_tariff_cost_by_period_from_db() {
+    let pool = test_pool().await;
+    let t = home_tariff();
+    for (iface, at) in [
+        ("eth0", "2026-03-02 07:10:00"),
+        ("eth0", "2026-03-02 07:50:00"),
+        ("eth0", "2026-03-03 12:00:00"),
+        ("eth0", "2026-03-03 12:30:00"),
+        ("wlan0", "2026-03-03 12:00:00"),
+    ] {
+        db::save_delta_at(&pool, iface, &(GIB / 2), &(GIB / 2), ts(at))
+            .await
+            .unwrap();
+    }
+
+    // 07:00-08:00 Monday splits into 1 GiB off-peak and 1 GiB peak; the
+    // off-peak half uses up the free GiB, leaving 4.0. wlan0 is not billed.
+    let by_day = ambr::tariff::cost_by_period(
+        &pool,
+        &t,
+        db::Granularity::Day,
+        ts("2026-03-02 00:00:00"),
+        ts("2026-03-04 00:00:00"),
+        db::HostFilter::Local,
+    )
+    .await
+    .unwrap();
+    assert!((by_day["2026-03-02"] - 4.0).abs() < 1e-9, "{:?}", by_day);
+    assert!((by_day["2026-03-03"] - 8.0).abs() < 1e-9, "{:?}", by_day);
+
+    // Starting mid-month still accounts for the allowance used earlier.
+    let by_hour = ambr::tariff::cost_by_period(
+        &pool,
+        &t,
+        db::Granularity::Hour,
+        ts("2026-03-03 00:00:00"),
+        ts("2026-03-04 00:00:00"),
+        db::HostFilter::Local,
+    )
+    .await
+    .unwrap();
+    assert_eq!(by_hour.len(), 1);
+    assert!(
+        (by_hour["2026-03-03 12:00"] - 8.0).abs() < 1e-9,
+        "{:?}",
+        by_hour
+    );
+}
+
+#[tokio::test]
+async fn test_metrics_endpoint_scrape() {
+    use ambr::recorder::{InterfaceCounters, RecorderState, SharedState};
+
+    let pool = test_pool().await;
+    db::add_quota(&pool, &monthly_quota("mobile", 1_000_000, 1))
+        .await
+        .unwrap();
+    let now = chrono::Utc::now().naive_utc();
+    db::save_delta_at(&pool, "wwan0", &250_000, &0, now)
+        .await
+        .unwrap();
+
+    let mut state = RecorderState {
+        last_sample: Some(now - chrono::Duration::seconds(3)),
+        rounds: 7,
+        insert_errors: 2,
+        ..Default::default()
+    };
+    state.interfaces.insert(
+        "wwan0".to_string(),
+        InterfaceCounters {
+            rx_bytes: 1234,
+            tx_bytes: 567,
+            rx_packets: 10,
+            tx_packets: 9,
+            rx_errors: 1,
+            tx_errors: 0,
+        },
+    );
+    state
+        .interfaces
+        .insert("we\"ird".to_string(), InterfaceCounters::default());
+    let shared = SharedState::new(std::sync::Mutex::new(state));
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(ambr::metrics::serve(listener, shared.clone(), pool));
+
+    let resp = reqwest::get(format!("http://{}/metrics", addr))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert!(
+        resp.headers()["content-type"]
+            .to_str()
+            .unwrap()
+            .starts_with("text/plain; version=0.0.4")
+    );
+    let body = resp.text().await.unwrap();
+    for line in [
+        "# TYPE ambr_rx_bytes_total counter",
+        "ambr_rx_bytes_total{interface=\"wwan0\"} 1234",
+        "ambr_tx_bytes_total{interface=\"wwan0\"} 567",
+        "ambr_rx_packets_total{interface=\"wwan0\"} 10",
+        "ambr_rx_errors_total{interface=\"wwan0\"} 1",
+        "ambr_rx_bytes_total{interface=\"we\\\"ird\"} 0",
+        "# TYPE ambr_quota_used_bytes gauge",
+        "ambr_quota_used_bytes{quota=\"mobile\"} 250000",
+        "ambr_quota_limit_bytes{quota=\"mobile\"} 1000000",
+        "ambr_quota_used_ratio{quota=\"mobile\"} 0.25",
+        "ambr_recorder_rounds_total 7",
+        "ambr_recorder_insert_errors_total 2",
+    ] {
+        assert!(body.lines().any(|l| l == line), "missing {line}\n{body}");
+    }
+    let age: f64 = body
+        .lines()
+        .find_map(|l| l.strip_prefix("ambr_recorder_last_sample_age_seconds "))
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((3.0..60.0).contains(&age), "{age}");
+
+    // The endpoint reads live state on every scrape.
+    shared.lock().unwrap().rounds = 8;
+    let body = reqwest::get(format!("http://{}/metrics", addr))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(body.contains("ambr_recorder_rounds_total 8\n"));
+
+    let missing = reqwest::get(format!("http://{}/nope", addr)).await.unwrap();
+    assert_eq!(missing.status(), 404);
+    server.abort();
+}
+
+#[tokio::test]
+async fn test_api_usage_pagination_and_filters() {
+    let pool = test_pool().await;
+    for (iface, at, rx, tx) in [
+        ("eth0", "2026-09-01 10:00:00", 1_000, 10),
+        ("eth0", "2026-09-01 11:30:00", 2_000, 20),
+        ("eth0", "2026-09-02 09:00:00", 4_000, 40),
+        ("wlan0", "2026-09-02 09:00:00", 8_000, 80),
+        ("eth0", "2026-09-03 23:59:59", 16_000, 160),
+    ] {
+        db::save_delta_at(&pool, iface, &rx, &tx, ts(at))
+            .await
+            .unwrap();
+    }
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    let server = tokio::spawn(ambr::api::serve(listener, pool));
+    let get = |path: &str| {
+        let url = format!("{}{}", base, path);
+        async move {
+            let resp = reqwest::get(url).await.unwrap();
+            let status = resp.status().as_u16();
+            (status, resp.json::<serde_json::Value>().await.unwrap())
+        }
+    };
+
+    let (status, page) = get("/usage/day?interface=eth0&limit=2").await;
+    assert_eq!(status, 200);
+    assert_eq!(page["granularity"], "day");
+    assert_eq!(page["next_offset"], 2);
+    let items = page["items"].as_array().unwrap();
+    assert_eq!(items.len(), 2);
+    assert_eq!(items[0]["period"], "2026-09-03");
+    assert_eq!(items[0]["rx_bytes"], 16_000);
+    assert_eq!(items[1]["period"], "2026-09-02");
+    assert_eq!(items[1]["total_bytes"], 4_040);
+
+    let (_, page) = get("/usage/day?interface=eth0&limit=2&offset=2").await;
+    assert!(page["next_offset"].is_null());
+    assert_eq!(page["items"][0]["period"], "2026-09-01");
+    assert_eq!(page["items"][0]["rx_bytes"], 3_000);
+
+    // [from, to) on raw timestamps.
+    let (_, page) = get("/usage/hour?from=2026-09-01T11:00&to=2026-09-03").await;
+    let periods: Vec<&str> = page["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r["period"].as_str().unwrap())
+        .collect();
+    assert_eq!(periods, ["2026-09-02 09:00", "2026-09-01 11:00"]);
+    assert_eq!(page["items"][0]["rx_bytes"], 12_000);
+
+    let (_, page) = get("/usage/month").await;
+    assert_eq!(page["items"][0]["rx_bytes"], 31_000);
+
+    let (_, ifaces) = get("/interfaces").await;
+    assert_eq!(ifaces[0]["interface"], "eth0");
+    assert_eq!(ifaces[0]["first_seen"], "2026-09-01T10:00:00");
+    assert_eq!(ifaces[0]["last_seen"], "2026-09-03T23:59:59");
+    assert_eq!(ifaces[1]["tx_bytes"], 80);
+    let (_, local) = get("/interfaces?host=local").await;
+    assert_eq!(local, ifaces);
+
+    let (status, live) = get("/live").await;
+    assert_eq!(status, 200);
+    assert!(live["last_5min"]["total_mib"].is_number());
+    assert!(live["by_interface"].is_array());
+
+    for bad in [
+        "/usage/fortnight",
+        "/usage/day?from=yesterday",
+        "/usage/day?limit=0",
+        "/usage/day?limit=5000",
+        "/interfaces?host=nas",
+    ] {
+        let (status, body) = get(bad).await;
+        assert_eq!(status, 400, "{bad}");
+        assert!(body["error"].is_string());
+    }
+
+    let (_, spec) = get("/openapi.json").await;
+    assert_eq!(spec["openapi"], "3.0.3");
+    for path in ["/usage/{granularity}", "/live", "/interfaces"] {
+        assert!(spec["paths"][path]["get"].is_object(), "{path}");
+    }
+    server.abort();
+}
+
+#[tokio::test]
+async fn test_rollup_hourly_merges_old_samples() {
+    let pool = test_pool().await;
+    for (iface, at, rx) in [
+        ("eth0", "2026-09-01 10:00:10", 1),
+        ("eth0", "2026-09-01 10:30:00", 2),
+        ("eth0", "2026-09-01 10:59:50", 4),
+        ("wlan0", "2026-09-01 10:15:00", 8),
+        ("eth0", "2026-09-01 11:05:00", 16),
+        ("eth0", "2026-09-01 11:10:00", 32),
+    ] {
+        db::save_delta_at(&pool, iface, &rx, &(rx * 10), ts(at))
+            .await
+            .unwrap();
+    }
+    let all = db::UsageFilter::default();
+    let hourly = || db::usage_page(&pool, db::Granularity::Hour, &all, None, None, 100, 0);
+    let before = hourly().await.unwrap();
+
+    // The cutoff is rounded down to the hour, so 11:xx stays raw.
+    let (merged, written) = db::rollup_hourly(&pool, ts("2026-09-01 11:20:00"))
+        .await
+        .unwrap();
+    assert_eq!((merged, written), (3, 1));
+    let rows: Vec<(String, i64, i64, String)> = sqlx::query_as(
+        "SELECT interface, rx_bytes, tx_bytes, timestamp FROM traffic ORDER BY interface, timestamp",
+    )
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+    let rows: Vec<(&str, i64, i64, &str)> = rows
+        .iter()
+        .map(|(i, rx, tx, t)| (i.as_str(), *rx, *tx, t.as_str()))
+        .collect();
+    assert_eq!(
+        rows,
+        [
+            ("eth0", 7, 70, "2026-09-01 10:00:00"),
+            ("eth0", 16, 160, "2026-09-01 11:05:00"),
+            ("eth0", 32, 320, "2026-09-01 11:10:00"),
+            ("wlan0", 8, 80, "2026-09-01 10:15:00"),
+        ]
+    );
+    assert_eq!(
+        hourly().await.unwrap(),
+        before,
+        "hourly totals are unchanged"
+    );
+
+    // Nothing left to merge.
+    assert_eq!(
+        db::rollup_hourly(&pool, ts("2026-09-01 11:20:00"))
+            .await
+            .unwrap(),
+        (0, 0)
+    );
+}
+
+#[tokio::test]
+async fn test_export_formats_and_filters() {
+    use ambr::export::{ExportOptions, Format, export};
+
+    let pool = test_pool().await;
+    // Above 2^53, so a float anywhere on the way would show.
+    let big: i64 = 9_007_199_254_740_993;
+    for (iface, at, rx, tx) in [
+        ("eth0", "2026-08-31 23:59:00", 1, 2),
+        ("eth0", "2026-09-01 10:00:00", big, 5),
+        ("wlan,x", "2026-09-01 10:30:00", 3, 4),
+        ("eth0", "2026-09-07 00:00:00", 10, 20),
+    ] {
+        db::save_delta_at(&pool, iface, &rx, &tx, ts(at))
+            .await
+            .unwrap();
+    }
+    let run = |opts: ExportOptions| {
+        let pool = pool.clone();
+        async move {
+            let mut out = Vec::new();
+            let rows = export(&pool, &opts, &mut out).await.unwrap();
+            (rows, String::from_utf8(out).unwrap())
+        }
+    };
+
+    assert_eq!(
+        run(ExportOptions::default()).await,
+        (
+            4,
+            "time,interface,rx_bytes,tx_bytes,total_bytes\n\
+             2026-08-31T23:59:00,eth0,1,2,3\n\
+             2026-09-01T10:00:00,eth0,9007199254740993,5,9007199254740998\n\
+             2026-09-01T10:30:00,\"wlan,x\",3,4,7\n\
+             2026-09-07T00:00:00,eth0,10,20,30\n"
+                .to_string()
+        )
+    );
+
+    // Weeks start on Monday.
+    let weekly = ExportOptions {
+        granularity: Some(db::Granularity::Week),
+        filter: db::UsageFilter {
+            interface: Some("eth0".into()),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    assert_eq!(
+        run(weekly).await.1,
+        "time,interface,rx_bytes,tx_bytes,total_bytes\n\
+         2026-08-31T00:00:00,eth0,9007199254740994,7,9007199254741001\n\
+         2026-09-07T00:00:00,eth0,10,20,30\n"
+    );
+
+    let monthly = ExportOptions {
+        format: Format::Json,
+        granularity: Some(db::Granularity::Month),
+        from: Some(ts("2026-09-01 00:00:00")),
+        to: Some(ts("2026-09-07 00:00:00")),
+        ..Default::default()
+    };
+    assert_eq!(
+        run(monthly).await.1,
+        "[\n\
+         {\"time\":\"2026-09-01T00:00:00\",\"interface\":\"eth0\",\"rx_bytes\":9007199254740993,\"tx_bytes\":5,\"total_bytes\":9007199254740998},\n\
+         {\"time\":\"2026-09-01T00:00:00\",\"interface\":\"wlan,x\",\"rx_bytes\":3,\"tx_bytes\":4,\"total_bytes\":7}\n\
+         ]\n"
+    );
+
+    let daily = ExportOptions {
+        format: Format::Ndjson,
+        granularity: Some(db::Granularity::Day),
+        from: Some(ts("2026-09-01 00:00:00")),
+        ..Default::default()
+    };
+    let (rows, out) = run(daily).await;
+    assert_eq!(rows, 3);
+    let lines: Vec<serde_json::Value> = out
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(lines[0]["time"], "2026-09-01T00:00:00");
+    assert_eq!(lines[0]["rx_bytes"].as_i64(), Some(big));
+    assert_eq!(lines[2]["time"], "2026-09-07T00:00:00");
+
+    let empty = ExportOptions {
+        format: Format::Json,
+        from: Some(ts("2030-01-01 00:00:00")),
+        ..Default::default()
+    };
+    assert_eq!(run(empty).await, (0, "[]\n".to_string()));
+}
+
+#[tokio::test]
+async fn test_vnstat_db_import_tops_up_coarser_levels() {
+    use ambr::vnstat::{Clock, load};
+    use sqlx::sqlite::SqliteConnectOptions;
+
+    // A minimal vnStat 2.x database.
+    let path = temp_path("vnstat.db");
+    let vn = SqlitePool::connect_with(
+        SqliteConnectOptions::new()
+            .filename(&path)
+            .create_if_missing(true),
+    )
+    .await
+    .unwrap();
+    for sql in [
+        "CREATE TABLE info (id INTEGER PRIMARY KEY, name TEXT, value TEXT)",
+        "CREATE TABLE interface (id INTEGER PRIMARY KEY, name TEXT, alias TEXT)",
+        "INSERT INTO interface (id, name) VALUES (1, 'eth0')",
+    ] {
+        sqlx::query(sql).execute(&vn).await.unwrap();
+    }
+    for (table, date, rx, tx) in [
+        ("fiveminute", "2026-09-10 10:05:00", 30, 2),
+        ("hour", "2026-09-10 10:00:00", 100, 10),
+        ("hour", "2026-09-10 11:00:00", 50, 5),
+        ("day", "2026-09-09", 7, 3),
+        ("day", "2026-09-10", 1000, 100),
+        ("month", "2026-08-01", 42, 0),
+        ("month", "2026-09-01", 5000, 500),
+        ("year", "2026-01-01", 6000, 600),
+    ] {
+        sqlx::query(&format!(
+            "CREATE TABLE IF NOT EXISTS {} (id INTEGER PRIMARY KEY, interface INTEGER, date DATE, rx INTEGER, tx INTEGER)",
+            table
+        ))
+        .execute(&vn)
+        .await
+        .unwrap();
+        sqlx::query(&format!(
+            "INSERT INTO {} (interface, date, rx, tx) VALUES (1, ?, ?, ?)",
+            table
+        ))
+        .bind(date)
+        .bind(rx)
+        .bind(tx)
+        .execute(&vn)
+        .await
+        .unwrap();
+    }
+    vn.close().await;
+
+    let pool = test_pool().await;
+    // ambr already saw more than vnStat in the 11:00 hour.
+    db::save_delta_at(&pool, "eth0", &60, &1, ts("2026-09-10 11:30:00"))
+        .await
+        .unwrap();
+
+    let periods = load(&path, Clock::Utc).await.unwrap();
+    assert_eq!(periods.len(), 8);
+    let summary = db::import_periods(&pool, &periods).await.unwrap();
+    assert_eq!(summary.rows, 8);
+    assert_eq!((summary.rx_bytes, summary.tx_bytes), (5940, 599));
+
+    let rows: Vec<(String, i64, i64)> =
+        sqlx::query_as("SELECT timestamp, rx_bytes, tx_bytes FROM traffic ORDER BY timestamp, id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    let rows: Vec<(&str, i64, i64)> = rows
+        .iter()
+        .map(|(t, rx, tx)| (t.as_str(), *rx, *tx))
+        .collect();
+    assert_eq!(
+        rows,
+        [
+            ("2026-01-01 00:00:00", 958, 100),
+            ("2026-08-01 00:00:00", 42, 0),
+            ("2026-09-01 00:00:00", 3993, 397),
+            ("2026-09-09 00:00:00", 7, 3),
+            ("2026-09-10 00:00:00", 840, 85),
+            ("2026-09-10 10:00:00", 70, 8),
+            ("2026-09-10 10:05:00", 30, 2),
+            ("2026-09-10 11:00:00", 0, 4),
+            ("2026-09-10 11:30:00", 60, 1),
+        ]
+    );
+    let (rx, tx): (i64, i64) = sqlx::query_as("SELECT SUM(rx_bytes), SUM(tx_bytes) FROM traffic")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!((rx, tx), (6000, 600), "year totals match vnStat");
+
+    // Importing again adds nothing.
+    let again = db::import_periods(&pool, &periods).await.unwrap();
+    assert_eq!((again.periods, again.rows), (8, 0));
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_vnstat_json_parse() {
+    use ambr::vnstat::{Clock, parse_json};
+
+    let json = r#"{"vnstatversion":"2.12","jsonversion":"2","interfaces":[{"name":"wlan0","alias":"",
+        "traffic":{"total":{"rx":3,"tx":4},
+          "hour":[{"id":1,"date":{"year":2026,"month":9,"day":10},"time":{"hour":10,"minute":0},"timestamp":1789034400,"rx":1,"tx":2}],
+          "month":[{"id":2,"date":{"year":2026,"month":2},"rx":3,"tx":4}],
+          "year":[{"id":3,"date":{"year":2026},"rx":3,"tx":4}]}}]}"#;
+    let periods = parse_json(json, Clock::Utc).unwrap();
+    let got: Vec<(&str, NaiveDateTime, NaiveDateTime, i64, i64)> = periods
+        .iter()
+        .map(|p| (p.interface.as_str(), p.start, p.end, p.rx_bytes, p.tx_bytes))
+        .collect();
+    assert_eq!(
+        got,
+        [
+            (
+                "wlan0",
+                ts("2026-09-10 10:00:00"),
+                ts("2026-09-10 11:00:00"),
+                1,
+                2
+            ),
+            (
+                "wlan0",
+                ts("2026-02-01 00:00:00"),
+                ts("2026-03-01 00:00:00"),
+                3,
+                4
+            ),
+            (
+                "wlan0",
+                ts("2026-01-01 00:00:00"),
+                ts("2027-01-01 00:00:00"),
+                3,
+                4
+            ),
+        ]
+    );
+
+    let old = r#"{"vnstatversion":"1.18","jsonversion":"1","interfaces":[]}"#;
+    assert!(parse_json(old, Clock::Utc).is_err());
+}
+
+#[tokio::test]
+async fn test_influx_export_import_roundtrip() {
+    use ambr::export::{ExportOptions, Format, export};
+    use ambr::influx::{Precision, read};
+
+    let pool = test_pool().await;
+    for (iface, at, rx, tx) in [
+        ("eth0", "2026-09-10 10:00:00", 1_048_576, 65_536),
+        ("my wlan,0", "2026-09-10 10:00:10", 3, 4),
+    ] {
+        db::save_delta_at(&pool, iface, &rx, &tx, ts(at))
+            .await
+            .unwrap();
+    }
+    let opts = ExportOptions {
+        format: Format::Influx,
+        ..Default::default()
+    };
+    let mut out = Vec::new();
+    export(&pool, &opts, &mut out).await.unwrap();
+    let text = String::from_utf8(out).unwrap();
+    assert_eq!(
+        text,
+        "ambr_traffic,interface=eth0 rx=1048576i,tx=65536i 1789034400000000000\n\
+         ambr_traffic,interface=my\\ wlan\\,0 rx=3i,tx=4i 1789034410000000000\n"
+    );
+
+    let copy = test_pool().await;
+    let periods = read(text.as_bytes(), Precision::Nanoseconds).unwrap();
+    db::import_periods(&copy, &periods).await.unwrap();
+    let all = db::UsageFilter::default();
+    assert_eq!(
+        db::usage_page(&copy, db::Granularity::Hour, &all, None, None, 10, 0)
+            .await
+            .unwrap(),
+        db::usage_page(&pool, db::Granularity::Hour, &all, None, None, 10, 0)
+            .await
+            .unwrap()
+    );
+    // Same points again: already there.
+    assert_eq!(db::import_periods(&copy, &periods).await.unwrap().rows, 0);
+
+    // Hand-written fixture: seconds, float fields, comments, other series.
+    let fixture = "# fixture\n\
+        cpu,host=a usage=1 1789034400\n\
+        ambr_traffic,interface=eth0,host=a tx=20,rx=10 1789034400\n\
+        ambr_traffic,interface=eth0 rx=5i,tx=0i 1789034400\n";
+    let periods = read(fixture.as_bytes(), Precision::Seconds).unwrap();
+    assert_eq!(periods.len(), 1, "same second is summed");
+    assert_eq!(periods[0].start, ts("2026-09-10 10:00:00"));
+    assert_eq!((periods[0].rx_bytes, periods[0].tx_bytes), (15, 20));
+
+    let err = read(
+        "ambr_traffic,interface=eth0 rx=1i 1".as_bytes(),
+        Precision::Seconds,
+    )
+    .unwrap_err();
+    assert!(err.to_string().starts_with("line 1:"));
+}
+
+#[test]
+fn test_push_config_and_backoff() {
+    use ambr::push::{MAX_BACKOFF, backoff};
+    use std::time::Duration;
+
+    let config = ambr::config::Config::parse(
+        r#"
+        [push]
+        url = "http://collector:9193/ingest"
+        token = "s3cret"
+        machine_id = "pi-1"
+        "#,
+    )
+    .unwrap();
+    let push = config.push.unwrap();
+    assert_eq!(push.url, "http://collector:9193/ingest");
+    assert_eq!((push.interval_secs, push.batch_size), (60, 1000));
+    let host = ambr::push::HostIdentity::from_config(&push);
+    assert_eq!(host.machine_id, "pi-1");
+
+    assert_eq!(backoff(1), Duration::from_secs(5));
+    assert_eq!(backoff(2), Duration::from_secs(10));
+    assert_eq!(backoff(4), Duration::from_secs(40));
+    assert_eq!(backoff(30), MAX_BACKOFF);
+}
+
+#[tokio::test]
+async fn test_push_outbox_to_server() {
+    use ambr::push::{Batch, HostIdentity, IngestReply, PushConfig, Pusher, encode};
+
+    let sender = test_pool().await;
+    for (iface, at, rx) in [
+        ("eth0", "2026-09-10 10:00:00", 100),
+        ("eth0", "2026-09-10 10:00:10", 200),
+        ("wlan0", "2026-09-10 10:00:10", 5),
+    ] {
+        db::save_delta_queued(&sender, iface, &rx, &1, ts(at))
+            .await
+            .unwrap();
+    }
+    assert_eq!(db::outbox_len(&sender).await.unwrap(), 3);
+
+    let collector = test_pool().await;
+    // Something the collector recorded itself stays separate from pushed rows.
+    db::save_delta_at(&collector, "eth0", &7, &7, ts("2026-09-10 10:00:00"))
+        .await
+        .unwrap();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(ambr::ingest::serve(
+        listener,
+        collector.clone(),
+        Some("s3cret".into()),
+    ));
+    let config = |token: &str| PushConfig {
+        url: format!("http://{}/ingest", addr),
+        token: Some(token.into()),
+        interval_secs: 1,
+        batch_size: 2,
+        hostname: Some("pi".into()),
+        machine_id: Some("pi-1".into()),
+        labels: Default::default(),
+    };
+
+    // A wrong token is refused and nothing leaves the outbox.
+    assert!(
+        Pusher::new(sender.clone(), config("wrong"))
+            .flush()
+            .await
+            .is_err()
+    );
+    assert_eq!(db::outbox_len(&sender).await.unwrap(), 3);
+
+    // Two batches of at most two samples.
+    let pusher = Pusher::new(sender.clone(), config("s3cret"));
+    assert_eq!(pusher.flush().await.unwrap(), 3);
+    assert_eq!(db::outbox_len(&sender).await.unwrap(), 0);
+    assert_eq!(pusher.flush().await.unwrap(), 0);
+
+    let rows: Vec<(Option<String>, String, i64)> = sqlx::query_as(
+        "SELECT h.hostname, t.interface, t.rx_bytes FROM traffic t \
+         LEFT JOIN hosts h ON h.id = t.host_id ORDER BY t.id",
+    )
+    .fetch_all(&collector)
+    .await
+    .unwrap();
+    assert_eq!(
+        rows,
+        [
+            (None, "eth0".to_string(), 7),
+            (Some("pi".to_string()), "eth0".to_string(), 100),
+            (Some("pi".to_string()), "eth0".to_string(), 200),
+            (Some("pi".to_string()), "wlan0".to_string(), 5),
+        ]
+    );
+
+    // A batch resent after a lost reply is not stored twice.
+    let resend = Batch {
+        host: HostIdentity {
+            hostname: "pi".into(),
+            machine_id: "pi-1".into(),
+            labels: Default::default(),
+        },
+        database_id: Some(db::database_id(&sender).await.unwrap()),
+        samples: vec![db::OutboxRow {
+            seq: 3,
+            interface: "wlan0".into(),
+            timestamp: ts("2026-09-10 10:00:10"),
+            rx_bytes: 5,
+            tx_bytes: 1,
+        }],
+    };
+    let reply: IngestReply = reqwest::Client::new()
+        .post(format!("http://{}/ingest", addr))
+        .bearer_auth("s3cret")
+        .header("content-encoding", "gzip")
+        .body(encode(&resend).unwrap())
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(
+        reply,
+        IngestReply {
+            stored: 0,
+            skipped: 1
+        }
+    );
+
+    // The pusher's database is recreated, so its sequence numbers start over.
+    let recreated = test_pool().await;
+    assert_ne!(
+        db::database_id(&recreated).await.unwrap(),
+        db::database_id(&sender).await.unwrap()
+    );
+    db::save_delta_queued(&recreated, "eth0", &300, &1, ts("2026-09-10 10:00:30"))
+        .await
+        .unwrap();
+    let pusher_after = Pusher::new(recreated, config("s3cret"));
+    assert_eq!(pusher_after.flush().await.unwrap(), 1);
+    let (pushed,): (i64,) =
+        sqlx::query_as("SELECT COUNT(*) FROM traffic WHERE host_id IS NOT NULL")
+            .fetch_one(&collector)
+            .await
+            .unwrap();
+    assert_eq!(pushed, 4);
+    assert_eq!(db::hosts(&collector).await.unwrap()[0].last_seq, 1);
+    server.abort();
+
+    // With the collector gone, new samples wait in the outbox.
+    db::save_delta_queued(&sender, "eth0", &1, &1, ts("2026-09-10 10:00:20"))
+        .await
+        .unwrap();
+    assert!(pusher.flush().await.is_err());
+    assert_eq!(db::outbox_len(&sender).await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn test_ingest_detects_restarted_sequence() {
+    async fn ingest(
+        pool: &sqlx::SqlitePool,
+        database_id: Option<&str>,
+        seqs: std::ops::RangeInclusive<i64>,
+    ) -> db::IngestSummary {
+        let rows: Vec<_> = seqs
+            .map(|seq| db::OutboxRow {
+                seq,
+                interface: "eth0".into(),
+                timestamp: ts("2026-09-10 10:00:00"),
+                rx_bytes: 1,
+                tx_bytes: 1,
+            })
+            .collect();
+        db::ingest_samples(pool, "pi", "pi-1", database_id, &Default::default(), &rows)
+            .await
+            .unwrap()
+    }
+    let pool = test_pool().await;
+    let summary = |stored, skipped, restarted| db::IngestSummary {
+        stored,
+        skipped,
+        restarted,
+    };
+
+    assert_eq!(ingest(&pool, Some("a"), 1..=2).await, summary(2, 0, false));
+    assert_eq!(ingest(&pool, Some("a"), 1..=2).await, summary(0, 2, false));
+    // Another database under the same machine id starts from 1 again.
+    assert_eq!(ingest(&pool, Some("b"), 1..=1).await, summary(1, 0, true));
+    assert_eq!(ingest(&pool, Some("b"), 1..=5).await, summary(4, 1, false));
+    // Without an id, a batch ending below the last seq is a restart too.
+    assert_eq!(ingest(&pool, None, 1..=2).await, summary(2, 0, true));
+    assert_eq!(db::hosts(&pool).await.unwrap()[0].last_seq, 2);
+}
+
+#[tokio::test]
+async fn test_multi_host_filters() {
+    use db::HostFilter;
+
+    let pool = test_pool().await;
+    let now = chrono::Utc::now().naive_utc();
+    db::save_delta_at(&pool, "eth0", &100, &10, now)
+        .await
+        .unwrap();
+    let labels = [("site".to_string(), "office".to_string())].into();
+    let sample = |seq, rx| db::OutboxRow {
+        seq,
+        interface: "eth0".into(),
+        timestamp: now,
+        rx_bytes: rx,
+        tx_bytes: 0,
+    };
+    db::ingest_samples(
+        &pool,
+        "pi",
+        "pi-1",
+        None,
+        &labels,
+        &[sample(1, 1000), sample(2, 24)],
+    )
+    .await
+    .unwrap();
+
+    let hosts = db::hosts(&pool).await.unwrap();
+    assert_eq!(hosts.len(), 1);
+    assert_eq!((hosts[0].hostname.as_str(), hosts[0].last_seq), ("pi", 2));
+    assert_eq!(hosts[0].labels, labels);
+    let pi = HostFilter::Host(hosts[0].id);
+    assert_eq!(db::resolve_host(&pool, "pi").await.unwrap(), Some(pi));
+    assert_eq!(db::resolve_host(&pool, "pi-1").await.unwrap(), Some(pi));
+    assert_eq!(
+        db::resolve_host(&pool, "local").await.unwrap(),
+        Some(HostFilter::Local)
+    );
+    assert_eq!(db::resolve_host(&pool, "nas").await.unwrap(), None);
+
+    let rx = |rows: Vec<db::PeriodRow>| (rows[0].rx_mib * MIB).round() as i64;
+    for (host, want) in [
+        (HostFilter::All, 1124),
+        (HostFilter::Local, 100),
+        (pi, 1024),
+    ] {
+        assert_eq!(rx(db::usage_by_hour(&pool, 1, host).await.unwrap()), want);
+        assert_eq!(rx(db::usage_by_day(&pool, 1, host).await.unwrap()), want);
+        assert_eq!(rx(db::usage_by_week(&pool, 1, host).await.unwrap()), want);
+        assert_eq!(rx(db::usage_by_month(&pool, 1, host).await.unwrap()), want);
+        let filter = db::UsageFilter {
+            host,
+            ..Default::default()
+        };
+        let page = db::usage_page(&pool, db::Granularity::Day, &filter, None, None, 1, 0)
+            .await
+            .unwrap();
+        assert_eq!(page[0].rx_bytes, want);
+        let ifaces = db::interfaces(&pool, host).await.unwrap();
+        assert_eq!(ifaces.len(), 1);
+        assert_eq!(ifaces[0].rx_bytes, want);
+    }
+
+    // This machine's features only count its own traffic.
+    let (rx, _, _) = db::recent_totals(&pool, 1, HostFilter::Local)
+        .await
+        .unwrap();
+    assert!((rx * MIB - 100.0).abs() < 1e-6);
+    let live = db::recent_by_interface(&pool, 1, HostFilter::Local)
+        .await
+        .unwrap();
+    assert!((live[0].rx_mib * MIB - 100.0).abs() < 1e-6);
+    let since = now - chrono::Duration::minutes(1);
+    assert_eq!(
+        db::bytes_since(&pool, since, Some("eth0"), HostFilter::Local)
+            .await
+            .unwrap(),
+        (100, 10)
+    );
+    assert_eq!(
+        db::bytes_since(&pool, since, None, HostFilter::All)
+            .await
+            .unwrap(),
+        (1124, 10)
+    );
+    let hour = |h: &[(chrono::NaiveDateTime, i64, i64)]| h.iter().map(|r| r.1).sum::<i64>();
+    let hours = db::hourly_bytes(
+        &pool,
+        since,
+        now + chrono::Duration::seconds(1),
+        &[],
+        HostFilter::Local,
+    )
+    .await
+    .unwrap();
+    assert_eq!(hour(&hours), 100);
+    let split = db::split_usage(
+        &pool,
+        since,
+        now + chrono::Duration::seconds(1),
+        &[],
+        &[],
+        HostFilter::Local,
+    )
+    .await
+    .unwrap();
+    assert_eq!(split.counted_rx, 100);
+}
+
+#[tokio::test]
+async fn test_init_db_upgrades_single_host_database() {
+    let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+    sqlx::query(
+        "CREATE TABLE traffic (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            interface TEXT NOT NULL,
+            rx_bytes INTEGER NOT NULL,
+            tx_bytes INTEGER NOT NULL,
+            timestamp DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    sqlx::query("INSERT INTO traffic (interface, rx_bytes, tx_bytes) VALUES ('eth0', 5, 6)")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    db::init_db(&pool).await.unwrap();
+    db::init_db(&pool).await.unwrap();
+    assert!(db::hosts(&pool).await.unwrap().is_empty());
+    let all = db::usage_by_day(&pool, 1, db::HostFilter::All)
+        .await
+        .unwrap();
+    let local = db::usage_by_day(&pool, 1, db::HostFilter::Local)
+        .await
+        .unwrap();
+    assert_eq!(all, local);
+    assert_eq!(all.len(), 1);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_control_socket_protocol() {
+    use ambr::control::{Client, ControlServer, Request, Response};
+    use ambr::recorder::Recorder;
+
+    let pool = test_pool().await;
+    db::save_delta_at(&pool, "eth0", &1, &1, ts("2020-01-01 10:00:00"))
+        .await
+        .unwrap();
+    db::save_delta_at(&pool, "eth0", &2, &2, ts("2020-01-01 10:00:10"))
+        .await
+        .unwrap();
+
+    let config_path = temp_path("control.toml");
+    std::fs::write(
+        &config_path,
+        "[[alert]]\nname = \"big\"\nkind = \"volume\"\nbytes = \"1GiB\"\nwindow_minutes = 60\n",
+    )
+    .unwrap();
+    let socket = temp_path("ambr.sock");
+
+    let recorder = Recorder::new(pool.clone(), 1);
+    let listener = ControlServer::bind(&socket).await.unwrap();
+    let server = ControlServer::new(recorder.handle(), pool.clone()).with_config_path(config_path);
+    let server_task = tokio::spawn(server.serve(listener));
+    let recorder_task = tokio::spawn(recorder.run());
+
+    // A second daemon must not steal the socket.
+    assert!(ControlServer::bind(&socket).await.is_err());
+
+    let mut client = Client::connect(&socket).await.unwrap();
+    let rates = client.live().await.unwrap();
+    assert_eq!(rates.interfaces.len(), 0, "no round yet");
+
+    assert_eq!(client.request(&Request::Pause).await.unwrap(), Response::Ok);
+    match client.request(&Request::Status).await.unwrap() {
+        Response::Status {
+            paused,
+            alert_errors,
+            ..
+        } => {
+            assert!(paused);
+            assert_eq!(alert_errors, 0);
+        }
+        other => panic!("{other:?}"),
+    }
+    assert_eq!(
+        client.request(&Request::Resume).await.unwrap(),
+        Response::Ok
+    );
+    assert_eq!(
+        client.request(&Request::Reload).await.unwrap(),
+        Response::Reloaded { rules: 1 }
+    );
+    // Rollups never reach into a quota's current cycle.
+    let mut quota = monthly_quota("long", 1, 1);
+    quota.cycle = ambr::quota::Cycle::Custom {
+        from: ts("2020-01-01 00:00:00"),
+        to: ts("2100-01-01 00:00:00"),
+    };
+    db::add_quota(&pool, &quota).await.unwrap();
+    assert_eq!(
+        client
+            .request(&Request::Rollup {
+                older_than_days: 30
+            })
+            .await
+            .unwrap(),
+        Response::RolledUp {
+            samples: 0,
+            rows: 0,
+            before: ts("2020-01-01 00:00:00"),
+        }
+    );
+    db::delete_quota(&pool, "long").await.unwrap();
+    match client
+        .request(&Request::Rollup {
+            older_than_days: 30,
+        })
+        .await
+        .unwrap()
+    {
+        Response::RolledUp { samples, rows, .. } => assert_eq!((samples, rows), (2, 1)),
+        other => panic!("{other:?}"),
+    }
+    assert!(
+        client
+            .request(&Request::Rollup { older_than_days: 0 })
+            .await
+            .is_err()
+    );
+
+    // Raw protocol: one JSON object per line, errors are reported in-band.
+    {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+        let mut raw = tokio::net::UnixStream::connect(&socket).await.unwrap();
+        raw.write_all(b"{\"cmd\":\"bogus\"}\n{\"cmd\":\"status\"}\n")
+            .await
+            .unwrap();
+        let mut lines = BufReader::new(raw).lines();
+        let first: serde_json::Value =
+            serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(first["type"], "error");
+        let second: serde_json::Value =
+            serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(second["type"], "status");
+        assert_eq!(second["paused"], false);
+    }
+
+    // Streaming: the current rates, then one update per round.
+    let mut sub = client.subscribe().await.unwrap();
+    sub.next().await.unwrap().unwrap();
+    let update = tokio::time::timeout(std::time::Duration::from_secs(5), sub.next())
+        .await
+        .expect("an update within a few rounds")
+        .unwrap()
+        .unwrap();
+    assert!(update.at.is_some());
+    assert_eq!(update.interval_secs, 1);
+
+    server_task.abort();
+    recorder_task.abort();
+    let _ = std::fs::remove_file(&socket);
+}
+
+#[test]
+fn test_mqtt_topics_and_discovery() {
+    use ambr::mqtt::{MqttConfig, Topics, topic_segment};
+    use ambr::recorder::{InterfaceRate, LiveRates};
+
+    let config: MqttConfig = toml::from_str("host = \"broker.lan\"").unwrap();
+    assert_eq!(config.port(), 1883);
+    assert_eq!(config.interval_secs, 30);
+    assert!(config.discovery);
+    let tls: MqttConfig = toml::from_str("host = \"b\"\ntls = true").unwrap();
+    assert_eq!(tls.port(), 8883);
+    assert!(toml::from_str::<MqttConfig>("host = \"b\"\nqos = 2").is_err());
+
+    assert_eq!(topic_segment("br-lan.10/x+#"), "br-lan_10_x__");
+    let topics = Topics::new(&config, "my.host");
+    assert_eq!(topics.base, "ambr/my_host");
+    assert_eq!(topics.status(), "ambr/my_host/status");
+    assert_eq!(topics.state("eth0.5"), "ambr/my_host/eth0_5/state");
+
+    let discovery = topics.discovery("eth0.5");
+    assert_eq!(discovery.len(), 6);
+    assert!(discovery.iter().all(|m| m.retain));
+    assert_eq!(
+        discovery[0].topic,
+        "homeassistant/sensor/my_host/eth0_5_rx_rate/config"
+    );
+    let payload: serde_json::Value = serde_json::from_str(&discovery[0].payload).unwrap();
+    assert_eq!(payload["name"], "eth0.5 download rate");
+    assert_eq!(payload["unique_id"], "ambr_my_host_eth0_5_rx_rate");
+    assert_eq!(payload["state_topic"], "ambr/my_host/eth0_5/state");
+    assert_eq!(payload["value_template"], "{{ value_json.rx_rate }}");
+    assert_eq!(payload["unit_of_measurement"], "B/s");
+    assert_eq!(payload["availability_topic"], "ambr/my_host/status");
+    assert_eq!(payload["device"]["identifiers"][0], "ambr_my_host");
+    let month_tx: serde_json::Value = serde_json::from_str(&discovery[5].payload).unwrap();
+    assert_eq!(month_tx["device_class"], "data_size");
+    assert_eq!(month_tx["state_class"], "total_increasing");
+
+    let custom = MqttConfig {
+        topic_prefix: Some("home/net/".into()),
+        discovery: false,
+        ..config
+    };
+    let topics = Topics::new(&custom, "pi");
+    assert_eq!(topics.state("wlan0"), "home/net/wlan0/state");
+    assert!(topics.discovery("wlan0").is_empty());
+
+    // wlan0 has totals but no rate this round; it still reports.
+    let rates = LiveRates {
+        at: Some(ts("2026-09-10 10:00:00")),
+        interval_secs: 10,
+        paused: false,
+        interfaces: vec![InterfaceRate {
+            interface: "eth0".into(),
+            rx_bytes_per_sec: 1250.0,
+            tx_bytes_per_sec: 80.5,
+        }],
+    };
+    let today = [
+        ("eth0".to_string(), (100, 10)),
+        ("wlan0".to_string(), (5, 1)),
+    ]
+    .into();
+    let month = [
+        ("eth0".to_string(), (1000, 100)),
+        ("wlan0".to_string(), (50, 10)),
+    ]
+    .into();
+    let states = topics.states(&rates, &today, &month);
+    let names: Vec<&str> = states.iter().map(|(n, _)| n.as_str()).collect();
+    assert_eq!(names, ["eth0", "wlan0"]);
+    let eth0: serde_json::Value = serde_json::from_str(&states[0].1.payload).unwrap();
+    assert_eq!(eth0["rx_rate"], 1250.0);
+    assert_eq!(eth0["tx_rate"], 80.5);
+    assert_eq!(eth0["today_rx"], 100);
+    assert_eq!(eth0["month_tx"], 100);
+    let wlan0: serde_json::Value = serde_json::from_str(&states[1].1.payload).unwrap();
+    assert_eq!(wlan0["rx_rate"], 0.0);
+    assert_eq!(wlan0["month_rx"], 50);
+}
+
+/// Reads one MQTT packet: the first header byte and the rest after the length.
+async fn read_mqtt_packet(stream: &mut tokio::net::TcpStream) -> Option<(u8, Vec<u8>)> {
+    use tokio::io::AsyncReadExt;
+
+    let header = stream.read_u8().await.ok()?;
+    let (mut len, mut shift) = (0usize, 0);
+    loop {
+        let b = stream.read_u8().await.ok()?;
+        len |= ((b & 0x7f) as usize) << shift;
+        shift += 7;
+        if b & 0x80 == 0 {
+            break;
+        }
+    }
+    let mut body = vec![0; len];
+    stream.read_exact(&mut body).await.ok()?;
+    Some((header, body))
+}
+
+#[tokio::test]
+async fn test_mqtt_publisher_against_broker() {
+    use ambr::mqtt::{MqttConfig, Publisher};
+    use ambr::recorder::RecorderState;
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::AsyncWriteExt;
+
+    let pool = test_pool().await;
+    db::save_delta(&pool, "eth0", &4096, &1024).await.unwrap();
+    let state = Arc::new(Mutex::new(RecorderState {
+        last_deltas: [("eth0".to_string(), (1000, 500))].into(),
+        interval_secs: 10,
+        ..Default::default()
+    }));
+
+    // Just enough of a broker: accept the connection and acknowledge.
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let config = MqttConfig {
+        port: Some(port),
+        username: Some("ambr".into()),
+        password: Some("s3cret".into()),
+        topic_prefix: Some("test/ambr".into()),
+        discovery_prefix: "ha".into(),
+        ..toml::from_str("host = \"127.0.0.1\"").unwrap()
+    };
+    let publisher = Publisher::new(pool.clone(), state, config.clone()).unwrap();
+    let task = tokio::spawn(publisher.run());
+
+    let (mut stream, _) = listener.accept().await.unwrap();
+    let (header, connect) = read_mqtt_packet(&mut stream).await.unwrap();
+    assert_eq!(header >> 4, 1);
+    let connect = String::from_utf8_lossy(&connect);
+    assert!(connect.contains("test/ambr/status") && connect.contains("offline"));
+    assert!(connect.contains("ambr") && connect.contains("s3cret"));
+    stream.write_all(&[0x20, 0x02, 0x00, 0x00]).await.unwrap();
+
+    let mut published = BTreeMap::new();
+    while !published.contains_key("test/ambr/eth0/state") {
+        let (header, body) = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            read_mqtt_packet(&mut stream),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        if header >> 4 != 3 {
+            continue;
+        }
+        let topic_len = u16::from_be_bytes([body[0], body[1]]) as usize;
+        let topic = String::from_utf8(body[2..2 + topic_len].to_vec()).unwrap();
+        let mut payload = &body[2 + topic_len..];
+        if (header >> 1) & 3 > 0 {
+            stream
+                .write_all(&[0x40, 0x02, payload[0], payload[1]])
+                .await
+                .unwrap();
+            payload = &payload[2..];
+        }
+        assert_eq!(header & 1, 1, "{} should be retained", topic);
+        published.insert(topic, String::from_utf8(payload.to_vec()).unwrap());
+    }
+    task.abort();
+
+    assert_eq!(published["test/ambr/status"], "online");
+    let node = ambr::mqtt::topic_segment(&ambr::push::HostIdentity::local().hostname);
+    assert!(published.contains_key(&format!("ha/sensor/{}/eth0_today_rx/config", node)));
+    let state: serde_json::Value =
+        serde_json::from_str(&published["test/ambr/eth0/state"]).unwrap();
+    assert_eq!(state["rx_rate"], 100.0);
+    assert_eq!(state["tx_rate"], 50.0);
+    assert_eq!(state["today_rx"], 4096);
+    assert_eq!(state["month_tx"], 1024);
+
+    // Client certificates without TLS are a configuration error.
+    let broken = MqttConfig {
+        client_cert: Some("cert.pem".into()),
+        client_key: Some("key.pem".into()),
+        ..config
+    };
+    assert!(Publisher::new(pool, Default::default(), broken).is_err());
+}
+
+#[tokio::test]
+async fn test_month_report_html() {
+    use ambr::report::{month_report, parse_month, render_html};
+
+    let pool = test_pool().await;
+    for (iface, at, rx, tx) in [
+        ("eth0", "2026-08-31 23:59:59", 999, 999),
+        ("eth0", "2026-09-01 08:00:00", 3000, 1000),
+        ("eth0", "2026-09-14 12:00:00", 9000, 1000),
+        ("wlan<0>", "2026-09-14 13:00:00", 500, 500),
+        ("wlan<0>", "2026-09-30 23:59:59", 200, 100),
+        ("eth0", "2026-10-01 00:00:00", 999, 999),
+    ] {
+        db::save_delta_at(&pool, iface, &rx, &tx, ts(at))
+            .await
+            .unwrap();
+    }
+    db::add_quota(&pool, &monthly_quota("cap", 10_000, 1))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        parse_month("2026-09").unwrap(),
+        NaiveDate::from_ymd_opt(2026, 9, 1).unwrap()
+    );
+    assert!(parse_month("2026-13").is_err());
+    assert!(parse_month("Sept").is_err());
+
+    let month = parse_month("2026-09").unwrap();
+    let report = month_report(&pool, month, ts("2026-10-18 09:00:00"))
+        .await
+        .unwrap();
+    assert_eq!(report.days.len(), 30);
+    assert_eq!(report.totals(), (12_700, 2_600));
+    let names: Vec<&str> = report
+        .interfaces
+        .iter()
+        .map(|i| i.interface.as_str())
+        .collect();
+    assert_eq!(names, ["eth0", "wlan<0>"]);
+    let busiest = report.interfaces[0].busiest_day.unwrap();
+    assert_eq!(busiest.date, NaiveDate::from_ymd_opt(2026, 9, 14).unwrap());
+    let top: Vec<(u32, i64)> = report
+        .top_days(5)
+        .iter()
+        .map(|d| (chrono::Datelike::day(&d.date), d.total()))
+        .collect();
+    assert_eq!(top, [(14, 11_000), (1, 4_000), (30, 300)]);
+    // The quota is evaluated for September's cycle, not October's.
+    assert_eq!(report.quotas.len(), 1);
+    assert_eq!(report.quotas[0].used_bytes, 15_300);
+
+    let html = render_html(&report);
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains("Network usage – September 2026"));
+    assert!(html.contains("<svg"));
+    assert!(html.contains("wlan&lt;0&gt;") && !html.contains("wlan<0>"));
+    assert!(html.contains("<strong>cap</strong>") && html.contains("class=\"over\""));
+    // Nothing is loaded from elsewhere.
+    for external in ["http://", "https://", "src=", "href=", "@import"] {
+        assert!(!html.contains(external), "{}", external);
+    }
+
+    let empty = month_report(
+        &pool,
+        parse_month("2025-02").unwrap(),
+        ts("2026-10-18 09:00:00"),
+    )
+    .await
+    .unwrap();
+    assert_eq!(empty.days.len(), 28);
+    assert!(empty.interfaces.is_empty());
+    assert!(render_html(&empty).contains("No traffic recorded"));
+}
+
+#[tokio::test]
+async fn test_report_summary_periods_and_breach() {
+    use ambr::report::summary;
+
+    let pool = test_pool().await;
+    for (iface, at, rx, tx) in [
+        ("eth0", "2026-08-31 23:59:59", 100, 10),
+        ("eth0", "2026-09-01 00:00:00", 200, 20),
+        ("eth0", "2026-09-30 12:00:00", 300, 30),
+        ("eth0", "2026-10-01 09:00:00", 400, 40),
+        ("wlan0", "2026-10-01 23:59:59", 5, 5),
+    ] {
+        db::save_delta_at(&pool, iface, &rx, &tx, ts(at))
+            .await
+            .unwrap();
+    }
+    let now = ts("2026-10-02 08:00:00");
+    // Yesterday was the 1st, so last month is September only.
+    let s = summary(&pool, now, None).await.unwrap();
+    let names: Vec<&str> = s.interfaces.iter().map(|i| i.interface.as_str()).collect();
+    assert_eq!(names, ["eth0", "wlan0"]);
+    let eth0 = &s.interfaces[0];
+    assert_eq!(eth0.today.total_bytes, 0);
+    assert_eq!(
+        (eth0.yesterday.rx_bytes, eth0.yesterday.tx_bytes),
+        (400, 40)
+    );
+    assert_eq!(eth0.this_month.total_bytes, 440);
+    assert_eq!(eth0.last_month.total_bytes, 550);
+    let labels: Vec<&str> = eth0.periods().iter().map(|(l, _)| *l).collect();
+    assert_eq!(labels, ["today", "yesterday", "this month", "last month"]);
+    assert!(s.quotas.is_empty() && !s.breached());
+
+    let only = summary(&pool, now, Some("wlan0")).await.unwrap();
+    assert_eq!(only.interfaces.len(), 1);
+    assert_eq!(only.interfaces[0].yesterday.total_bytes, 10);
+
+    db::add_quota(&pool, &monthly_quota("small", 450, 1))
+        .await
+        .unwrap();
+    db::add_quota(&pool, &monthly_quota("big", 10_000, 1))
+        .await
+        .unwrap();
+    let s = summary(&pool, now, None).await.unwrap();
+    let status: Vec<(&str, i64, bool)> = s
+        .quotas
+        .iter()
+        .map(|q| (q.name.as_str(), q.used_bytes, q.exceeded))
+        .collect();
+    assert_eq!(status, [("big", 450, false), ("small", 450, true)]);
+    assert!(s.breached());
+
+    let json = serde_json::to_value(&s).unwrap();
+    assert_eq!(json["generated"], "2026-10-02T08:00:00");
+    assert_eq!(json["interfaces"][0]["last_month"]["total_bytes"], 550);
+    assert_eq!(json["quotas"][1]["exceeded"], true);
+}
+
+#[tokio::test]
+async fn test_compare_periods_cut_at_same_offset() {
+    use ambr::compare::{Baseline, Change};
+
+    // Thursday 2026-10-15 12:00 against Monday–Thursday noon the week before
+    let now = ts("2026-10-15 12:00:00");
+    let (current, previous) = Baseline::LastWeek.periods(now);
+    assert_eq!((current.from, current.to), (ts("2026-10-12 00:00:00"), now));
+    assert_eq!(
+        (previous.from, previous.to),
+        (ts("2026-10-05 00:00:00"), ts("2026-10-08 12:00:00"))
+    );
+    assert_eq!(previous.label, "2026-W40");
+    let (current, previous) = Baseline::LastYear.periods(now);
+    assert_eq!(
+        (current.label.as_str(), previous.label.as_str()),
+        ("2026-10", "2025-10")
+    );
+    assert_eq!(previous.to, ts("2025-10-15 12:00:00"));
+
+    let pool = test_pool().await;
+    let samples = [
+        ("eth0", 100, "2026-10-13 09:00:00"),
+        ("eth0", 50, "2026-10-06 09:00:00"),
+        // Later in last week than now is in this week: not compared
+        ("eth0", 1000, "2026-10-09 09:00:00"),
+        ("wlan0", 30, "2026-10-07 09:00:00"),
+        ("usb0", 20, "2026-10-14 09:00:00"),
+    ];
+    for (iface, mib, at) in samples {
+        db::save_delta_at(&pool, iface, &(mib * MIB as i64), &0, ts(at))
+            .await
+            .unwrap();
+    }
+    let c = Baseline::LastWeek
+        .compare(&pool, now, db::HostFilter::All)
+        .await
+        .unwrap();
+    let names: Vec<_> = c.interfaces.iter().map(|i| i.interface.as_str()).collect();
+    assert_eq!(names, ["eth0", "usb0", "wlan0"]);
+    let mib = |n: i64| n * MIB as i64;
+    assert_eq!(
+        c.interfaces[0].rx,
+        Change {
+            current: mib(100),
+            previous: mib(50)
+        }
+    );
+    assert_eq!(c.interfaces[0].total.percent(), Some(100.0));
+    assert_eq!(c.interfaces[1].total.percent(), None);
+    assert_eq!(c.interfaces[2].total.delta(), -mib(30));
+    assert_eq!(c.interfaces[2].total.percent(), Some(-100.0));
+
+    let total = c.total();
+    assert_eq!(total.interface, "all");
+    assert_eq!(
+        total.total,
+        Change {
+            current: mib(120),
+            previous: mib(80)
+        }
+    );
+    assert_eq!(total.total.percent(), Some(50.0));
+}
+
+#[tokio::test]
+async fn test_usage_profile_averages_per_slot() {
+    use ambr::profile::usage_profile;
+
+    let pool = test_pool().await;
+    // 2026-10-05 is a Monday; two weeks of data from then on
+    let samples = [
+        ("eth0", 10, "2026-10-05 20:15:00"),
+        ("eth0", 30, "2026-10-12 20:45:00"),
+        ("wlan0", 14, "2026-10-07 08:00:00"),
+        // Outside the window
+        ("eth0", 999, "2026-10-19 20:00:00"),
+    ];
+    for (iface, mib, at) in samples {
+        db::save_delta_at(&pool, iface, &(mib * MIB as i64), &0, ts(at))
+            .await
+            .unwrap();
+    }
+    let (from, to) = (ts("2026-09-01 00:00:00"), ts("2026-10-19 00:00:00"));
+    let p = usage_profile(&pool, from, to, db::HostFilter::All)
+        .await
+        .unwrap();
+
+    // The window starts on the day of the first sample: two Mondays, two Wednesdays
+    assert_eq!(p.from, ts("2026-10-05 00:00:00"));
+    assert_eq!(p.to, to);
+    let mib = |a: &ambr::profile::Average| a.total() / MIB;
+    assert_eq!(mib(&p.cells[0][20]), 20.0);
+    assert_eq!(mib(&p.cells[2][8]), 7.0);
+    assert_eq!(mib(&p.cells[1][20]), 0.0);
+    assert_eq!(p.max_cell() / MIB, 20.0);
+    // Hour 20 occurs 14 times in two weeks; Mondays are two 24-hour days
+    assert_eq!(mib(&p.hours[20]), 40.0 / 14.0);
+    assert_eq!(mib(&p.weekdays[0]), 20.0);
+    assert_eq!(mib(&p.weekdays[2]), 7.0);
+    assert_eq!(p.busiest_hour(), 20);
+    assert_eq!(p.busiest_weekday(), 0);
+    assert_eq!(p.hours[20].tx_bytes, 0.0);
+
+    // Nothing recorded: an empty window rather than an error
+    let empty = usage_profile(&pool, from, ts("2026-09-02 00:00:00"), db::HostFilter::All)
+        .await
+        .unwrap();
+    assert_eq!(empty.max_cell(), 0.0);
+}